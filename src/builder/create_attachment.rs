@@ -71,14 +71,20 @@ impl CreateAttachment {
 
     /// Builds an [`CreateAttachment`] by downloading attachment data from a URL.
     ///
+    /// Files on Discord's CDN are downloaded from the base URL of the client instead, if one is set
+    /// with [`HttpBuilder::base_url`].
+    ///
+    /// [`HttpBuilder::base_url`]: crate::http::HttpBuilder::base_url
+    ///
     /// # Errors
     ///
     /// [`Error::Url`] if the URL is invalid, [`Error::Http`] if downloading the data fails.
     #[cfg(feature = "http")]
     pub async fn url(http: impl AsRef<Http>, url: &str) -> Result<CreateAttachment> {
-        let url = Url::parse(url).map_err(|_| Error::Url(url.to_string()))?;
+        let http = http.as_ref();
+        let url = Url::parse(&http.cdn_url(url)).map_err(|_| Error::Url(url.to_string()))?;
 
-        let response = http.as_ref().client.get(url.clone()).send().await?;
+        let response = http.client.get(url.clone()).send().await?;
         let data = response.bytes().await?.to_vec();
 
         let filename = url
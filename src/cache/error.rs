@@ -0,0 +1,27 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+/// An error that occurred while working with the [`Cache`].
+///
+/// [`Cache`]: super::Cache
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// A snapshot passed to [`Cache::restore`] was written in a different format version than the
+    /// one supported by this version of the library. Contains the version of the snapshot.
+    ///
+    /// [`Cache::restore`]: super::Cache::restore
+    UnsupportedSnapshotVersion(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedSnapshotVersion(version) => {
+                write!(f, "Unsupported cache snapshot version: {version}")
+            },
+        }
+    }
+}
+
+impl StdError for Error {}
@@ -96,6 +96,7 @@ impl CacheUpdate for GuildCreateEvent {
 
     fn update(&mut self, cache: &Cache) -> Option<()> {
         cache.unavailable_guilds.remove(&self.guild.id);
+        cache.stale_guilds.remove(&self.guild.id);
         let mut guild = self.guild.clone();
 
         for (user_id, member) in &mut guild.members {
@@ -118,6 +119,8 @@ impl CacheUpdate for GuildDeleteEvent {
     type Output = Guild;
 
     fn update(&mut self, cache: &Cache) -> Option<Self::Output> {
+        cache.stale_guilds.remove(&self.guild.id);
+
         if self.guild.unavailable {
             cache.unavailable_guilds.insert(self.guild.id, ());
             cache.guilds.remove(&self.guild.id);
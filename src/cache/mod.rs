@@ -40,12 +40,15 @@ use parking_lot::RwLock;
 use tracing::instrument;
 
 pub use self::cache_update::CacheUpdate;
+pub use self::error::Error as CacheError;
 pub use self::settings::Settings;
 use crate::model::prelude::*;
 
 mod cache_update;
+mod error;
 mod event;
 mod settings;
+mod snapshot;
 pub(crate) mod wrappers;
 
 #[cfg(feature = "temp_cache")]
@@ -191,6 +194,8 @@ pub struct Cache {
     /// Additionally, guilds are always unavailable for bot users when a Ready is received. Guilds
     /// are "sent in" over time through the receiving of [`Event::GuildCreate`]s.
     pub(crate) unavailable_guilds: MaybeMap<GuildId, ()>,
+    /// A list of guilds restored from a snapshot that have not been confirmed by the gateway yet.
+    pub(crate) stale_guilds: MaybeMap<GuildId, ()>,
 
     // Users cache:
     // ---
@@ -279,6 +284,7 @@ impl Cache {
 
             guilds: MaybeMap(settings.cache_guilds.then(DashMap::default)),
             unavailable_guilds: MaybeMap(settings.cache_guilds.then(DashMap::default)),
+            stale_guilds: MaybeMap(settings.cache_guilds.then(DashMap::default)),
 
             users: MaybeMap(settings.cache_users.then(DashMap::default)),
 
@@ -520,6 +526,15 @@ impl Cache {
         self.unavailable_guilds.as_read_only()
     }
 
+    /// Returns the guilds restored by [`Self::restore`] whose data may be outdated.
+    ///
+    /// A guild is no longer stale once its shard resumes its session, or once a Guild Create is
+    /// received for it.
+    #[inline]
+    pub fn stale_guilds(&self) -> ReadOnlyMapRef<'_, GuildId, ()> {
+        self.stale_guilds.as_read_only()
+    }
+
     /// This method returns all channels from a guild of with the given `guild_id`.
     #[inline]
     #[deprecated = "Use Cache::guild and Guild::channels instead"]
@@ -750,6 +765,26 @@ impl Cache {
         e.update(self)
     }
 
+    /// Replaces the shard data once the shard manager switched to a new set of shards, whose
+    /// Ready events were not dispatched.
+    pub(crate) fn switch_shards(&self, total: u32, connected: HashSet<ShardId>) {
+        let mut shard_data = self.shard_data.write();
+        shard_data.total = total;
+        shard_data.connected = connected;
+        shard_data.has_sent_shards_ready = true;
+    }
+
+    /// Marks the guilds of a shard that resumed its session as no longer stale, as the gateway
+    /// replays any events the cache missed.
+    pub(crate) fn confirm_resumed_shard(&self, shard_id: ShardId) {
+        let total = u64::from(self.shard_data.read().total.max(1));
+
+        if let Some(stale_guilds) = &self.stale_guilds.0 {
+            stale_guilds
+                .retain(|guild_id, ()| (guild_id.get() >> 22) % total != u64::from(shard_id.0));
+        }
+    }
+
     pub(crate) fn update_user_entry(&self, user: &User) {
         if let Some(users) = &self.users.0 {
             match users.entry(user.id) {
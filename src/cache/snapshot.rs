@@ -0,0 +1,273 @@
+//! Serialization of the cache's contents, to warm up the cache when a bot restarts.
+
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::io::{Read, Write};
+
+use serde::ser::{SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+
+use super::wrappers::MaybeMap;
+use super::{Cache, CacheError};
+use crate::internal::prelude::*;
+use crate::json::{from_slice, to_vec};
+use crate::model::prelude::*;
+
+/// The version of the snapshot format, which must be bumped whenever the format changes.
+const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Deserialize)]
+struct SnapshotVersion {
+    version: u32,
+}
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    version: u32,
+    shard_total: u32,
+    user: &'a CurrentUser,
+    guilds: Values<'a, GuildId, Guild>,
+    unavailable_guilds: Vec<GuildId>,
+    users: Values<'a, UserId, User>,
+    messages: Messages<'a>,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    shard_total: u32,
+    user: CurrentUser,
+    guilds: Vec<Guild>,
+    unavailable_guilds: Vec<GuildId>,
+    users: Vec<User>,
+    messages: Vec<ChannelMessages>,
+}
+
+/// The cached messages of a channel, from oldest to newest.
+#[derive(Deserialize)]
+struct ChannelMessages {
+    channel_id: ChannelId,
+    messages: Vec<Message>,
+}
+
+/// Serializes the values of a cache map as a sequence, without cloning them.
+struct Values<'a, K: Eq + Hash, V>(&'a MaybeMap<K, V>);
+
+impl<K: Eq + Hash, V: Serialize> Serialize for Values<'_, K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for entry in self.0.iter() {
+            seq.serialize_element(entry.value())?;
+        }
+
+        seq.end()
+    }
+}
+
+/// Serializes the cached messages of each channel, in the order they were cached.
+struct Messages<'a>(&'a Cache);
+
+impl Serialize for Messages<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct ChannelMessagesRef<'a> {
+            channel_id: ChannelId,
+            messages: Vec<&'a Message>,
+        }
+
+        let mut seq = serializer.serialize_seq(Some(self.0.messages.len()))?;
+        for entry in &self.0.messages {
+            let queue = self.0.message_queue.get(entry.key());
+            let messages = queue.iter().flat_map(|queue| queue.iter());
+
+            seq.serialize_element(&ChannelMessagesRef {
+                channel_id: *entry.key(),
+                messages: messages.filter_map(|id| entry.value().get(id)).collect(),
+            })?;
+        }
+
+        seq.end()
+    }
+}
+
+impl Cache {
+    /// Writes a snapshot of the cached guilds, with their channels and members, users, messages,
+    /// and the current user to the given writer.
+    ///
+    /// The snapshot can be loaded into a new cache with [`Self::restore`], which allows a bot to
+    /// start with a warm cache after a restart, especially when combined with resuming the
+    /// previous gateway sessions.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use serenity::cache::Cache;
+    /// # fn run(cache: &Cache) -> Result<(), Box<dyn std::error::Error>> {
+    /// let file = std::fs::File::create("cache.json")?;
+    /// cache.snapshot(std::io::BufWriter::new(file))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Json`] if the cache could not be serialized, or [`Error::Io`] if writing
+    /// to the writer failed.
+    pub fn snapshot(&self, mut writer: impl Write) -> Result<()> {
+        let snapshot = SnapshotRef {
+            version: SNAPSHOT_VERSION,
+            shard_total: self.shard_data.read().total,
+            user: &self.user.read(),
+            guilds: Values(&self.guilds),
+            unavailable_guilds: self.unavailable_guilds.iter().map(|entry| *entry.key()).collect(),
+            users: Values(&self.users),
+            messages: Messages(self),
+        };
+
+        writer.write_all(&to_vec(&snapshot)?)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Loads a snapshot written by [`Self::snapshot`] into the cache.
+    ///
+    /// Data the cache is configured not to store is skipped. Restored guilds are marked as stale,
+    /// which they stay until their shard resumes its session or a Guild Create is received for
+    /// them. See [`Self::stale_guilds`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CacheError::UnsupportedSnapshotVersion`] if the snapshot was written by an
+    /// incompatible version of the library, [`Error::Json`] if it could not be deserialized, or
+    /// [`Error::Io`] if reading from the reader failed.
+    pub fn restore(&self, mut reader: impl Read) -> Result<()> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        let SnapshotVersion {
+            version,
+        } = from_slice(&bytes)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(Error::Cache(CacheError::UnsupportedSnapshotVersion(version)));
+        }
+
+        let snapshot: Snapshot = from_slice(&bytes)?;
+
+        self.shard_data.write().total = snapshot.shard_total;
+        *self.user.write() = snapshot.user;
+
+        for user in snapshot.users {
+            self.users.insert(user.id, user);
+        }
+
+        for guild_id in snapshot.unavailable_guilds {
+            self.unavailable_guilds.insert(guild_id, ());
+        }
+
+        for guild in snapshot.guilds {
+            for channel_id in guild.channels.keys() {
+                self.channels.insert(*channel_id, guild.id);
+            }
+
+            self.stale_guilds.insert(guild.id, ());
+            self.guilds.insert(guild.id, guild);
+        }
+
+        let max_messages = self.settings().max_messages;
+        for ChannelMessages {
+            channel_id,
+            mut messages,
+        } in snapshot.messages
+        {
+            messages.drain(..messages.len().saturating_sub(max_messages));
+            if messages.is_empty() {
+                continue;
+            }
+
+            let queue = messages.iter().map(|message| message.id).collect::<VecDeque<_>>();
+            let messages = messages.into_iter().map(|message| (message.id, message)).collect();
+
+            self.messages.insert(channel_id, messages);
+            self.message_queue.insert(channel_id, queue);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::{Cache, CacheError, Settings};
+    use crate::model::prelude::*;
+    use crate::Error;
+
+    fn guild_create(id: u64, channel_id: u64) -> GuildCreateEvent {
+        let channel = GuildChannel {
+            id: ChannelId::new(channel_id),
+            guild_id: GuildId::new(id),
+            ..Default::default()
+        };
+
+        GuildCreateEvent {
+            guild: Guild {
+                id: GuildId::new(id),
+                name: format!("guild {id}"),
+                channels: HashMap::from([(channel.id, channel)]),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let settings = Settings {
+            max_messages: 2,
+            ..Default::default()
+        };
+        let cache = Cache::new_with_settings(settings.clone());
+        cache.update(&mut guild_create(1, 10));
+        cache.update(&mut guild_create(2, 20));
+
+        for id in 100..103 {
+            cache.update(&mut MessageCreateEvent {
+                message: Message {
+                    id: MessageId::new(id),
+                    channel_id: ChannelId::new(10),
+                    guild_id: Some(GuildId::new(1)),
+                    ..Default::default()
+                },
+            });
+        }
+
+        let mut bytes = Vec::new();
+        cache.snapshot(&mut bytes).unwrap();
+
+        let restored = Cache::new_with_settings(settings);
+        restored.restore(bytes.as_slice()).unwrap();
+
+        assert_eq!(restored.guild_count(), 2);
+        assert_eq!(restored.guild(GuildId::new(2)).unwrap().name, "guild 2");
+        assert_eq!(restored.guild(GuildId::new(1)).unwrap().channels.len(), 1);
+        assert_eq!(restored.channels.get(&ChannelId::new(20)).as_deref(), Some(&GuildId::new(2)));
+        assert_eq!(restored.message_queue.get(&ChannelId::new(10)).unwrap().len(), 2);
+        assert!(restored.message(ChannelId::new(10), MessageId::new(102)).is_some());
+        assert!(restored.message(ChannelId::new(10), MessageId::new(100)).is_none());
+
+        // Restored guilds are stale until confirmed again by the gateway.
+        assert_eq!(restored.stale_guilds().len(), 2);
+        restored.update(&mut guild_create(2, 20));
+        assert_eq!(restored.stale_guilds().len(), 1);
+        assert!(restored.stale_guilds().get(&GuildId::new(1)).is_some());
+    }
+
+    #[test]
+    fn restore_rejects_other_versions() {
+        let cache = Cache::new();
+        let mut bytes = Vec::new();
+        cache.snapshot(&mut bytes).unwrap();
+
+        let snapshot = String::from_utf8(bytes).unwrap().replace("\"version\":1", "\"version\":0");
+        let err = Cache::new().restore(snapshot.as_bytes()).unwrap_err();
+        assert!(matches!(err, Error::Cache(CacheError::UnsupportedSnapshotVersion(0))));
+    }
+}
@@ -14,7 +14,7 @@ use crate::model::channel::ChannelType;
 use crate::model::event::Event;
 use crate::model::guild::Member;
 #[cfg(feature = "cache")]
-use crate::model::id::GuildId;
+use crate::model::id::{GuildId, ShardId};
 
 #[cfg(feature = "cache")]
 macro_rules! if_cache {
@@ -57,6 +57,8 @@ pub(crate) fn dispatch_model(
     let full_events = update_cache_with_event(
         #[cfg(feature = "cache")]
         &context.cache,
+        #[cfg(feature = "cache")]
+        context.shard_id,
         event,
     );
 
@@ -94,6 +96,7 @@ pub(crate) fn dispatch_model(
 #[cfg_attr(not(feature = "cache"), allow(unused_mut))]
 fn update_cache_with_event(
     #[cfg(feature = "cache")] cache: &Cache,
+    #[cfg(feature = "cache")] shard_id: ShardId,
     event: Event,
 ) -> Option<(FullEvent, Option<FullEvent>)> {
     let mut extra_event = None;
@@ -362,8 +365,13 @@ fn update_cache_with_event(
                 data_about_bot: event.ready,
             }
         },
-        Event::Resumed(event) => FullEvent::Resume {
-            event,
+        Event::Resumed(event) => {
+            #[cfg(feature = "cache")]
+            cache.confirm_resumed_shard(shard_id);
+
+            FullEvent::Resume {
+                event,
+            }
         },
         Event::TypingStart(event) => FullEvent::TypingStart {
             event,
@@ -491,6 +499,24 @@ fn update_cache_with_event(
         Event::MessagePollVoteRemove(event) => FullEvent::MessagePollVoteRemove {
             event,
         },
+        Event::GuildSoundboardSoundCreate(event) => FullEvent::GuildSoundboardSoundCreate {
+            sound: event.sound,
+        },
+        Event::GuildSoundboardSoundUpdate(event) => FullEvent::GuildSoundboardSoundUpdate {
+            sound: event.sound,
+        },
+        Event::GuildSoundboardSoundDelete(event) => FullEvent::GuildSoundboardSoundDelete {
+            sound_id: event.sound_id,
+            guild_id: event.guild_id,
+        },
+        Event::GuildSoundboardSoundsUpdate(event) => FullEvent::GuildSoundboardSoundsUpdate {
+            sounds: event.soundboard_sounds,
+            guild_id: event.guild_id,
+        },
+        Event::SoundboardSounds(event) => FullEvent::SoundboardSounds {
+            sounds: event.soundboard_sounds,
+            guild_id: event.guild_id,
+        },
     };
 
     Some((event, extra_event))
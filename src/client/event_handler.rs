@@ -481,6 +481,28 @@ event_handler! {
     /// Dispatched when a user removes a previous vote on a poll.
     MessagePollVoteRemove { event: MessagePollVoteRemoveEvent } => async fn poll_vote_remove(&self, ctx: Context);
 
+    /// Dispatched when a soundboard sound is created in a guild.
+    ///
+    /// Provides data about the created sound.
+    GuildSoundboardSoundCreate { sound: SoundboardSound } => async fn guild_soundboard_sound_create(&self, ctx: Context);
+
+    /// Dispatched when a soundboard sound of a guild is updated.
+    ///
+    /// Provides data about the updated sound.
+    GuildSoundboardSoundUpdate { sound: SoundboardSound } => async fn guild_soundboard_sound_update(&self, ctx: Context);
+
+    /// Dispatched when a soundboard sound is deleted from a guild.
+    GuildSoundboardSoundDelete { sound_id: SoundId, guild_id: GuildId } => async fn guild_soundboard_sound_delete(&self, ctx: Context);
+
+    /// Dispatched when multiple soundboard sounds of a guild are updated at once.
+    GuildSoundboardSoundsUpdate { sounds: Vec<SoundboardSound>, guild_id: GuildId } => async fn guild_soundboard_sounds_update(&self, ctx: Context);
+
+    /// Dispatched with the soundboard sounds of a guild, after they were requested with
+    /// [`ShardMessenger::request_soundboard_sounds`].
+    ///
+    /// [`ShardMessenger::request_soundboard_sounds`]: crate::gateway::ShardMessenger::request_soundboard_sounds
+    SoundboardSounds { sounds: Vec<SoundboardSound>, guild_id: GuildId } => async fn soundboard_sounds(&self, ctx: Context);
+
     /// Dispatched when an HTTP rate limit is hit
     Ratelimit { data: RatelimitInfo } => async fn ratelimit(&self);
 }
@@ -28,6 +28,8 @@ use std::ops::Range;
 use std::sync::Arc;
 #[cfg(feature = "framework")]
 use std::sync::OnceLock;
+#[cfg(feature = "gateway")]
+use std::time::Duration;
 
 use futures::channel::mpsc::UnboundedReceiver as Receiver;
 use futures::future::BoxFuture;
@@ -50,7 +52,19 @@ use crate::cache::Settings as CacheSettings;
 use crate::framework::Framework;
 #[cfg(feature = "voice")]
 use crate::gateway::VoiceGatewayManager;
-use crate::gateway::{ActivityData, PresenceData};
+use crate::gateway::{
+    ActivityData,
+    EventSink,
+    GatewayEncoding,
+    GatewayRecorder,
+    GatewayReplay,
+    IdentifyCoordinator,
+    PresenceData,
+    PresenceRotation,
+    ReconnectPolicy,
+    SessionSnapshot,
+    TransportCompression,
+};
 #[cfg(feature = "gateway")]
 use crate::gateway::{ShardManager, ShardManagerOptions};
 use crate::http::Http;
@@ -76,6 +90,17 @@ pub struct ClientBuilder {
     event_handlers: Vec<Arc<dyn EventHandler>>,
     raw_event_handlers: Vec<Arc<dyn RawEventHandler>>,
     presence: PresenceData,
+    compression: TransportCompression,
+    encoding: GatewayEncoding,
+    identify_coordinator: Option<Arc<dyn IdentifyCoordinator>>,
+    session_snapshots: Vec<SessionSnapshot>,
+    chunk_large_guilds: bool,
+    zombie_timeout: Option<Duration>,
+    reconnect_policy: Option<Arc<dyn ReconnectPolicy>>,
+    event_sink: Option<Arc<dyn EventSink>>,
+    presence_rotation: Option<PresenceRotation>,
+    gateway_recorder: Option<GatewayRecorder>,
+    gateway_replay: Option<GatewayReplay>,
 }
 
 #[cfg(feature = "gateway")]
@@ -94,6 +119,17 @@ impl ClientBuilder {
             event_handlers: vec![],
             raw_event_handlers: vec![],
             presence: PresenceData::default(),
+            compression: TransportCompression::default(),
+            encoding: GatewayEncoding::default(),
+            identify_coordinator: None,
+            session_snapshots: Vec::new(),
+            chunk_large_guilds: false,
+            zombie_timeout: None,
+            reconnect_policy: None,
+            event_sink: None,
+            presence_rotation: None,
+            gateway_recorder: None,
+            gateway_replay: None,
         }
     }
 
@@ -322,6 +358,190 @@ impl ClientBuilder {
     pub fn get_presence(&self) -> &PresenceData {
         &self.presence
     }
+
+    /// Sets the transport compression used for the shards' gateway connections. Refer to
+    /// [`TransportCompression`] for more information.
+    pub fn transport_compression(mut self, compression: TransportCompression) -> Self {
+        self.compression = compression;
+
+        self
+    }
+
+    /// Gets the transport compression. See [`Self::transport_compression`] for more info.
+    pub fn get_transport_compression(&self) -> TransportCompression {
+        self.compression
+    }
+
+    /// Sets the encoding used for payloads sent over the shards' gateway connections. Refer to
+    /// [`GatewayEncoding`] for more information.
+    pub fn gateway_encoding(mut self, encoding: GatewayEncoding) -> Self {
+        self.encoding = encoding;
+
+        self
+    }
+
+    /// Gets the gateway encoding. See [`Self::gateway_encoding`] for more info.
+    pub fn get_gateway_encoding(&self) -> GatewayEncoding {
+        self.encoding
+    }
+
+    /// Sets the coordinator that shard IDENTIFYs wait on. By default, only the shards of the
+    /// current process are coordinated; provide a shared implementation when running shards of the
+    /// same bot across multiple processes.
+    pub fn identify_coordinator<C>(mut self, identify_coordinator: C) -> Self
+    where
+        C: IdentifyCoordinator + 'static,
+    {
+        self.identify_coordinator = Some(Arc::new(identify_coordinator));
+
+        self
+    }
+
+    /// Gets the identify coordinator, if already set. See [`Self::identify_coordinator`] for more
+    /// info.
+    pub fn get_identify_coordinator(&self) -> Option<Arc<dyn IdentifyCoordinator>> {
+        self.identify_coordinator.clone()
+    }
+
+    /// Sets snapshots of sessions from a previous run of the bot, as returned by
+    /// [`ShardManager::shutdown_all_resumable`]. When the client is started, the shards they
+    /// belong to will attempt to resume them instead of identifying, falling back to identifying
+    /// if a session is no longer valid.
+    ///
+    /// As no Ready is received for a resumed session, the application ID should be set with
+    /// [`Self::application_id`] if it is needed.
+    pub fn session_snapshots(mut self, session_snapshots: Vec<SessionSnapshot>) -> Self {
+        self.session_snapshots = session_snapshots;
+
+        self
+    }
+
+    /// Gets the session snapshots. See [`Self::session_snapshots`] for more info.
+    pub fn get_session_snapshots(&self) -> &[SessionSnapshot] {
+        &self.session_snapshots
+    }
+
+    /// Sets whether the members of large guilds are requested when the guilds are received, so
+    /// that their full member lists end up in the cache. Requests are spaced out to stay well
+    /// within the gateway send ratelimit.
+    ///
+    /// This requires the [`GatewayIntents::GUILD_MEMBERS`] intent. To request members on demand
+    /// instead, use [`ShardMessenger::request_members`].
+    ///
+    /// [`ShardMessenger::request_members`]: crate::gateway::ShardMessenger::request_members
+    pub fn chunk_large_guilds(mut self, chunk_large_guilds: bool) -> Self {
+        self.chunk_large_guilds = chunk_large_guilds;
+
+        self
+    }
+
+    /// Gets whether large guilds are chunked. See [`Self::chunk_large_guilds`] for more info.
+    pub fn get_chunk_large_guilds(&self) -> bool {
+        self.chunk_large_guilds
+    }
+
+    /// Sets how long a connected shard may go without receiving a dispatch event before it is
+    /// considered a zombie and restarted. By default, shards are never restarted for this reason.
+    ///
+    /// A shard only reconnects on its own when Discord stops acknowledging its heartbeats, which
+    /// does not catch connections that keep heartbeating but no longer receive events. Choose a
+    /// timeout well above the longest quiet period expected for the bot's guilds.
+    ///
+    /// The health of each shard can be inspected with [`ShardManager::health`].
+    pub fn zombie_timeout(mut self, zombie_timeout: Duration) -> Self {
+        self.zombie_timeout = Some(zombie_timeout);
+
+        self
+    }
+
+    /// Gets the zombie timeout. See [`Self::zombie_timeout`] for more info.
+    pub fn get_zombie_timeout(&self) -> Option<Duration> {
+        self.zombie_timeout
+    }
+
+    /// Sets the policy deciding whether shards resume, identify anew, back off, or shut down
+    /// after Discord closes their connection. By default, a [`DefaultReconnectPolicy`] following
+    /// Discord's documentation of close codes is used.
+    ///
+    /// [`DefaultReconnectPolicy`]: crate::gateway::DefaultReconnectPolicy
+    pub fn reconnect_policy<P>(mut self, reconnect_policy: P) -> Self
+    where
+        P: ReconnectPolicy + 'static,
+    {
+        self.reconnect_policy = Some(Arc::new(reconnect_policy));
+
+        self
+    }
+
+    /// Gets the reconnect policy, if already set. See [`Self::reconnect_policy`] for more info.
+    pub fn get_reconnect_policy(&self) -> Option<Arc<dyn ReconnectPolicy>> {
+        self.reconnect_policy.clone()
+    }
+
+    /// Sets a sink that the shards forward the raw payloads of dispatched events to, instead of
+    /// updating the cache and dispatching the events to the event handlers and the framework.
+    ///
+    /// This lets shards run in a different process than the event handlers, which receive the
+    /// events through an [`EventConsumer`]. See [`EventSink`] for more information.
+    ///
+    /// [`EventConsumer`]: crate::gateway::EventConsumer
+    /// [`EventSink`]: crate::gateway::EventSink
+    pub fn event_sink<S>(mut self, event_sink: S) -> Self
+    where
+        S: EventSink + 'static,
+    {
+        self.event_sink = Some(Arc::new(event_sink));
+
+        self
+    }
+
+    /// Gets the event sink, if already set. See [`Self::event_sink`] for more info.
+    pub fn get_event_sink(&self) -> Option<Arc<dyn EventSink>> {
+        self.event_sink.clone()
+    }
+
+    /// Sets activities for all shards to rotate through at an interval, overriding the activity
+    /// set with [`Self::activity`] once the shards are connected. See [`PresenceRotation`] for
+    /// more information.
+    pub fn presence_rotation(mut self, presence_rotation: PresenceRotation) -> Self {
+        self.presence_rotation = Some(presence_rotation);
+
+        self
+    }
+
+    /// Gets the presence rotation, if already set. See [`Self::presence_rotation`] for more info.
+    pub fn get_presence_rotation(&self) -> Option<&PresenceRotation> {
+        self.presence_rotation.as_ref()
+    }
+
+    /// Sets a recorder that the shards write every payload they receive from the gateway to, so
+    /// that the session can be replayed later with [`Self::gateway_replay`].
+    pub fn gateway_recorder(mut self, gateway_recorder: GatewayRecorder) -> Self {
+        self.gateway_recorder = Some(gateway_recorder);
+
+        self
+    }
+
+    /// Gets the gateway recorder, if already set. See [`Self::gateway_recorder`] for more info.
+    pub fn get_gateway_recorder(&self) -> Option<&GatewayRecorder> {
+        self.gateway_recorder.as_ref()
+    }
+
+    /// Sets a recording for the shards to replay instead of connecting to the gateway. Events are
+    /// handled as usual, but nothing is sent to Discord over the gateway. See [`GatewayReplay`]
+    /// for more information.
+    ///
+    /// **Note**: This does not affect HTTP requests, which are still sent to Discord.
+    pub fn gateway_replay(mut self, gateway_replay: GatewayReplay) -> Self {
+        self.gateway_replay = Some(gateway_replay);
+
+        self
+    }
+
+    /// Gets the gateway replay, if already set. See [`Self::gateway_replay`] for more info.
+    pub fn get_gateway_replay(&self) -> Option<&GatewayReplay> {
+        self.gateway_replay.as_ref()
+    }
 }
 
 #[cfg(feature = "gateway")]
@@ -339,6 +559,17 @@ impl IntoFuture for ClientBuilder {
         let raw_event_handlers = self.raw_event_handlers;
         let intents = self.intents;
         let presence = self.presence;
+        let compression = self.compression;
+        let encoding = self.encoding;
+        let identify_coordinator = self.identify_coordinator;
+        let session_snapshots = self.session_snapshots;
+        let chunk_large_guilds = self.chunk_large_guilds;
+        let zombie_timeout = self.zombie_timeout;
+        let reconnect_policy = self.reconnect_policy;
+        let event_sink = self.event_sink;
+        let presence_rotation = self.presence_rotation;
+        let gateway_recorder = self.gateway_recorder;
+        let gateway_replay = self.gateway_replay;
 
         let mut http = self.http;
 
@@ -361,11 +592,15 @@ impl IntoFuture for ClientBuilder {
         let cache = Arc::new(Cache::new_with_settings(self.cache_settings));
 
         Box::pin(async move {
-            let ws_url = Arc::new(Mutex::new(match http.get_gateway().await {
-                Ok(response) => response.url,
-                Err(err) => {
-                    tracing::warn!("HTTP request to get gateway URL failed: {}", err);
-                    "wss://gateway.discord.gg".to_string()
+            // A replay never connects to the gateway.
+            let ws_url = Arc::new(Mutex::new(match gateway_replay {
+                Some(_) => "wss://gateway.discord.gg".to_string(),
+                None => match http.get_gateway().await {
+                    Ok(response) => response.url,
+                    Err(err) => {
+                        tracing::warn!("HTTP request to get gateway URL failed: {}", err);
+                        "wss://gateway.discord.gg".to_string()
+                    },
                 },
             }));
 
@@ -388,6 +623,17 @@ impl IntoFuture for ClientBuilder {
                 http: Arc::clone(&http),
                 intents,
                 presence: Some(presence),
+                compression,
+                encoding,
+                identify_coordinator,
+                session_snapshots,
+                chunk_large_guilds,
+                zombie_timeout,
+                reconnect_policy,
+                event_sink,
+                presence_rotation,
+                gateway_recorder,
+                gateway_replay,
             });
 
             let client = Client {
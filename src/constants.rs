@@ -62,6 +62,8 @@ enum_number! {
         Hello = 10,
         /// Sent immediately following a client heartbeat that was received.
         HeartbeatAck = 11,
+        /// Used to request the soundboard sounds of guilds.
+        RequestSoundboardSounds = 31,
         _ => Unknown(u8),
     }
 }
@@ -111,6 +113,10 @@ pub mod close_codes {
     ///
     /// Cannot reconnect.
     pub const SHARDING_REQUIRED: u16 = 4011;
+    /// An invalid version of the gateway was connected to.
+    ///
+    /// Cannot reconnect.
+    pub const INVALID_API_VERSION: u16 = 4012;
     /// Undocumented gateway intents have been provided.
     pub const INVALID_GATEWAY_INTENTS: u16 = 4013;
     /// Disallowed gateway intents have been provided.
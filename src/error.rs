@@ -8,6 +8,8 @@ use reqwest::{header::InvalidHeaderValue, Error as ReqwestError};
 use tokio_tungstenite::tungstenite::error::Error as TungsteniteError;
 use tracing::instrument;
 
+#[cfg(feature = "cache")]
+use crate::cache::CacheError;
 #[cfg(feature = "client")]
 use crate::client::ClientError;
 #[cfg(feature = "gateway")]
@@ -68,6 +70,11 @@ pub enum Error {
     Other(&'static str),
     /// An error from the [`url`] crate.
     Url(String),
+    /// An error from the [`cache`] module.
+    ///
+    /// [`cache`]: crate::cache
+    #[cfg(feature = "cache")]
+    Cache(CacheError),
     /// A [client] error.
     ///
     /// [client]: crate::client
@@ -88,6 +95,13 @@ pub enum Error {
     Tungstenite(TungsteniteError),
 }
 
+#[cfg(feature = "cache")]
+impl From<CacheError> for Error {
+    fn from(e: CacheError) -> Error {
+        Error::Cache(e)
+    }
+}
+
 impl From<FormatError> for Error {
     fn from(e: FormatError) -> Error {
         Error::Format(e)
@@ -158,6 +172,8 @@ impl fmt::Display for Error {
             Self::Json(inner) => fmt::Display::fmt(&inner, f),
             Self::Model(inner) => fmt::Display::fmt(&inner, f),
             Self::Url(msg) => f.write_str(msg),
+            #[cfg(feature = "cache")]
+            Self::Cache(inner) => fmt::Display::fmt(&inner, f),
             #[cfg(feature = "client")]
             Self::Client(inner) => fmt::Display::fmt(&inner, f),
             #[cfg(feature = "gateway")]
@@ -178,6 +194,8 @@ impl StdError for Error {
             Self::Io(inner) => Some(inner),
             Self::Json(inner) => Some(inner),
             Self::Model(inner) => Some(inner),
+            #[cfg(feature = "cache")]
+            Self::Cache(inner) => Some(inner),
             #[cfg(feature = "client")]
             Self::Client(inner) => Some(inner),
             #[cfg(feature = "http")]
@@ -0,0 +1,463 @@
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::io::{Error as IoError, ErrorKind};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+
+use async_trait::async_trait;
+#[cfg(unix)]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use tokio::sync::Mutex;
+use tokio::sync::{mpsc, RwLock};
+use tracing::{instrument, warn};
+use typemap_rev::TypeMap;
+
+use super::ShardMessenger;
+#[cfg(feature = "cache")]
+use crate::cache::Cache;
+use crate::client::dispatch::dispatch_model;
+use crate::client::{Context, EventHandler, RawEventHandler};
+#[cfg(feature = "framework")]
+use crate::framework::Framework;
+use crate::gateway::ws::decode;
+use crate::gateway::GatewayEncoding;
+use crate::http::Http;
+use crate::internal::prelude::*;
+#[cfg(unix)]
+use crate::internal::tokio::spawn_named;
+use crate::model::event::GatewayEvent;
+use crate::model::id::ShardId;
+
+/// A destination for the raw payloads of the events dispatched to shards, for running the shards
+/// in a different place than the event handlers.
+///
+/// When an event sink is set with [`ClientBuilder::event_sink`] or on the
+/// [`ShardManagerOptions`], shards forward the payload of every dispatched event to it instead of
+/// updating the cache and dispatching the event to event handlers, the framework, and collectors.
+/// An [`EventConsumer`] turns the payloads back into events on the receiving end.
+///
+/// Payloads are forwarded in the order they are received. A shard waits for each forward to
+/// complete before receiving the next payload, so sinks should not take long.
+///
+/// [`ClientBuilder::event_sink`]: crate::client::ClientBuilder::event_sink
+/// [`ShardManagerOptions`]: super::ShardManagerOptions
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    /// Forwards the payload of an event dispatched to the given shard.
+    ///
+    /// The payload is decompressed, but still in the [`GatewayEncoding`] the shard connects with.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload could not be forwarded, which the shard logs before
+    /// carrying on.
+    async fn forward(&self, shard_id: ShardId, payload: Vec<u8>) -> Result<()>;
+}
+
+/// An [`EventSink`] sending payloads over a tokio channel, to be consumed with
+/// [`EventConsumer::run_channel`].
+#[derive(Clone, Debug)]
+pub struct ChannelSink {
+    tx: mpsc::Sender<(ShardId, Vec<u8>)>,
+}
+
+impl ChannelSink {
+    /// Creates a sink along with the receiving end of its channel, which holds up to `buffer`
+    /// payloads before shards wait for them to be received.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is 0.
+    #[must_use]
+    pub fn new(buffer: usize) -> (Self, mpsc::Receiver<(ShardId, Vec<u8>)>) {
+        let (tx, rx) = mpsc::channel(buffer);
+        let sink = Self {
+            tx,
+        };
+
+        (sink, rx)
+    }
+}
+
+#[async_trait]
+impl EventSink for ChannelSink {
+    async fn forward(&self, shard_id: ShardId, payload: Vec<u8>) -> Result<()> {
+        self.tx.send((shard_id, payload)).await.map_err(|_| Error::Other("event channel closed"))
+    }
+}
+
+/// The longest payload of a frame of the [`UnixSocketSink`] format, which is the default limit of
+/// a WebSocket message.
+#[cfg(unix)]
+const MAX_FRAME_LEN: u32 = 64 << 20;
+
+/// An [`EventSink`] writing payloads to a Unix domain socket, to be consumed with
+/// [`EventConsumer::listen_unix`].
+///
+/// Each payload is written as a frame made of the shard ID and the length of the payload, both as
+/// big-endian `u32`s, followed by the payload itself. Payloads may be up to 64 MiB long. The socket
+/// is connected to when the first payload is forwarded, and connected to again after writing to it
+/// failed. Payloads that could not be written are dropped.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixSocketSink {
+    path: PathBuf,
+    stream: Mutex<Option<UnixStream>>,
+}
+
+#[cfg(unix)]
+impl UnixSocketSink {
+    /// Creates a sink writing to the socket at the given path.
+    #[must_use]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            stream: Mutex::new(None),
+        }
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl EventSink for UnixSocketSink {
+    async fn forward(&self, shard_id: ShardId, payload: Vec<u8>) -> Result<()> {
+        let mut stream = self.stream.lock().await;
+        let connected = match &mut *stream {
+            Some(connected) => connected,
+            None => stream.insert(UnixStream::connect(&self.path).await?),
+        };
+
+        if let Err(why) = write_frame(connected, shard_id, &payload).await {
+            *stream = None;
+            return Err(why.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Dispatches the payloads forwarded by an [`EventSink`] to event handlers, the same way the
+/// shards of a [`Client`] would: the cache is updated, and each event is turned into [`FullEvent`]s
+/// that are dispatched with a [`Context`].
+///
+/// As the shards run elsewhere, the [`Context::shard`] messenger cannot reach them, and messages
+/// sent through it are dropped. Collectors registered with it do receive the shard's events.
+///
+/// # Examples
+///
+/// Forward the events of a [`Client`] over a channel and handle them in the same process:
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use serenity::gateway::{ChannelSink, EventConsumer, EventConsumerOptions, GatewayEncoding};
+/// use serenity::http::Http;
+/// use serenity::prelude::*;
+///
+/// struct Handler;
+///
+/// #[serenity::async_trait]
+/// impl EventHandler for Handler {}
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let token = std::env::var("DISCORD_TOKEN")?;
+/// let intents = GatewayIntents::non_privileged();
+/// let (sink, rx) = ChannelSink::new(1024);
+/// let mut client = Client::builder(&token, intents).event_sink(sink).await?;
+///
+/// let consumer = EventConsumer::new(EventConsumerOptions {
+///     data: Arc::new(RwLock::new(TypeMap::new())),
+///     event_handlers: vec![Arc::new(Handler)],
+///     raw_event_handlers: vec![],
+///     # #[cfg(feature = "framework")]
+///     # framework: None,
+///     # #[cfg(feature = "cache")]
+///     # cache: Arc::clone(&client.cache),
+///     http: Arc::new(Http::new(&token)),
+///     encoding: GatewayEncoding::Json,
+/// });
+/// tokio::spawn(async move { consumer.run_channel(rx).await });
+///
+/// client.start().await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Client`]: crate::Client
+/// [`FullEvent`]: crate::client::FullEvent
+#[derive(Clone)]
+pub struct EventConsumer {
+    data: Arc<RwLock<TypeMap>>,
+    event_handlers: Vec<Arc<dyn EventHandler>>,
+    raw_event_handlers: Vec<Arc<dyn RawEventHandler>>,
+    #[cfg(feature = "framework")]
+    framework: Option<Arc<dyn Framework>>,
+    #[cfg(feature = "cache")]
+    cache: Arc<Cache>,
+    http: Arc<Http>,
+    encoding: GatewayEncoding,
+    messengers: Arc<StdMutex<HashMap<ShardId, ShardMessenger>>>,
+}
+
+impl EventConsumer {
+    /// Creates a new consumer.
+    #[must_use]
+    pub fn new(opt: EventConsumerOptions) -> Self {
+        Self {
+            data: opt.data,
+            event_handlers: opt.event_handlers,
+            raw_event_handlers: opt.raw_event_handlers,
+            #[cfg(feature = "framework")]
+            framework: opt.framework,
+            #[cfg(feature = "cache")]
+            cache: opt.cache,
+            http: opt.http,
+            encoding: opt.encoding,
+            messengers: Arc::new(StdMutex::new(HashMap::new())),
+        }
+    }
+
+    /// Returns the messenger given to the contexts of the events of the given shard, for example
+    /// to register collectors with.
+    #[must_use]
+    pub fn messenger(&self, shard_id: ShardId) -> ShardMessenger {
+        let mut messengers = self.messengers.lock().expect("poison");
+        messengers.entry(shard_id).or_insert_with(ShardMessenger::detached).clone()
+    }
+
+    /// Decodes a forwarded payload and dispatches its event. Payloads that are not dispatches are
+    /// ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload could not be decoded with the consumer's encoding.
+    #[instrument(skip(self, payload))]
+    pub fn dispatch(&self, shard_id: ShardId, payload: &[u8]) -> Result<()> {
+        let GatewayEvent::Dispatch(_, event) = decode(self.encoding, payload)? else {
+            return Ok(());
+        };
+
+        let shard = self.messenger(shard_id);
+
+        #[cfg(feature = "collector")]
+        shard.collectors.lock().expect("poison").retain_mut(|callback| (callback.0)(&event));
+
+        let context = Context {
+            data: Arc::clone(&self.data),
+            shard,
+            shard_id,
+            http: Arc::clone(&self.http),
+            #[cfg(feature = "cache")]
+            cache: Arc::clone(&self.cache),
+        };
+
+        dispatch_model(
+            event,
+            &context,
+            #[cfg(feature = "framework")]
+            self.framework.clone(),
+            self.event_handlers.clone(),
+            self.raw_event_handlers.clone(),
+        );
+
+        Ok(())
+    }
+
+    /// Dispatches the payloads received from a [`ChannelSink`], until all senders are dropped.
+    pub async fn run_channel(&self, mut rx: mpsc::Receiver<(ShardId, Vec<u8>)>) {
+        while let Some((shard_id, payload)) = rx.recv().await {
+            if let Err(why) = self.dispatch(shard_id, &payload) {
+                warn!("[EventConsumer] Err dispatching payload of shard {}: {:?}", shard_id, why);
+            }
+        }
+    }
+
+    /// Binds a Unix domain socket at the given path and dispatches the payloads written to it by
+    /// any number of [`UnixSocketSink`]s.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket could not be bound, such as when the path already exists, or
+    /// if accepting a connection failed.
+    #[cfg(unix)]
+    pub async fn listen_unix(&self, path: impl AsRef<Path>) -> Result<()> {
+        let listener = UnixListener::bind(path)?;
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let consumer = self.clone();
+            spawn_named("event_consumer::unix_connection", async move {
+                consumer.read_frames(stream).await;
+            });
+        }
+    }
+
+    /// Dispatches the payloads of the frames read from a connection, until it is closed.
+    #[cfg(unix)]
+    async fn read_frames(&self, mut reader: impl AsyncRead + Unpin) {
+        loop {
+            match read_frame(&mut reader).await {
+                Ok(Some((shard_id, payload))) => {
+                    if let Err(why) = self.dispatch(shard_id, &payload) {
+                        warn!(
+                            "[EventConsumer] Err dispatching payload of shard {}: {:?}",
+                            shard_id, why
+                        );
+                    }
+                },
+                Ok(None) => return,
+                Err(why) => {
+                    warn!("[EventConsumer] Err reading from socket: {:?}", why);
+                    return;
+                },
+            }
+        }
+    }
+}
+
+/// The options to create an [`EventConsumer`] with.
+pub struct EventConsumerOptions {
+    pub data: Arc<RwLock<TypeMap>>,
+    pub event_handlers: Vec<Arc<dyn EventHandler>>,
+    pub raw_event_handlers: Vec<Arc<dyn RawEventHandler>>,
+    #[cfg(feature = "framework")]
+    pub framework: Option<Arc<dyn Framework>>,
+    #[cfg(feature = "cache")]
+    pub cache: Arc<Cache>,
+    pub http: Arc<Http>,
+    /// The encoding the forwarding shards connect with.
+    pub encoding: GatewayEncoding,
+}
+
+/// Writes a payload as a frame of the [`UnixSocketSink`] format.
+#[cfg(unix)]
+async fn write_frame(
+    writer: &mut (impl AsyncWrite + Unpin),
+    shard_id: ShardId,
+    payload: &[u8],
+) -> std::io::Result<()> {
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|&len| len <= MAX_FRAME_LEN)
+        .ok_or_else(|| IoError::new(ErrorKind::InvalidInput, "payload too large"))?;
+
+    writer.write_u32(shard_id.0).await?;
+    writer.write_u32(len).await?;
+    writer.write_all(payload).await?;
+    writer.flush().await
+}
+
+/// Reads a frame of the [`UnixSocketSink`] format, returning [`None`] if the connection was closed
+/// before the next frame.
+///
+/// Fails with [`ErrorKind::InvalidData`] if the frame's payload is longer than allowed, before
+/// reading it.
+#[cfg(unix)]
+async fn read_frame(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> std::io::Result<Option<(ShardId, Vec<u8>)>> {
+    let shard_id = match reader.read_u32().await {
+        Ok(shard_id) => ShardId(shard_id),
+        Err(why) if why.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(why) => return Err(why),
+    };
+    let len = reader.read_u32().await?;
+    if len > MAX_FRAME_LEN {
+        return Err(IoError::new(ErrorKind::InvalidData, "frame payload too large"));
+    }
+
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).await?;
+
+    Ok(Some((shard_id, payload)))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    use std::io::ErrorKind;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    #[cfg(unix)]
+    use tokio::io::AsyncWriteExt;
+    use tokio::sync::{mpsc, RwLock};
+    use tokio::time::timeout;
+    use typemap_rev::TypeMap;
+
+    #[cfg(unix)]
+    use super::{read_frame, write_frame, MAX_FRAME_LEN};
+    use super::{ChannelSink, EventConsumer, EventConsumerOptions, EventSink, ShardId};
+    #[cfg(feature = "cache")]
+    use crate::cache::Cache;
+    use crate::client::{Context, EventHandler};
+    use crate::gateway::GatewayEncoding;
+    use crate::http::Http;
+    use crate::model::event::ResumedEvent;
+
+    struct Handler(mpsc::UnboundedSender<ShardId>);
+
+    #[async_trait]
+    impl EventHandler for Handler {
+        async fn resume(&self, ctx: Context, _: ResumedEvent) {
+            self.0.send(ctx.shard_id).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn forwarded_events_reach_event_handlers() {
+        let (tx, mut resumes) = mpsc::unbounded_channel();
+        let consumer = EventConsumer::new(EventConsumerOptions {
+            data: Arc::new(RwLock::new(TypeMap::new())),
+            event_handlers: vec![Arc::new(Handler(tx))],
+            raw_event_handlers: vec![],
+            #[cfg(feature = "framework")]
+            framework: None,
+            #[cfg(feature = "cache")]
+            cache: Arc::new(Cache::new()),
+            http: Arc::new(Http::new("token")),
+            encoding: GatewayEncoding::Json,
+        });
+        let (sink, rx) = ChannelSink::new(8);
+        tokio::spawn(async move { consumer.run_channel(rx).await });
+
+        sink.forward(ShardId(2), br#"{"op":11}"#.to_vec()).await.unwrap();
+        sink.forward(ShardId(2), br#"{"op":0,"s":1,"t":"RESUMED","d":{}}"#.to_vec()).await.unwrap();
+
+        let shard_id = timeout(Duration::from_secs(5), resumes.recv()).await.unwrap();
+        assert_eq!(shard_id, Some(ShardId(2)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn frames_round_trip() {
+        let (mut writer, mut reader) = tokio::io::duplex(64);
+
+        write_frame(&mut writer, ShardId(3), br#"{"op":0}"#).await.unwrap();
+        write_frame(&mut writer, ShardId(1), b"").await.unwrap();
+        drop(writer);
+
+        let frame = read_frame(&mut reader).await.unwrap();
+        assert_eq!(frame, Some((ShardId(3), br#"{"op":0}"#.to_vec())));
+        let frame = read_frame(&mut reader).await.unwrap();
+        assert_eq!(frame, Some((ShardId(1), vec![])));
+        assert_eq!(read_frame(&mut reader).await.unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn oversized_frames_are_rejected() {
+        let (mut writer, mut reader) = tokio::io::duplex(64);
+
+        writer.write_u32(0).await.unwrap();
+        writer.write_u32(MAX_FRAME_LEN + 1).await.unwrap();
+
+        let why = read_frame(&mut reader).await.unwrap_err();
+        assert_eq!(why.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -18,3 +18,30 @@ pub struct ShardStageUpdateEvent {
     /// The ID of the shard that had its connection stage change.
     pub shard_id: ShardId,
 }
+
+/// An event denoting the progress of resharding with [`ShardManager::reshard`].
+///
+/// [`ShardManager::reshard`]: super::ShardManager::reshard
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum ReshardEvent {
+    /// The shards of the new set are being started.
+    Started {
+        /// The total number of shards after resharding.
+        shard_total: u32,
+    },
+    /// A shard of the new set has received all of its guilds.
+    ShardReady {
+        /// The ID of the shard.
+        shard_id: ShardId,
+        /// The number of shards of the new set that are ready.
+        ready: u32,
+        /// The number of shards in the new set.
+        total: u32,
+    },
+    /// Events are now dispatched from the new set of shards, and the old set is being shut down.
+    Switched,
+    /// Resharding was aborted, because the manager was shut down or another resharding is already
+    /// in progress. The old set of shards keeps running.
+    Aborted,
+}
@@ -0,0 +1,231 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::gateway::ConnectionStage;
+
+/// The period over which [`ShardHealth::events_per_second`] is averaged.
+const EVENT_RATE_WINDOW: Duration = Duration::from_secs(10);
+/// The granularity with which received events are counted for the event rate.
+const EVENT_RATE_BUCKET: Duration = Duration::from_secs(1);
+
+/// A snapshot of the health of a shard, as returned by [`ShardManager::health`].
+///
+/// Metrics are kept across restarts of the shard, for as long as the [`ShardManager`] manages it.
+///
+/// [`ShardManager`]: super::ShardManager
+/// [`ShardManager::health`]: super::ShardManager::health
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ShardHealth {
+    /// The current connection stage of the shard.
+    pub stage: ConnectionStage,
+    /// The latency between when the last heartbeat was sent and when it was acknowledged.
+    pub latency: Option<Duration>,
+    /// The number of times the shard has reconnected, either by resuming its session or by
+    /// identifying anew.
+    pub reconnects: u64,
+    /// The number of times the shard has attempted to resume its session.
+    pub resume_attempts: u64,
+    /// The number of resume attempts that were successful.
+    pub resumes_succeeded: u64,
+    /// When the shard last received an event of any kind, including heartbeat acknowledgements.
+    pub last_event_at: Option<Instant>,
+    /// When the shard last received a dispatch event.
+    pub last_dispatch_at: Option<Instant>,
+    /// The average number of events received per second over the last 10 seconds.
+    pub events_per_second: f64,
+    /// The total time the shard has spent in each connection stage, including the current one.
+    pub stage_durations: HashMap<ConnectionStage, Duration>,
+}
+
+impl ShardHealth {
+    /// Returns the ratio of resume attempts that were successful, or [`None`] if the shard has
+    /// not attempted to resume yet.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn resume_success_rate(&self) -> Option<f64> {
+        (self.resume_attempts > 0)
+            .then(|| self.resumes_succeeded as f64 / self.resume_attempts as f64)
+    }
+}
+
+/// The health metrics of a shard, shared between its runners and the [`ShardManager`].
+///
+/// [`ShardManager`]: super::ShardManager
+pub(crate) type SharedHealth = Arc<Mutex<HealthRecorder>>;
+
+/// Records the health metrics of a shard as its runners observe them.
+#[derive(Debug)]
+pub(crate) struct HealthRecorder {
+    stage: ConnectionStage,
+    stage_since: Instant,
+    reconnects: u64,
+    resume_attempts: u64,
+    resumes_succeeded: u64,
+    last_event_at: Option<Instant>,
+    last_dispatch_at: Option<Instant>,
+    /// The number of events received per bucket, oldest first.
+    event_counts: VecDeque<(Instant, u32)>,
+    stage_durations: HashMap<ConnectionStage, Duration>,
+}
+
+impl HealthRecorder {
+    pub(crate) fn new(now: Instant) -> Self {
+        Self {
+            stage: ConnectionStage::Disconnected,
+            stage_since: now,
+            reconnects: 0,
+            resume_attempts: 0,
+            resumes_succeeded: 0,
+            last_event_at: None,
+            last_dispatch_at: None,
+            event_counts: VecDeque::new(),
+            stage_durations: HashMap::new(),
+        }
+    }
+
+    /// Records the current stage of the shard, accounting the time spent in the previous one.
+    pub(crate) fn record_stage(&mut self, stage: ConnectionStage, now: Instant) {
+        if stage == self.stage {
+            return;
+        }
+
+        let elapsed = now.saturating_duration_since(self.stage_since);
+        *self.stage_durations.entry(self.stage).or_default() += elapsed;
+
+        if stage == ConnectionStage::Resuming {
+            self.reconnects += 1;
+            self.resume_attempts += 1;
+        }
+
+        self.stage = stage;
+        self.stage_since = now;
+    }
+
+    /// Records that a new runner was started for a shard that had one before.
+    pub(crate) fn record_reconnect(&mut self) {
+        self.reconnects += 1;
+    }
+
+    /// Records that the shard has successfully resumed its session.
+    pub(crate) fn record_resumed(&mut self) {
+        self.resumes_succeeded += 1;
+    }
+
+    /// Records a received event.
+    pub(crate) fn record_event(&mut self, dispatch: bool, now: Instant) {
+        self.last_event_at = Some(now);
+        if dispatch {
+            self.last_dispatch_at = Some(now);
+        }
+
+        match self.event_counts.back_mut() {
+            Some((start, count)) if now.saturating_duration_since(*start) < EVENT_RATE_BUCKET => {
+                *count += 1;
+            },
+            _ => self.event_counts.push_back((now, 1)),
+        }
+
+        self.expire_event_counts(now);
+    }
+
+    /// Returns whether the shard is connected but has received no dispatches for `timeout`.
+    pub(crate) fn is_zombie(&self, timeout: Duration, now: Instant) -> bool {
+        let since = match self.last_dispatch_at {
+            Some(last_dispatch_at) => last_dispatch_at.max(self.stage_since),
+            None => self.stage_since,
+        };
+
+        self.stage == ConnectionStage::Connected && now.saturating_duration_since(since) >= timeout
+    }
+
+    pub(crate) fn snapshot(&mut self, latency: Option<Duration>, now: Instant) -> ShardHealth {
+        self.expire_event_counts(now);
+
+        let mut stage_durations = self.stage_durations.clone();
+        *stage_durations.entry(self.stage).or_default() +=
+            now.saturating_duration_since(self.stage_since);
+
+        let events = self.event_counts.iter().map(|(_, count)| count).sum::<u32>();
+
+        ShardHealth {
+            stage: self.stage,
+            latency,
+            reconnects: self.reconnects,
+            resume_attempts: self.resume_attempts,
+            resumes_succeeded: self.resumes_succeeded,
+            last_event_at: self.last_event_at,
+            last_dispatch_at: self.last_dispatch_at,
+            events_per_second: f64::from(events) / EVENT_RATE_WINDOW.as_secs_f64(),
+            stage_durations,
+        }
+    }
+
+    fn expire_event_counts(&mut self, now: Instant) {
+        while self
+            .event_counts
+            .front()
+            .is_some_and(|&(start, _)| now.saturating_duration_since(start) >= EVENT_RATE_WINDOW)
+        {
+            self.event_counts.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::HealthRecorder;
+    use crate::gateway::ConnectionStage;
+
+    #[test]
+    fn tracks_stages_and_resumes() {
+        let start = Instant::now();
+        let mut recorder = HealthRecorder::new(start);
+
+        recorder.record_stage(ConnectionStage::Connected, start + Duration::from_secs(2));
+        recorder.record_stage(ConnectionStage::Resuming, start + Duration::from_secs(12));
+        recorder.record_stage(ConnectionStage::Connected, start + Duration::from_secs(13));
+        recorder.record_resumed();
+
+        let health = recorder.snapshot(None, start + Duration::from_secs(15));
+        assert_eq!(health.reconnects, 1);
+        assert_eq!(health.resume_success_rate(), Some(1.0));
+        assert_eq!(health.stage_durations[&ConnectionStage::Disconnected], Duration::from_secs(2));
+        assert_eq!(health.stage_durations[&ConnectionStage::Connected], Duration::from_secs(12));
+        assert_eq!(health.stage_durations[&ConnectionStage::Resuming], Duration::from_secs(1));
+    }
+
+    #[test]
+    fn averages_event_rate() {
+        let start = Instant::now();
+        let mut recorder = HealthRecorder::new(start);
+
+        for i in 0..50 {
+            recorder.record_event(true, start + Duration::from_millis(i * 100));
+        }
+
+        let health = recorder.snapshot(None, start + Duration::from_secs(5));
+        assert!((health.events_per_second - 5.0).abs() < f64::EPSILON);
+
+        let health = recorder.snapshot(None, start + Duration::from_secs(20));
+        assert!(health.events_per_second.abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn detects_zombies() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(60);
+        let mut recorder = HealthRecorder::new(start);
+
+        recorder.record_stage(ConnectionStage::Connected, start);
+        recorder.record_event(true, start + Duration::from_secs(30));
+        assert!(!recorder.is_zombie(timeout, start + Duration::from_secs(60)));
+        assert!(recorder.is_zombie(timeout, start + Duration::from_secs(90)));
+
+        recorder.record_stage(ConnectionStage::Resuming, start + Duration::from_secs(90));
+        assert!(!recorder.is_zombie(timeout, start + Duration::from_secs(200)));
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+use tokio::time::{sleep_until, Duration, Instant};
+
+/// The time Discord requires between two IDENTIFYs in the same ratelimit bucket.
+const IDENTIFY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Interface for coordinating shard IDENTIFYs, which Discord ratelimits per bot.
+///
+/// Shards are grouped into buckets by `shard_id % max_concurrency`, and only one shard per bucket
+/// may identify every 5 seconds. The [`ShardQueuer`] calls [`Self::acquire`] before starting each
+/// shard, so when shards of one bot run across multiple processes, an implementation backed by
+/// shared state (such as Redis, a database, or a local socket) lets them all share one identify
+/// queue.
+///
+/// [`LocalIdentifyCoordinator`] is used by default, which only coordinates the shards of the
+/// current process.
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/gateway#sharding-max-concurrency).
+///
+/// [`ShardQueuer`]: super::ShardQueuer
+#[async_trait]
+pub trait IdentifyCoordinator: Send + Sync {
+    /// Waits until a shard in the given ratelimit bucket is allowed to identify, and reserves that
+    /// identify for it.
+    ///
+    /// The bucket is the shard's ID modulo the bot's `max_concurrency`.
+    async fn acquire(&self, bucket: u64);
+}
+
+/// An [`IdentifyCoordinator`] that coordinates the shards of the current process only.
+#[derive(Debug)]
+pub struct LocalIdentifyCoordinator {
+    interval: Duration,
+    /// The instant of the latest identify reserved in each bucket.
+    buckets: Mutex<HashMap<u64, Instant>>,
+}
+
+impl LocalIdentifyCoordinator {
+    /// Creates a coordinator letting one shard per bucket identify every 5 seconds.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_interval(IDENTIFY_INTERVAL)
+    }
+
+    fn with_interval(interval: Duration) -> Self {
+        Self {
+            interval,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Default for LocalIdentifyCoordinator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl IdentifyCoordinator for LocalIdentifyCoordinator {
+    async fn acquire(&self, bucket: u64) {
+        let reserved = {
+            let mut buckets = self.buckets.lock().await;
+            let now = Instant::now();
+            let reserved =
+                buckets.get(&bucket).map_or(now, |&last| (last + self.interval).max(now));
+            buckets.insert(bucket, reserved);
+
+            reserved
+        };
+
+        sleep_until(reserved).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::time::{Duration, Instant};
+
+    use super::{IdentifyCoordinator, LocalIdentifyCoordinator};
+
+    const INTERVAL: Duration = Duration::from_millis(200);
+
+    /// Serves identify reservations to multiple processes over TCP, one line per request.
+    async fn serve(coordinator: LocalIdentifyCoordinator) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let coordinator = Arc::new(coordinator);
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let coordinator = Arc::clone(&coordinator);
+
+                tokio::spawn(async move {
+                    let (read, mut write) = stream.into_split();
+                    let mut lines = BufReader::new(read).lines();
+                    while let Some(line) = lines.next_line().await.unwrap() {
+                        coordinator.acquire(line.parse().unwrap()).await;
+                        write.write_all(b"ok\n").await.unwrap();
+                    }
+                });
+            }
+        });
+
+        addr
+    }
+
+    /// The identify coordinator of a single process, backed by the shared TCP server.
+    struct TcpIdentifyCoordinator(SocketAddr);
+
+    #[async_trait]
+    impl IdentifyCoordinator for TcpIdentifyCoordinator {
+        async fn acquire(&self, bucket: u64) {
+            let mut stream = TcpStream::connect(self.0).await.unwrap();
+            stream.write_all(format!("{bucket}\n").as_bytes()).await.unwrap();
+
+            let mut response = String::new();
+            BufReader::new(stream).read_line(&mut response).await.unwrap();
+            assert_eq!(response, "ok\n");
+        }
+    }
+
+    #[tokio::test]
+    async fn local_spaces_identifies_per_bucket() {
+        let coordinator = LocalIdentifyCoordinator::with_interval(INTERVAL);
+        let start = Instant::now();
+
+        coordinator.acquire(0).await;
+        coordinator.acquire(1).await;
+        assert!(start.elapsed() < INTERVAL);
+
+        coordinator.acquire(0).await;
+        assert!(start.elapsed() >= INTERVAL);
+    }
+
+    #[tokio::test]
+    async fn processes_share_identify_queue_over_tcp() {
+        let addr = serve(LocalIdentifyCoordinator::with_interval(INTERVAL)).await;
+        let processes = [TcpIdentifyCoordinator(addr), TcpIdentifyCoordinator(addr)];
+        let start = Instant::now();
+
+        // Each process identifies one shard in bucket 0, and one in bucket 1.
+        let acquires = processes.iter().flat_map(|process| {
+            [0, 1].map(|bucket| async move {
+                process.acquire(bucket).await;
+                (bucket, start.elapsed())
+            })
+        });
+        let mut identifies = futures::future::join_all(acquires).await;
+        identifies.sort();
+
+        // Allow for the latency of the responses, which are timed on the client side.
+        for pair in identifies.chunks(2) {
+            let [(_, first), (_, second)] = pair else { unreachable!() };
+            assert!(second.saturating_sub(*first) >= INTERVAL / 2, "{identifies:?}");
+        }
+    }
+}
@@ -40,7 +40,12 @@
 //! [`Client`]: crate::Client
 //! [`Shard`]: crate::gateway::Shard
 
+mod broker;
 mod event;
+mod health;
+mod identify_coordinator;
+mod presence_rotation;
+mod send_ratelimiter;
 mod shard_manager;
 mod shard_messenger;
 mod shard_queuer;
@@ -49,20 +54,30 @@ mod shard_runner_message;
 #[cfg(feature = "voice")]
 mod voice;
 
+use std::collections::HashMap;
 use std::fmt;
-use std::time::Duration as StdDuration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration as StdDuration, Instant};
 
-pub use self::event::ShardStageUpdateEvent;
+use tokio::sync::mpsc::UnboundedSender;
+
+#[cfg(unix)]
+pub use self::broker::UnixSocketSink;
+pub use self::broker::{ChannelSink, EventConsumer, EventConsumerOptions, EventSink};
+pub use self::event::{ReshardEvent, ShardStageUpdateEvent};
+pub use self::health::ShardHealth;
+pub use self::identify_coordinator::{IdentifyCoordinator, LocalIdentifyCoordinator};
+pub use self::presence_rotation::PresenceRotation;
 pub use self::shard_manager::{ShardManager, ShardManagerOptions};
-pub use self::shard_messenger::ShardMessenger;
-pub use self::shard_queuer::ShardQueuer;
+pub use self::shard_messenger::{RequestedMembers, ShardMessenger};
+pub use self::shard_queuer::{SessionStarts, ShardQueuer};
 pub use self::shard_runner::{ShardRunner, ShardRunnerOptions};
 pub use self::shard_runner_message::ShardRunnerMessage;
 #[cfg(feature = "voice")]
 pub use self::voice::VoiceGatewayManager;
 use super::ChunkGuildFilter;
 use crate::gateway::ConnectionStage;
-use crate::model::event::Event;
+use crate::model::event::{Event, GuildMembersChunkEvent};
 use crate::model::id::ShardId;
 
 /// A message to be sent to the [`ShardQueuer`].
@@ -90,6 +105,24 @@ pub struct ShardRunnerInfo {
     pub runner_tx: ShardMessenger,
     /// The current connection stage of the shard.
     pub stage: ConnectionStage,
+    /// The messages waiting for the gateway send ratelimit.
+    pub send_queue: SendQueueInfo,
+}
+
+/// Metrics on the messages of a [`ShardRunner`] that are waiting for the gateway send ratelimit.
+///
+/// Discord disconnects shards that send more than 120 messages per minute, so messages such as
+/// presence updates and member requests are queued once that limit is reached. Heartbeats are
+/// never queued.
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct SendQueueInfo {
+    /// The number of queued messages.
+    pub depth: usize,
+    /// When the oldest queued message was queued, if any.
+    pub oldest_queued_at: Option<Instant>,
+    /// The total time that sent messages have spent queued.
+    pub total_wait: StdDuration,
 }
 
 impl AsRef<ShardMessenger> for ShardRunnerInfo {
@@ -98,6 +131,11 @@ impl AsRef<ShardMessenger> for ShardRunnerInfo {
     }
 }
 
+/// The member requests of a shard that are awaiting their [`GuildMembersChunkEvent`]s, keyed by
+/// nonce.
+pub(crate) type MemberRequests =
+    Arc<Mutex<HashMap<String, UnboundedSender<GuildMembersChunkEvent>>>>;
+
 /// Newtype around a callback that will be called on every incoming request. As long as this
 /// collector should still receive events, it should return `true`. Once it returns `false`, it is
 /// removed.
@@ -0,0 +1,168 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::client::Context;
+use crate::gateway::ActivityData;
+
+type ActivitiesFn = dyn Fn(&Context) -> Vec<ActivityData> + Send + Sync;
+
+/// Rotates the activity of every shard through a list of activities, switching to the next one
+/// at a fixed interval.
+///
+/// Set on the client with [`ClientBuilder::presence_rotation`]. All shards show the same activity
+/// at the same time. Updates are sent within the gateway send ratelimit, leaving precedence to
+/// messages sent through the [`ShardMessenger`], and are skipped while a shard is reconnecting.
+///
+/// # Examples
+///
+/// Alternate between two activities every minute, the second showing the number of cached guilds:
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// use serenity::gateway::{ActivityData, PresenceRotation};
+/// use serenity::prelude::*;
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let rotation = PresenceRotation::from_fn(Duration::from_secs(60), |ctx| {
+///     # #[cfg(feature = "cache")]
+///     let guilds = ctx.cache.guild_count();
+///     # #[cfg(not(feature = "cache"))]
+///     # let guilds = 0;
+///     vec![ActivityData::listening("!help"), ActivityData::watching(format!("{guilds} servers"))]
+/// });
+///
+/// let token = std::env::var("DISCORD_TOKEN")?;
+/// let mut client = Client::builder(&token, GatewayIntents::non_privileged())
+///     .presence_rotation(rotation)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`ClientBuilder::presence_rotation`]: crate::client::ClientBuilder::presence_rotation
+/// [`ShardMessenger`]: super::ShardMessenger
+#[derive(Clone)]
+pub struct PresenceRotation {
+    activities: Activities,
+    interval: Duration,
+    /// The instant from which the rotation's intervals are counted.
+    started: Instant,
+}
+
+#[derive(Clone)]
+enum Activities {
+    List(Vec<ActivityData>),
+    Fn(Arc<ActivitiesFn>),
+}
+
+impl PresenceRotation {
+    /// Creates a rotation through the given activities, switching to the next one each
+    /// `interval`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    #[must_use]
+    pub fn new(activities: Vec<ActivityData>, interval: Duration) -> Self {
+        Self::with_activities(Activities::List(activities), interval)
+    }
+
+    /// Creates a rotation through the activities produced by a closure, switching to the next
+    /// one each `interval`.
+    ///
+    /// The closure is called with the shard's context whenever an activity is due, and the
+    /// activity at the position of the current interval in the returned list is shown.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    #[must_use]
+    pub fn from_fn<F>(interval: Duration, activities: F) -> Self
+    where
+        F: Fn(&Context) -> Vec<ActivityData> + Send + Sync + 'static,
+    {
+        Self::with_activities(Activities::Fn(Arc::new(activities)), interval)
+    }
+
+    fn with_activities(activities: Activities, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "presence rotation interval must not be zero");
+
+        Self {
+            activities,
+            interval,
+            started: Instant::now(),
+        }
+    }
+
+    /// Returns the interval between two activities.
+    #[must_use]
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns the number of the interval that the given instant falls into.
+    pub(crate) fn tick(&self, now: Instant) -> u32 {
+        let elapsed = now.saturating_duration_since(self.started);
+        u32::try_from(elapsed.as_nanos() / self.interval.as_nanos()).unwrap_or(u32::MAX)
+    }
+
+    /// Returns the instant at which the given interval starts.
+    pub(crate) fn tick_start(&self, tick: u32) -> Instant {
+        self.started + self.interval.saturating_mul(tick)
+    }
+
+    /// Returns the activity to show during the given interval, if there is any.
+    pub(crate) fn activity(&self, tick: u32, ctx: &Context) -> Option<ActivityData> {
+        match &self.activities {
+            Activities::List(activities) => pick(activities, tick).cloned(),
+            Activities::Fn(activities) => pick(&activities(ctx), tick).cloned(),
+        }
+    }
+}
+
+impl fmt::Debug for PresenceRotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("PresenceRotation");
+        if let Activities::List(activities) = &self.activities {
+            debug.field("activities", activities);
+        }
+
+        debug.field("interval", &self.interval).finish_non_exhaustive()
+    }
+}
+
+fn pick(activities: &[ActivityData], tick: u32) -> Option<&ActivityData> {
+    let len = u32::try_from(activities.len()).ok().filter(|&len| len > 0)?;
+    activities.get((tick % len) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{pick, PresenceRotation};
+    use crate::gateway::ActivityData;
+
+    #[test]
+    fn ticks_follow_interval() {
+        let interval = Duration::from_secs(30);
+        let rotation = PresenceRotation::new(vec![], interval);
+        let start = rotation.tick_start(0);
+
+        assert_eq!(rotation.tick(start), 0);
+        assert_eq!(rotation.tick(start + Duration::from_secs(29)), 0);
+        assert_eq!(rotation.tick(start + Duration::from_secs(95)), 3);
+        assert_eq!(rotation.tick_start(3), start + Duration::from_secs(90));
+    }
+
+    #[test]
+    fn activities_wrap_around() {
+        let activities = vec![ActivityData::playing("a"), ActivityData::playing("b")];
+
+        assert_eq!(pick(&activities, 0).map(|a| a.name.as_str()), Some("a"));
+        assert_eq!(pick(&activities, 3).map(|a| a.name.as_str()), Some("b"));
+        assert_eq!(pick(&[], 3), None);
+    }
+}
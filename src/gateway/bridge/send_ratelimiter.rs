@@ -0,0 +1,80 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The number of messages that may be sent over a gateway connection per [`WINDOW`].
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/gateway#rate-limiting).
+const LIMIT: usize = 120;
+/// The period over which the gateway send ratelimit applies.
+const WINDOW: Duration = Duration::from_secs(60);
+/// The number of sends in each window that only heartbeats may use, so that a shard sending many
+/// other messages never misses a heartbeat.
+const HEARTBEAT_RESERVE: usize = 5;
+
+/// A sliding window ratelimiter for the messages a shard sends over the gateway.
+#[derive(Debug, Default)]
+pub(crate) struct SendRatelimiter {
+    /// The instants of the sends in the current window, oldest first.
+    sent: VecDeque<Instant>,
+}
+
+impl SendRatelimiter {
+    /// Records a send if the ratelimit allows another message that is not a heartbeat, returning
+    /// whether it does.
+    pub(crate) fn try_acquire(&mut self, now: Instant) -> bool {
+        self.expire(now);
+
+        if self.sent.len() >= LIMIT - HEARTBEAT_RESERVE {
+            return false;
+        }
+
+        self.sent.push_back(now);
+        true
+    }
+
+    /// Records a heartbeat, which is sent regardless of the ratelimit.
+    pub(crate) fn record_heartbeat(&mut self, now: Instant) {
+        self.expire(now);
+        self.sent.push_back(now);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self.sent.front().is_some_and(|&sent| now.saturating_duration_since(sent) >= WINDOW) {
+            self.sent.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{SendRatelimiter, HEARTBEAT_RESERVE, LIMIT, WINDOW};
+
+    #[test]
+    fn leaves_room_for_heartbeats() {
+        let mut ratelimiter = SendRatelimiter::default();
+        let start = Instant::now();
+
+        let allowed = (0..LIMIT).filter(|_| ratelimiter.try_acquire(start)).count();
+        assert_eq!(allowed, LIMIT - HEARTBEAT_RESERVE);
+
+        ratelimiter.record_heartbeat(start);
+        assert!(!ratelimiter.try_acquire(start + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn sends_expire_after_window() {
+        let mut ratelimiter = SendRatelimiter::default();
+        let start = Instant::now();
+
+        ratelimiter.record_heartbeat(start);
+        let later = start + Duration::from_secs(10);
+        while ratelimiter.try_acquire(later) {}
+
+        // Only the heartbeat has expired after the first window.
+        assert!(ratelimiter.try_acquire(start + WINDOW));
+        assert!(!ratelimiter.try_acquire(start + WINDOW));
+        assert!(ratelimiter.try_acquire(later + WINDOW));
+    }
+}
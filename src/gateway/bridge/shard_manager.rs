@@ -1,9 +1,10 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Range;
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
 #[cfg(feature = "framework")]
 use std::sync::OnceLock;
-use std::time::Duration;
+use std::sync::{Arc, Mutex as StdMutex, Weak};
+use std::time::{Duration, Instant};
 
 use futures::channel::mpsc::{self, UnboundedReceiver as Receiver, UnboundedSender as Sender};
 use futures::{SinkExt, StreamExt};
@@ -12,19 +13,54 @@ use tokio::time::timeout;
 use tracing::{info, instrument, warn};
 use typemap_rev::TypeMap;
 
+use super::health::{HealthRecorder, SharedHealth};
 #[cfg(feature = "voice")]
 use super::VoiceGatewayManager;
-use super::{ShardId, ShardQueuer, ShardQueuerMessage, ShardRunnerInfo};
+use super::{
+    EventSink,
+    IdentifyCoordinator,
+    LocalIdentifyCoordinator,
+    PresenceRotation,
+    ReshardEvent,
+    ShardHealth,
+    ShardId,
+    ShardQueuer,
+    ShardQueuerMessage,
+    ShardRunnerInfo,
+    ShardRunnerMessage,
+};
 #[cfg(feature = "cache")]
 use crate::cache::Cache;
 use crate::client::{EventHandler, RawEventHandler};
 #[cfg(feature = "framework")]
 use crate::framework::Framework;
-use crate::gateway::{ConnectionStage, GatewayError, PresenceData};
+use crate::gateway::{
+    ConnectionStage,
+    DefaultReconnectPolicy,
+    GatewayEncoding,
+    GatewayError,
+    GatewayRecorder,
+    GatewayReplay,
+    PresenceData,
+    ReconnectPolicy,
+    SessionSnapshot,
+    TransportCompression,
+};
 use crate::http::Http;
 use crate::internal::prelude::*;
 use crate::internal::tokio::spawn_named;
-use crate::model::gateway::GatewayIntents;
+use crate::model::gateway::{GatewayIntents, ShardInfo};
+
+/// A set of shards with a new total that is being started by [`ShardManager::reshard`].
+#[derive(Debug)]
+struct Resharding {
+    shard_total: u32,
+    /// The runners of the new set, which are moved to [`ShardManager::runners`] once all of them
+    /// are ready.
+    runners: HashMap<ShardId, ShardRunnerInfo>,
+    /// Notifies the resharding task of shards that have received all of their guilds.
+    ready_tx: Sender<ShardId>,
+}
 
 /// A manager for handling the status of shards by starting them, restarting them, and stopping
 /// them when required.
@@ -53,7 +89,12 @@ use crate::model::gateway::GatewayIntents;
 ///
 /// use serenity::client::{EventHandler, RawEventHandler};
 /// use serenity::framework::{Framework, StandardFramework};
-/// use serenity::gateway::{ShardManager, ShardManagerOptions};
+/// use serenity::gateway::{
+///     GatewayEncoding,
+///     ShardManager,
+///     ShardManagerOptions,
+///     TransportCompression,
+/// };
 /// use serenity::http::Http;
 /// use serenity::model::gateway::GatewayIntents;
 /// use serenity::prelude::*;
@@ -89,6 +130,17 @@ use crate::model::gateway::GatewayIntents;
 ///     # http,
 ///     intents: GatewayIntents::non_privileged(),
 ///     presence: None,
+///     compression: TransportCompression::None,
+///     encoding: GatewayEncoding::Json,
+///     identify_coordinator: None,
+///     session_snapshots: vec![],
+///     chunk_large_guilds: false,
+///     zombie_timeout: None,
+///     reconnect_policy: None,
+///     event_sink: None,
+///     presence_rotation: None,
+///     gateway_recorder: None,
+///     gateway_replay: None,
 /// });
 /// # Ok(())
 /// # }
@@ -115,7 +167,15 @@ pub struct ShardManager {
     // and only is ever used to receive a single message
     shard_shutdown: Mutex<Receiver<ShardId>>,
     shard_shutdown_send: Sender<ShardId>,
+    /// Snapshots of the sessions of shards shut down by [`Self::shutdown_all_resumable`].
+    session_snapshots: Mutex<HashMap<ShardId, SessionSnapshot>>,
+    /// The set of shards being started by [`Self::reshard`], if any.
+    resharding: Mutex<Option<Resharding>>,
+    /// The health metrics of each shard, keyed by its ID and total.
+    health: StdMutex<HashMap<(ShardId, u32), SharedHealth>>,
     gateway_intents: GatewayIntents,
+    #[cfg(feature = "cache")]
+    cache: Arc<Cache>,
 }
 
 impl ShardManager {
@@ -137,8 +197,13 @@ impl ShardManager {
             shard_total: AtomicU32::new(opt.shard_total),
             shard_shutdown: Mutex::new(shutdown_recv),
             shard_shutdown_send: shutdown_send,
+            session_snapshots: Mutex::new(HashMap::new()),
+            resharding: Mutex::new(None),
+            health: StdMutex::new(HashMap::new()),
             runners: Arc::clone(&runners),
             gateway_intents: opt.intents,
+            #[cfg(feature = "cache")]
+            cache: Arc::clone(&opt.cache),
         });
 
         let mut shard_queuer = ShardQueuer {
@@ -147,9 +212,25 @@ impl ShardManager {
             raw_event_handlers: opt.raw_event_handlers,
             #[cfg(feature = "framework")]
             framework: opt.framework,
+            identify_coordinator: opt
+                .identify_coordinator
+                .unwrap_or_else(|| Arc::new(LocalIdentifyCoordinator::new())),
+            #[allow(deprecated)]
             last_start: None,
             manager: Arc::clone(&manager),
             queue: VecDeque::new(),
+            retries: Vec::new(),
+            start_failures: HashMap::new(),
+            reconnect_policy: opt
+                .reconnect_policy
+                .unwrap_or_else(|| Arc::new(DefaultReconnectPolicy)),
+            event_sink: opt.event_sink,
+            session_snapshots: opt
+                .session_snapshots
+                .into_iter()
+                .map(|snapshot| (snapshot.shard_info.id, snapshot))
+                .collect(),
+            session_starts: None,
             runners,
             rx: shard_queue_rx,
             #[cfg(feature = "voice")]
@@ -160,12 +241,25 @@ impl ShardManager {
             http: opt.http,
             intents: opt.intents,
             presence: opt.presence,
+            compression: opt.compression,
+            encoding: opt.encoding,
+            chunk_large_guilds: opt.chunk_large_guilds,
+            presence_rotation: opt.presence_rotation.map(Arc::new),
+            gateway_recorder: opt.gateway_recorder,
+            gateway_replay: opt.gateway_replay,
         };
 
         spawn_named("shard_queuer::run", async move {
             shard_queuer.run().await;
         });
 
+        if let Some(zombie_timeout) = opt.zombie_timeout {
+            let manager = Arc::downgrade(&manager);
+            spawn_named("shard_manager::restart_zombies", async move {
+                Self::restart_zombies(manager, zombie_timeout).await;
+            });
+        }
+
         (Arc::clone(&manager), return_value_rx)
     }
 
@@ -210,6 +304,152 @@ impl ShardManager {
         self.shard_total.store(total, Ordering::Relaxed);
     }
 
+    /// Switches to a new total number of shards without downtime.
+    ///
+    /// This starts shards `shard_index` through `shard_index + shard_init - 1` of `shard_total`
+    /// alongside the current shards, which keep dispatching events in the meantime. Once every new
+    /// shard has received all of its guilds, event dispatch switches to the new shards at once and
+    /// the old ones are shut down. Events received by the new shards before the switch are not
+    /// dispatched, so that no event is dispatched twice.
+    ///
+    /// Resharding is aborted right away if no shards are to be started, or if some of them would
+    /// not be below `shard_total`.
+    ///
+    /// Resharding runs in the background, and reports its progress through the returned stream,
+    /// which ends once resharding has finished or was aborted. New shards are started within the
+    /// session start limit like any other, so this may take a while for large bots.
+    ///
+    /// # Examples
+    ///
+    /// Reshard to 16 shards, all run by this process:
+    ///
+    /// ```rust,no_run
+    /// use futures::StreamExt;
+    /// use serenity::gateway::ReshardEvent;
+    /// use serenity::prelude::*;
+    ///
+    /// # async fn run(client: Client) {
+    /// let mut progress = client.shard_manager.reshard(0, 16, 16);
+    /// while let Some(event) = progress.next().await {
+    ///     if let ReshardEvent::ShardReady {
+    ///         ready,
+    ///         total,
+    ///         ..
+    ///     } = event
+    ///     {
+    ///         println!("{ready}/{total} new shards ready");
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn reshard(
+        self: &Arc<Self>,
+        shard_index: u32,
+        shard_init: u32,
+        shard_total: u32,
+    ) -> Receiver<ReshardEvent> {
+        let (events_tx, events_rx) = mpsc::unbounded();
+        let manager = Arc::clone(self);
+
+        spawn_named("shard_manager::reshard", async move {
+            manager.run_reshard(shard_index, shard_init, shard_total, &events_tx).await;
+        });
+
+        events_rx
+    }
+
+    #[instrument(skip(self, events))]
+    async fn run_reshard(
+        &self,
+        shard_index: u32,
+        shard_init: u32,
+        shard_total: u32,
+        events: &Sender<ReshardEvent>,
+    ) {
+        let emit = |event| drop(events.unbounded_send(event));
+
+        let Some(shard_ids) = reshard_range(shard_index, shard_init, shard_total) else {
+            warn!(
+                "Not resharding to {} shards: shards {}+{} are out of range",
+                shard_total, shard_index, shard_init
+            );
+            emit(ReshardEvent::Aborted);
+            return;
+        };
+
+        let mut ready_rx = {
+            let mut resharding = self.resharding.lock().await;
+            if resharding.is_some() || shard_total == self.shard_total() {
+                warn!("Not resharding to {} shards: invalid or already resharding", shard_total);
+                emit(ReshardEvent::Aborted);
+                return;
+            }
+
+            let (ready_tx, ready_rx) = mpsc::unbounded();
+            *resharding = Some(Resharding {
+                shard_total,
+                runners: HashMap::new(),
+                ready_tx,
+            });
+
+            ready_rx
+        };
+
+        info!("Resharding to {} shards", shard_total);
+        emit(ReshardEvent::Started {
+            shard_total,
+        });
+
+        for shard_id in shard_ids {
+            self.boot([ShardId(shard_id), ShardId(shard_total)]);
+        }
+
+        let mut ready = HashSet::new();
+        while ready.len() < shard_init as usize {
+            // The sender is dropped if resharding is aborted.
+            let Some(shard_id) = ready_rx.next().await else {
+                emit(ReshardEvent::Aborted);
+                return;
+            };
+
+            if ready.insert(shard_id) {
+                emit(ReshardEvent::ShardReady {
+                    shard_id,
+                    ready: ready.len() as u32,
+                    total: shard_init,
+                });
+            }
+        }
+
+        let old_runners = {
+            let mut runners = self.runners.lock().await;
+            let Some(resharding) = self.resharding.lock().await.take() else {
+                emit(ReshardEvent::Aborted);
+                return;
+            };
+
+            // Runners only dispatch events while their total matches the manager's, so this
+            // switches dispatch over to the new set.
+            self.shard_index.store(shard_index, Ordering::Relaxed);
+            self.shard_init.store(shard_init, Ordering::Relaxed);
+            self.shard_total.store(shard_total, Ordering::Relaxed);
+
+            #[cfg(feature = "cache")]
+            self.cache.switch_shards(shard_total, resharding.runners.keys().copied().collect());
+
+            self.health.lock().expect("poison").retain(|(_, total), _| *total == shard_total);
+
+            std::mem::replace(&mut *runners, resharding.runners)
+        };
+
+        info!("Switched to {} shards", shard_total);
+        emit(ReshardEvent::Switched);
+
+        for (shard_id, runner) in old_runners {
+            runner.runner_tx.send_to_shard(ShardRunnerMessage::Shutdown(shard_id, 1000));
+        }
+    }
+
     /// Restarts a shard runner.
     ///
     /// This sends a shutdown signal to a shard's associated [`ShardRunner`], and then queues a
@@ -240,6 +480,87 @@ impl ShardManager {
         self.boot([shard_id, ShardId(shard_total)]);
     }
 
+    /// Returns a snapshot of the health of each shard that currently has a [`ShardRunner`].
+    ///
+    /// # Examples
+    ///
+    /// Log shards that have not received an event for a minute:
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    ///
+    /// use serenity::prelude::*;
+    ///
+    /// # async fn run(client: Client) {
+    /// for (shard_id, health) in client.shard_manager.health().await {
+    ///     if health.last_event_at.map_or(true, |at| at.elapsed() > Duration::from_secs(60)) {
+    ///         println!("Shard {shard_id} is quiet, {} reconnects so far", health.reconnects);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// [`ShardRunner`]: super::ShardRunner
+    #[instrument(skip(self))]
+    pub async fn health(&self) -> HashMap<ShardId, ShardHealth> {
+        let shard_total = self.shard_total();
+        let now = Instant::now();
+        let runners = self.runners.lock().await;
+        let health = self.health.lock().expect("poison");
+
+        runners
+            .iter()
+            .filter_map(|(shard_id, runner)| {
+                let recorder = health.get(&(*shard_id, shard_total))?;
+                let snapshot = recorder.lock().expect("poison").snapshot(runner.latency, now);
+                Some((*shard_id, snapshot))
+            })
+            .collect()
+    }
+
+    /// Restarts shards that are connected but have received no dispatches for `timeout`, until
+    /// the manager is dropped.
+    async fn restart_zombies(manager: Weak<Self>, timeout: Duration) {
+        let mut interval = tokio::time::interval((timeout / 4).max(Duration::from_secs(1)));
+
+        loop {
+            interval.tick().await;
+            let Some(manager) = manager.upgrade() else {
+                return;
+            };
+
+            let shard_total = manager.shard_total();
+            let zombies = {
+                let now = Instant::now();
+                let health = manager.health.lock().expect("poison");
+                health
+                    .iter()
+                    .filter(|((_, total), _)| *total == shard_total)
+                    .filter_map(|(&(shard_id, _), recorder)| {
+                        let mut recorder = recorder.lock().expect("poison");
+                        if !recorder.is_zombie(timeout, now) {
+                            return None;
+                        }
+
+                        // Keeps the shard from being restarted again before its new runner runs.
+                        recorder.record_stage(ConnectionStage::Disconnected, now);
+                        Some(shard_id)
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            for shard_id in zombies {
+                if manager.has(shard_id).await {
+                    warn!(
+                        "Shard {} received no dispatches for {:?}; restarting",
+                        shard_id, timeout
+                    );
+                    manager.restart(shard_id).await;
+                }
+            }
+        }
+    }
+
     /// Returns the [`ShardId`]s of the shards that have been instantiated and currently have a
     /// valid [`ShardRunner`].
     ///
@@ -301,6 +622,35 @@ impl ShardManager {
     /// [`Self::shutdown`] method.
     #[instrument(skip(self))]
     pub async fn shutdown_all(&self) {
+        self.shutdown_all_with_code(1000).await;
+    }
+
+    /// Shuts down all shards like [`Self::shutdown_all`], but without invalidating their
+    /// sessions, and returns snapshots of those sessions.
+    ///
+    /// The snapshots can be persisted and passed to [`ClientBuilder::session_snapshots`] after the
+    /// process restarts, so that the shards resume their sessions instead of identifying anew.
+    ///
+    /// [`ClientBuilder::session_snapshots`]: crate::client::ClientBuilder::session_snapshots
+    #[instrument(skip(self))]
+    pub async fn shutdown_all_resumable(&self) -> Vec<SessionSnapshot> {
+        self.session_snapshots.lock().await.clear();
+
+        // Discord invalidates sessions closed with 1000 or 1001.
+        self.shutdown_all_with_code(4000).await;
+
+        self.session_snapshots.lock().await.drain().map(|(_, snapshot)| snapshot).collect()
+    }
+
+    async fn shutdown_all_with_code(&self, code: u16) {
+        if let Some(resharding) = self.resharding.lock().await.take() {
+            info!("Aborting resharding to {} shards", resharding.shard_total);
+
+            for (shard_id, runner) in resharding.runners {
+                runner.runner_tx.send_to_shard(ShardRunnerMessage::Shutdown(shard_id, code));
+            }
+        }
+
         let keys = {
             let runners = self.runners.lock().await;
 
@@ -314,7 +664,7 @@ impl ShardManager {
         info!("Shutting down all shards");
 
         for shard_id in keys {
-            self.shutdown(shard_id, 1000).await;
+            self.shutdown(shard_id, code).await;
         }
 
         drop(self.shard_queuer.unbounded_send(ShardQueuerMessage::Shutdown));
@@ -333,6 +683,95 @@ impl ShardManager {
         drop(self.shard_queuer.unbounded_send(msg));
     }
 
+    /// Returns the total number of shards of the shards that currently dispatch events.
+    pub(crate) fn shard_total(&self) -> u32 {
+        self.shard_total.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether shards with the given total are currently managed, either because they
+    /// dispatch events or because they are being started by [`Self::reshard`].
+    pub(crate) async fn manages_total(&self, shard_total: u32) -> bool {
+        shard_total == self.shard_total()
+            || self
+                .resharding
+                .lock()
+                .await
+                .as_ref()
+                .is_some_and(|resharding| resharding.shard_total == shard_total)
+    }
+
+    /// Adds the runner of a shard started by [`Self::reshard`], returning whether it is still
+    /// part of an ongoing resharding.
+    pub(crate) async fn add_resharded_runner(
+        &self,
+        shard_info: ShardInfo,
+        runner: ShardRunnerInfo,
+    ) -> bool {
+        match &mut *self.resharding.lock().await {
+            Some(resharding) if resharding.shard_total == shard_info.total => {
+                resharding.runners.insert(shard_info.id, runner);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Updates the information about the runner of a shard, whether it dispatches events or is
+    /// being started by [`Self::reshard`].
+    pub(crate) async fn update_runner(
+        &self,
+        shard_info: ShardInfo,
+        update: impl FnOnce(&mut ShardRunnerInfo),
+    ) {
+        if shard_info.total == self.shard_total() {
+            if let Some(runner) = self.runners.lock().await.get_mut(&shard_info.id) {
+                update(runner);
+            }
+        } else if let Some(resharding) = &mut *self.resharding.lock().await {
+            if resharding.shard_total == shard_info.total {
+                if let Some(runner) = resharding.runners.get_mut(&shard_info.id) {
+                    update(runner);
+                }
+            }
+        }
+    }
+
+    /// Restarts a shard on behalf of its runner. Shards of a set that was replaced by
+    /// [`Self::reshard`] are not restarted.
+    pub(crate) async fn restart_runner(&self, shard_info: ShardInfo) {
+        if shard_info.total == self.shard_total() {
+            self.restart_shard(shard_info.id).await;
+        } else if let Some(resharding) = &mut *self.resharding.lock().await {
+            if resharding.shard_total == shard_info.total {
+                resharding.runners.remove(&shard_info.id);
+                self.boot([shard_info.id, ShardId(shard_info.total)]);
+            }
+        }
+    }
+
+    /// Returns the health metrics of a shard for a new runner of it, which are kept across its
+    /// runners.
+    pub(crate) fn shard_health(&self, shard_info: ShardInfo) -> SharedHealth {
+        let mut health = self.health.lock().expect("poison");
+        if let Some(recorder) = health.get(&(shard_info.id, shard_info.total)) {
+            recorder.lock().expect("poison").record_reconnect();
+            return Arc::clone(recorder);
+        }
+
+        let recorder = Arc::new(StdMutex::new(HealthRecorder::new(Instant::now())));
+        health.insert((shard_info.id, shard_info.total), Arc::clone(&recorder));
+        recorder
+    }
+
+    /// Notifies the manager that a shard has received all guilds of its session.
+    pub(crate) async fn guilds_received(&self, shard_info: ShardInfo) {
+        if let Some(resharding) = &*self.resharding.lock().await {
+            if resharding.shard_total == shard_info.total {
+                drop(resharding.ready_tx.unbounded_send(shard_info.id));
+            }
+        }
+    }
+
     /// Returns the gateway intents used for this gateway connection.
     #[must_use]
     pub fn intents(&self) -> GatewayIntents {
@@ -345,6 +784,11 @@ impl ShardManager {
         }
     }
 
+    /// Stores the session of a shard that is shutting down without invalidating it.
+    pub(crate) async fn store_session_snapshot(&self, snapshot: SessionSnapshot) {
+        self.session_snapshots.lock().await.insert(snapshot.shard_info.id, snapshot);
+    }
+
     pub fn shutdown_finished(&self, id: ShardId) {
         if let Err(e) = self.shard_shutdown_send.unbounded_send(id) {
             tracing::warn!("failed to notify about finished shutdown: {}", e);
@@ -383,6 +827,13 @@ impl Drop for ShardManager {
     }
 }
 
+/// Returns the IDs of the shards to start when resharding, or [`None`] if there are none or some
+/// are not below the new total.
+fn reshard_range(shard_index: u32, shard_init: u32, shard_total: u32) -> Option<Range<u32>> {
+    let end = shard_index.checked_add(shard_init)?;
+    (shard_init > 0 && end <= shard_total).then_some(shard_index..end)
+}
+
 pub struct ShardManagerOptions {
     pub data: Arc<RwLock<TypeMap>>,
     pub event_handlers: Vec<Arc<dyn EventHandler>>,
@@ -400,4 +851,41 @@ pub struct ShardManagerOptions {
     pub http: Arc<Http>,
     pub intents: GatewayIntents,
     pub presence: Option<PresenceData>,
+    pub compression: TransportCompression,
+    pub encoding: GatewayEncoding,
+    /// The coordinator for shard IDENTIFYs, defaulting to a [`LocalIdentifyCoordinator`].
+    pub identify_coordinator: Option<Arc<dyn IdentifyCoordinator>>,
+    /// Snapshots of previous sessions, which the shards will attempt to resume when started.
+    pub session_snapshots: Vec<SessionSnapshot>,
+    /// Whether the shards request the members of large guilds when they are received.
+    pub chunk_large_guilds: bool,
+    /// How long a connected shard may go without receiving a dispatch before it is restarted, or
+    /// [`None`] to never restart shards for this reason.
+    pub zombie_timeout: Option<Duration>,
+    /// The policy deciding how shards reconnect, defaulting to a [`DefaultReconnectPolicy`].
+    pub reconnect_policy: Option<Arc<dyn ReconnectPolicy>>,
+    /// The sink to forward the raw payloads of dispatched events to, instead of dispatching them
+    /// to the event handlers. See [`EventSink`] for more information.
+    pub event_sink: Option<Arc<dyn EventSink>>,
+    /// The activities to rotate the presence of all shards through.
+    pub presence_rotation: Option<PresenceRotation>,
+    /// The recorder to write the payloads received by the shards to.
+    pub gateway_recorder: Option<GatewayRecorder>,
+    /// The recording to replay instead of connecting the shards to the gateway. See
+    /// [`GatewayReplay`] for more information.
+    pub gateway_replay: Option<GatewayReplay>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::reshard_range;
+
+    #[test]
+    fn reshard_range_stays_below_total() {
+        assert_eq!(reshard_range(0, 16, 16), Some(0..16));
+        assert_eq!(reshard_range(8, 8, 16), Some(8..16));
+        assert_eq!(reshard_range(0, 0, 16), None);
+        assert_eq!(reshard_range(8, 9, 16), None);
+        assert_eq!(reshard_range(u32::MAX, 2, 16), None);
+    }
 }
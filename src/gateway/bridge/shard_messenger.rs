@@ -1,15 +1,34 @@
-#[cfg(feature = "collector")]
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use futures::channel::mpsc::UnboundedSender as Sender;
+use tokio::time::timeout;
 use tokio_tungstenite::tungstenite::Message;
 
 #[cfg(feature = "collector")]
 use super::CollectorCallback;
-use super::{ChunkGuildFilter, ShardRunner, ShardRunnerMessage};
-use crate::gateway::ActivityData;
+use super::{ChunkGuildFilter, MemberRequests, ShardRunner, ShardRunnerMessage};
+use crate::gateway::{ActivityData, GatewayError};
+use crate::internal::prelude::*;
 use crate::model::prelude::*;
 
+/// How long [`ShardMessenger::request_members`] waits for each member chunk before giving up.
+const MEMBER_CHUNK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The counter used to generate the nonces of member requests.
+static NEXT_MEMBER_REQUEST: AtomicU64 = AtomicU64::new(0);
+
+/// The members of a guild returned by [`ShardMessenger::request_members`].
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct RequestedMembers {
+    /// The members that matched the filter.
+    pub members: Vec<Member>,
+    /// The IDs requested with [`ChunkGuildFilter::UserIds`] that are not members of the guild.
+    pub not_found: Vec<UserId>,
+}
+
 /// A handle to a [`ShardRunner`].
 ///
 /// This is used to cleanly communicate with a shard's respective [`ShardRunner`]. This can be used
@@ -20,6 +39,7 @@ use crate::model::prelude::*;
 #[derive(Clone, Debug)]
 pub struct ShardMessenger {
     pub(crate) tx: Sender<ShardRunnerMessage>,
+    pub(crate) member_requests: MemberRequests,
     #[cfg(feature = "collector")]
     pub(crate) collectors: Arc<std::sync::Mutex<Vec<CollectorCallback>>>,
 }
@@ -35,11 +55,27 @@ impl ShardMessenger {
     pub fn new(shard: &ShardRunner) -> Self {
         Self {
             tx: shard.runner_tx(),
+            member_requests: Arc::clone(&shard.member_requests),
             #[cfg(feature = "collector")]
             collectors: Arc::clone(&shard.collectors),
         }
     }
 
+    /// Creates a messenger that is not connected to a shard runner, for the events dispatched by
+    /// an [`EventConsumer`]. Messages sent through it are dropped.
+    ///
+    /// [`EventConsumer`]: super::EventConsumer
+    pub(crate) fn detached() -> Self {
+        let (tx, _) = futures::channel::mpsc::unbounded();
+
+        Self {
+            tx,
+            member_requests: MemberRequests::default(),
+            #[cfg(feature = "collector")]
+            collectors: Arc::default(),
+        }
+    }
+
     /// Requests that one or multiple [`Guild`]s be chunked.
     ///
     /// This will ask the gateway to start sending member chunks for large guilds (250 members+).
@@ -71,7 +107,7 @@ impl ShardMessenger {
     /// #         id: ShardId(0),
     /// #         total: 1,
     /// #     };
-    /// #     let mut shard = Shard::new(mutex.clone(), "", shard_info, GatewayIntents::all(), None).await?;
+    /// #     let mut shard = Shard::new(mutex.clone(), "", shard_info, GatewayIntents::all(), None, Default::default(), Default::default()).await?;
     /// #
     /// use serenity::model::id::GuildId;
     ///
@@ -98,7 +134,7 @@ impl ShardMessenger {
     /// #         total: 1,
     /// #     };
     /// #
-    /// #     let mut shard = Shard::new(mutex.clone(), "", shard_info, GatewayIntents::all(), None).await?;;
+    /// #     let mut shard = Shard::new(mutex.clone(), "", shard_info, GatewayIntents::all(), None, Default::default(), Default::default()).await?;;
     /// #
     /// use serenity::model::id::GuildId;
     ///
@@ -129,6 +165,77 @@ impl ShardMessenger {
         });
     }
 
+    /// Requests the members of a [`Guild`] matching the given filter, and waits for all
+    /// [`Event::GuildMembersChunk`]s sent in response.
+    ///
+    /// Unlike [`Self::chunk_guild`], the chunks are collected into a single result. They are still
+    /// dispatched to event handlers and, if the `cache` feature is enabled, added to the cache.
+    ///
+    /// Requesting all members with [`ChunkGuildFilter::None`] requires the
+    /// [`GatewayIntents::GUILD_MEMBERS`] intent.
+    ///
+    /// # Examples
+    ///
+    /// Request the members whose username starts with `"do"`:
+    ///
+    /// ```rust,no_run
+    /// # use serenity::gateway::{ChunkGuildFilter, ShardMessenger};
+    /// # use serenity::model::id::GuildId;
+    /// #
+    /// # async fn run(shard: ShardMessenger) -> Result<(), Box<dyn std::error::Error>> {
+    /// let guild_id = GuildId::new(81384788765712384);
+    /// let response = shard.request_members(guild_id, ChunkGuildFilter::Query("do".into())).await?;
+    ///
+    /// for member in response.members {
+    ///     println!("{}", member.user.name);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`GatewayError::MemberRequestTimeout`] if no chunk was received for 30 seconds, or
+    /// [`GatewayError::MemberRequestInterrupted`] if the shard was restarted or shut down before
+    /// all chunks were received.
+    ///
+    /// [`Guild`]: crate::model::guild::Guild
+    pub async fn request_members(
+        &self,
+        guild_id: GuildId,
+        filter: ChunkGuildFilter,
+    ) -> Result<RequestedMembers> {
+        let nonce = format!("serenity-{}", NEXT_MEMBER_REQUEST.fetch_add(1, Ordering::Relaxed));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        self.member_requests.lock().expect("poison").insert(nonce.clone(), tx);
+        let _guard = MemberRequestGuard {
+            member_requests: &self.member_requests,
+            nonce: &nonce,
+        };
+
+        self.chunk_guild(guild_id, None, false, filter, Some(nonce.clone()));
+
+        let mut response = RequestedMembers::default();
+        let mut received = 0;
+        let result = loop {
+            let chunk = match timeout(MEMBER_CHUNK_TIMEOUT, rx.recv()).await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break Err(GatewayError::MemberRequestInterrupted),
+                Err(_) => break Err(GatewayError::MemberRequestTimeout),
+            };
+
+            response.members.extend(chunk.members.into_values());
+            response.not_found.extend(chunk.not_found.into_iter().map(|id| UserId::new(id.get())));
+
+            received += 1;
+            if received >= chunk.chunk_count {
+                break Ok(response);
+            }
+        };
+
+        Ok(result?)
+    }
+
     /// Sets the user's current activity, if any.
     ///
     /// Other presence settings are maintained.
@@ -152,7 +259,7 @@ impl ShardMessenger {
     /// #         total: 1,
     /// #     };
     /// #
-    /// #     let mut shard = Shard::new(mutex.clone(), "", shard_info, GatewayIntents::all(), None).await?;
+    /// #     let mut shard = Shard::new(mutex.clone(), "", shard_info, GatewayIntents::all(), None, Default::default(), Default::default()).await?;
     /// use serenity::gateway::ActivityData;
     ///
     /// shard.set_activity(Some(ActivityData::playing("Heroes of the Storm")));
@@ -227,7 +334,7 @@ impl ShardMessenger {
     /// #         total: 1,
     /// #     };
     /// #
-    /// #     let mut shard = Shard::new(mutex.clone(), "", shard_info, GatewayIntents::all(), None).await?;
+    /// #     let mut shard = Shard::new(mutex.clone(), "", shard_info, GatewayIntents::all(), None, Default::default(), Default::default()).await?;
     /// #
     /// use serenity::model::user::OnlineStatus;
     ///
@@ -247,6 +354,70 @@ impl ShardMessenger {
         self.send_to_shard(ShardRunnerMessage::SetStatus(online_status));
     }
 
+    /// Requests the soundboard sounds of the given guilds.
+    ///
+    /// Discord responds with an [`Event::SoundboardSounds`] for each guild, which is dispatched to
+    /// [`EventHandler::soundboard_sounds`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use serenity::gateway::ShardMessenger;
+    /// # use serenity::model::id::GuildId;
+    /// #
+    /// # fn run(shard: ShardMessenger) {
+    /// shard.request_soundboard_sounds(vec![GuildId::new(81384788765712384)]);
+    /// # }
+    /// ```
+    ///
+    /// [`EventHandler::soundboard_sounds`]: crate::client::EventHandler::soundboard_sounds
+    pub fn request_soundboard_sounds(&self, guild_ids: Vec<GuildId>) {
+        self.send_to_shard(ShardRunnerMessage::RequestSoundboardSounds {
+            guild_ids,
+        });
+    }
+
+    /// Joins, moves between, or leaves a voice or stage channel of a guild, by updating the current
+    /// user's voice state.
+    ///
+    /// Passing [`None`] as `channel_id` leaves the guild's voice channel. Discord responds with an
+    /// [`Event::VoiceStateUpdate`] and, when joining a channel, an [`Event::VoiceServerUpdate`].
+    ///
+    /// This does not connect to the voice server, so it is useful on its own for e.g. moving
+    /// between stage channels. To send or receive audio, use a [`VoiceGatewayManager`] instead.
+    ///
+    /// # Examples
+    ///
+    /// Join a stage channel muted:
+    ///
+    /// ```rust,no_run
+    /// # use serenity::gateway::ShardMessenger;
+    /// # use serenity::model::id::{ChannelId, GuildId};
+    /// #
+    /// # fn run(shard: ShardMessenger) {
+    /// let guild_id = GuildId::new(81384788765712384);
+    /// let channel_id = ChannelId::new(381880193700069377);
+    ///
+    /// shard.update_voice_state(guild_id, Some(channel_id), true, false);
+    /// # }
+    /// ```
+    ///
+    /// [`VoiceGatewayManager`]: super::VoiceGatewayManager
+    pub fn update_voice_state(
+        &self,
+        guild_id: GuildId,
+        channel_id: Option<ChannelId>,
+        self_mute: bool,
+        self_deaf: bool,
+    ) {
+        self.send_to_shard(ShardRunnerMessage::UpdateVoiceState {
+            guild_id,
+            channel_id,
+            self_mute,
+            self_deaf,
+        });
+    }
+
     /// Shuts down the websocket by attempting to cleanly close the connection.
     pub fn shutdown_clean(&self) {
         self.send_to_shard(ShardRunnerMessage::Close(1000, None));
@@ -281,3 +452,135 @@ impl AsRef<ShardMessenger> for ShardMessenger {
         self
     }
 }
+
+/// Removes a member request from the shard's pending requests when dropped, including when the
+/// request is cancelled.
+struct MemberRequestGuard<'a> {
+    member_requests: &'a MemberRequests,
+    nonce: &'a str,
+}
+
+impl Drop for MemberRequestGuard<'_> {
+    fn drop(&mut self) {
+        self.member_requests.lock().expect("poison").remove(self.nonce);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::channel::mpsc;
+    use futures::StreamExt;
+    use tokio::time::{sleep, timeout};
+
+    use super::{MemberRequests, ShardMessenger, ShardRunnerMessage};
+    use crate::gateway::{ChunkGuildFilter, GatewayError, GatewayReplay};
+    use crate::model::prelude::*;
+    use crate::{Client, Error};
+
+    fn chunk(nonce: &str, index: u32, count: u32, ids: &[u64]) -> GuildMembersChunkEvent {
+        GuildMembersChunkEvent {
+            guild_id: GuildId::new(1),
+            members: ids
+                .iter()
+                .map(|&id| {
+                    let user = User {
+                        id: UserId::new(id),
+                        ..Default::default()
+                    };
+                    (user.id, Member {
+                        user,
+                        ..Default::default()
+                    })
+                })
+                .collect(),
+            chunk_index: index,
+            chunk_count: count,
+            not_found: vec![GenericId::new(99)],
+            presences: None,
+            nonce: Some(nonce.to_owned()),
+        }
+    }
+
+    fn messenger() -> (ShardMessenger, mpsc::UnboundedReceiver<ShardRunnerMessage>) {
+        let (tx, rx) = mpsc::unbounded();
+        let messenger = ShardMessenger {
+            tx,
+            member_requests: MemberRequests::default(),
+            #[cfg(feature = "collector")]
+            collectors: Arc::default(),
+        };
+
+        (messenger, rx)
+    }
+
+    #[tokio::test]
+    async fn request_members_collects_all_chunks() {
+        let (messenger, mut rx) = messenger();
+        let member_requests = MemberRequests::clone(&messenger.member_requests);
+
+        let runner = tokio::spawn(async move {
+            let Some(ShardRunnerMessage::ChunkGuild {
+                nonce: Some(nonce), ..
+            }) = rx.next().await
+            else {
+                panic!("expected a chunk request with a nonce");
+            };
+
+            let tx = member_requests.lock().unwrap().get(&nonce).unwrap().clone();
+            tx.send(chunk(&nonce, 0, 2, &[1, 2])).unwrap();
+            tx.send(chunk(&nonce, 1, 2, &[3])).unwrap();
+        });
+
+        let response = messenger
+            .request_members(GuildId::new(1), ChunkGuildFilter::UserIds(vec![UserId::new(99)]))
+            .await
+            .unwrap();
+        runner.await.unwrap();
+
+        let mut ids: Vec<_> = response.members.iter().map(|member| member.user.id.get()).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, [1, 2, 3]);
+        assert_eq!(response.not_found, [UserId::new(99), UserId::new(99)]);
+        assert!(messenger.member_requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn request_members_is_removed_when_cancelled() {
+        let (messenger, _rx) = messenger();
+
+        let request = messenger.request_members(GuildId::new(1), ChunkGuildFilter::None);
+        assert!(timeout(Duration::from_millis(10), request).await.is_err());
+
+        assert!(messenger.member_requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn request_members_fails_when_runner_restarts() {
+        // The session is invalidated a second after the Hello, which restarts the shard's runner.
+        let recording = concat!(
+            r#"{"shard_id":0,"elapsed_ms":0,"payload":{"op":10,"d":{"heartbeat_interval":41250}}}"#,
+            "\n",
+            r#"{"shard_id":0,"elapsed_ms":1000,"payload":{"op":9,"d":false}}"#,
+        );
+        let replay = GatewayReplay::from_recording(recording).unwrap().realtime(true);
+        let mut client =
+            Client::builder("token", GatewayIntents::empty()).gateway_replay(replay).await.unwrap();
+        let manager = Arc::clone(&client.shard_manager);
+        tokio::spawn(async move { client.start().await });
+
+        let messenger = loop {
+            if let Some(runner) = manager.runners.lock().await.get(&ShardId(0)) {
+                break runner.runner_tx.clone();
+            }
+            sleep(Duration::from_millis(10)).await;
+        };
+        let request = messenger.request_members(GuildId::new(1), ChunkGuildFilter::None);
+        let result = timeout(Duration::from_secs(10), request).await.unwrap();
+
+        assert!(matches!(result, Err(Error::Gateway(GatewayError::MemberRequestInterrupted))));
+        assert!(messenger.member_requests.lock().unwrap().is_empty());
+    }
+}
@@ -1,18 +1,23 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 #[cfg(feature = "framework")]
 use std::sync::OnceLock;
 
 use futures::channel::mpsc::UnboundedReceiver as Receiver;
+use futures::future::join_all;
 use futures::StreamExt;
 use tokio::sync::{Mutex, RwLock};
-use tokio::time::{sleep, timeout, Duration, Instant};
+use tokio::time::{timeout_at, Duration, Instant};
 use tracing::{debug, info, instrument, warn};
 use typemap_rev::TypeMap;
 
 #[cfg(feature = "voice")]
 use super::VoiceGatewayManager;
 use super::{
+    EventSink,
+    IdentifyCoordinator,
+    PresenceRotation,
+    SendQueueInfo,
     ShardId,
     ShardManager,
     ShardMessenger,
@@ -26,18 +31,57 @@ use crate::cache::Cache;
 use crate::client::{EventHandler, RawEventHandler};
 #[cfg(feature = "framework")]
 use crate::framework::Framework;
-use crate::gateway::{ConnectionStage, PresenceData, Shard, ShardRunnerMessage};
+use crate::gateway::reconnect::ReconnectBackoff;
+use crate::gateway::{
+    ConnectionStage,
+    GatewayEncoding,
+    GatewayRecorder,
+    GatewayReplay,
+    PresenceData,
+    ReconnectPolicy,
+    SessionSnapshot,
+    Shard,
+    ShardRunnerMessage,
+    TransportCompression,
+};
 use crate::http::Http;
 use crate::internal::prelude::*;
 use crate::internal::tokio::spawn_named;
-use crate::model::gateway::{GatewayIntents, ShardInfo};
+use crate::model::gateway::{GatewayIntents, SessionStartLimit, ShardInfo};
 
-const WAIT_BETWEEN_BOOTS_IN_SECONDS: u64 = 5;
+/// The session start limit as last reported by Discord, tracked locally between refreshes.
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/gateway#session-start-limit-object).
+#[derive(Clone, Copy, Debug)]
+pub struct SessionStarts {
+    /// The number of shards that may be started at once, each in its own `shard_id %
+    /// max_concurrency` bucket.
+    pub max_concurrency: u64,
+    /// The number of sessions that can still be started before the limit resets.
+    pub remaining: u64,
+    /// The instant at which the limit resets.
+    pub resets_at: Instant,
+}
+
+impl SessionStarts {
+    fn new(limit: &SessionStartLimit) -> Self {
+        Self {
+            max_concurrency: limit.max_concurrency.max(1),
+            remaining: limit.remaining,
+            resets_at: Instant::now() + Duration::from_millis(limit.reset_after),
+        }
+    }
+}
 
 /// The shard queuer is a simple loop that runs indefinitely to manage the startup of shards.
 ///
+/// Queued shards are started in batches of up to `max_concurrency` shards, one per `shard_id %
+/// max_concurrency` bucket, with each start first waiting on the [`IdentifyCoordinator`] for its
+/// bucket. Shards are only started while the daily session start limit allows it. Shards that fail
+/// to start are retried after a delay that grows exponentially with each consecutive failure.
+///
 /// A shard queuer instance _should_ be run in its own thread, due to the blocking nature of the
-/// loop itself as well as a 5 second thread sleep between shard starts.
+/// loop itself.
 pub struct ShardQueuer {
     /// A copy of [`Client::data`] to be given to runners for contextual dispatching.
     ///
@@ -54,16 +98,29 @@ pub struct ShardQueuer {
     /// A copy of the framework
     #[cfg(feature = "framework")]
     pub framework: Arc<OnceLock<Arc<dyn Framework>>>,
-    /// The instant that a shard was last started.
-    ///
-    /// This is used to determine how long to wait between shard IDENTIFYs.
+    /// The coordinator that shard IDENTIFYs wait on, to avoid session invalidations.
+    pub identify_coordinator: Arc<dyn IdentifyCoordinator>,
+    /// The instant that a batch of shards was last started.
+    #[deprecated = "Shard IDENTIFYs are spaced out by the identify_coordinator instead"]
     pub last_start: Option<Instant>,
     /// A copy of the [`ShardManager`] to communicate with it.
     pub manager: Arc<ShardManager>,
     /// The shards that are queued for booting.
-    ///
-    /// This will typically be filled with previously failed boots.
     pub queue: VecDeque<ShardInfo>,
+    /// The shards that failed to start, with the instants at which they are queued again.
+    pub retries: Vec<(Instant, ShardInfo)>,
+    /// The number of consecutive failed starts of each shard, to back off by.
+    pub start_failures: HashMap<ShardId, u32>,
+    /// The policy that started shards reconnect by when their connection is closed.
+    pub reconnect_policy: Arc<dyn ReconnectPolicy>,
+    /// The sink that started shards forward dispatched events to, if running in broker mode.
+    pub event_sink: Option<Arc<dyn EventSink>>,
+    /// Snapshots of previous sessions, which are resumed instead of identifying when their shard
+    /// is started.
+    pub session_snapshots: HashMap<ShardId, SessionSnapshot>,
+    /// The session start limit, fetched from Discord when the first batch of shards is started
+    /// and again each time it resets.
+    pub session_starts: Option<SessionStarts>,
     /// A copy of the map of shard runners.
     pub runners: Arc<Mutex<HashMap<ShardId, ShardRunnerInfo>>>,
     /// A receiver channel for the shard queuer to be told to start shards.
@@ -78,6 +135,18 @@ pub struct ShardQueuer {
     pub http: Arc<Http>,
     pub intents: GatewayIntents,
     pub presence: Option<PresenceData>,
+    /// The transport compression to connect the shards with.
+    pub compression: TransportCompression,
+    /// The payload encoding to connect the shards with.
+    pub encoding: GatewayEncoding,
+    /// Whether the shards request the members of large guilds when they are received.
+    pub chunk_large_guilds: bool,
+    /// The activities that the shards rotate their presence through.
+    pub presence_rotation: Option<Arc<PresenceRotation>>,
+    /// The recorder that the shards write received payloads to.
+    pub gateway_recorder: Option<GatewayRecorder>,
+    /// The recording that the shards replay instead of connecting to the gateway.
+    pub gateway_replay: Option<GatewayReplay>,
 }
 
 impl ShardQueuer {
@@ -86,12 +155,14 @@ impl ShardQueuer {
     /// This will loop over the internal [`Self::rx`] for [`ShardQueuerMessage`]s, blocking for
     /// messages on what to do.
     ///
-    /// If a [`ShardQueuerMessage::Start`] is received, this will:
+    /// If a [`ShardQueuerMessage::Start`] is received, the shard is queued. Whenever the queue is
+    /// not empty, this will:
     ///
-    /// 1. Check how much time has passed since the last shard was started
-    /// 2. If the amount of time is less than the ratelimit, it will sleep until that time has
-    ///    passed
-    /// 3. Start the shard by ID
+    /// 1. Wait until the session start limit has reset, if it was exhausted
+    /// 2. Take the next queued shard of each `shard_id % max_concurrency` bucket, up to the number
+    ///    of remaining session starts
+    /// 3. Start those shards in parallel once the [`Self::identify_coordinator`] allows it for
+    ///    their bucket, scheduling any that fail to start to be queued again after a backoff
     ///
     /// If a [`ShardQueuerMessage::Shutdown`] is received, this will return and the loop will be
     /// over.
@@ -99,81 +170,224 @@ impl ShardQueuer {
     /// **Note**: This should be run in its own thread due to the blocking nature of the loop.
     #[instrument(skip(self))]
     pub async fn run(&mut self) {
-        // The duration to timeout from reads over the Rx channel. This can be done in a loop, and
-        // if the read times out then a shard can be started if one is presently waiting in the
-        // queue.
-        const TIMEOUT: Duration = Duration::from_secs(WAIT_BETWEEN_BOOTS_IN_SECONDS);
-
         loop {
-            match timeout(TIMEOUT, self.rx.next()).await {
-                Ok(Some(ShardQueuerMessage::Shutdown)) => {
+            let msg = if let Some(deadline) = self.next_deadline() {
+                // Pending messages are handled before the deadline is checked, so that shards
+                // queued together are started together.
+                let Ok(msg) = timeout_at(deadline, self.rx.next()).await else {
+                    let now = Instant::now();
+                    self.queue.extend(take_due_retries(&mut self.retries, now));
+                    if !self.queue.is_empty() && self.next_start().map_or(true, |at| at <= now) {
+                        self.start_batch().await;
+                    }
+
+                    continue;
+                };
+
+                msg
+            } else {
+                self.rx.next().await
+            };
+
+            match msg {
+                Some(ShardQueuerMessage::Shutdown) => {
                     debug!("[Shard Queuer] Received to shutdown.");
                     self.shutdown_runners().await;
 
                     break;
                 },
-                Ok(Some(ShardQueuerMessage::ShutdownShard(shard, code))) => {
+                Some(ShardQueuerMessage::ShutdownShard(shard, code)) => {
                     debug!("[Shard Queuer] Received to shutdown shard {} with {}.", shard.0, code);
                     self.shutdown(shard, code).await;
                 },
-                Ok(Some(ShardQueuerMessage::Start(id, total))) => {
+                Some(ShardQueuerMessage::Start(id, total)) => {
                     debug!("[Shard Queuer] Received to start shard {} of {}.", id.0, total.0);
-                    self.checked_start(id, total.0).await;
-                },
-                Ok(None) => break,
-                Err(_) => {
-                    if let Some(shard) = self.queue.pop_front() {
-                        self.checked_start(shard.id, shard.total).await;
+                    self.retries.retain(|(_, shard)| shard.id != id);
+                    match self.session_snapshots.remove(&id) {
+                        Some(snapshot) if snapshot.shard_info.total == total.0 => {
+                            self.start_resumed(snapshot).await;
+                        },
+                        _ => self.queue.push_back(ShardInfo::new(id, total.0)),
                     }
                 },
+                None => break,
             }
         }
     }
 
-    #[instrument(skip(self))]
-    async fn check_last_start(&mut self) {
-        let Some(instant) = self.last_start else { return };
+    /// Returns the instant at which the queue should next be acted on: when the next batch of
+    /// queued shards may be started, or when the next failed shard is due to be retried.
+    fn next_deadline(&self) -> Option<Instant> {
+        let start =
+            (!self.queue.is_empty()).then(|| self.next_start().unwrap_or_else(Instant::now));
+        let retry = self.retries.iter().map(|&(at, _)| at).min();
+
+        start.into_iter().chain(retry).min()
+    }
+
+    /// Schedules a shard that failed to start to be queued again, after a delay that grows
+    /// exponentially with each consecutive failure.
+    fn retry_later(&mut self, shard: ShardInfo) {
+        let failures = self.start_failures.entry(shard.id).or_default();
+        let delay = ReconnectBackoff::delay(*failures);
+        *failures = failures.saturating_add(1);
 
-        // We must wait 5 seconds between IDENTIFYs to avoid session invalidations.
-        let duration = Duration::from_secs(WAIT_BETWEEN_BOOTS_IN_SECONDS);
-        let elapsed = instant.elapsed();
+        info!("[Shard Queuer] Re-queueing start of shard {} in {:?}", shard.id, delay);
+        self.retries.push((Instant::now() + delay, shard));
+    }
+
+    /// Returns the instant at which the session start limit resets, if it is exhausted.
+    fn next_start(&self) -> Option<Instant> {
+        self.session_starts.filter(|starts| starts.remaining == 0).map(|starts| starts.resets_at)
+    }
 
-        if elapsed >= duration {
+    #[instrument(skip(self))]
+    async fn refresh_session_starts(&mut self) {
+        // Replays neither connect to Discord nor have a session start limit.
+        if self.gateway_replay.is_some()
+            || self.session_starts.is_some_and(|starts| starts.resets_at > Instant::now())
+        {
             return;
         }
 
-        let to_sleep = duration - elapsed;
+        self.session_starts = match self.http.get_bot_gateway().await {
+            Ok(gateway) => Some(SessionStarts::new(&gateway.session_start_limit)),
+            Err(why) => {
+                warn!("[Shard Queuer] Err getting session start limit: {:?}", why);
 
-        sleep(to_sleep).await;
+                None
+            },
+        };
     }
 
     #[instrument(skip(self))]
-    async fn checked_start(&mut self, id: ShardId, total: u32) {
-        debug!("[Shard Queuer] Checked start for shard {} out of {}", id, total);
-        self.check_last_start().await;
+    async fn start_batch(&mut self) {
+        self.refresh_session_starts().await;
+
+        // Without a known session start limit, fall back to starting shards one at a time.
+        let (max_concurrency, remaining) = self
+            .session_starts
+            .map_or((1, u64::MAX), |starts| (starts.max_concurrency, starts.remaining));
+
+        if remaining == 0 {
+            if let Some(starts) = self.session_starts {
+                let resets_in = starts.resets_at.saturating_duration_since(Instant::now());
+                warn!(
+                    "[Shard Queuer] Session start limit reached, waiting {:?} for it to reset",
+                    resets_in
+                );
+            }
 
-        if let Err(why) = self.start(id, total).await {
-            warn!("[Shard Queuer] Err starting shard {}: {:?}", id, why);
-            info!("[Shard Queuer] Re-queueing start of shard {}", id);
+            return;
+        }
 
-            self.queue.push_back(ShardInfo::new(id, total));
+        let batch = take_batch(&mut self.queue, max_concurrency, remaining);
+        if let Some(starts) = &mut self.session_starts {
+            starts.remaining -= batch.len() as u64;
         }
 
-        self.last_start = Some(Instant::now());
+        debug!("[Shard Queuer] Starting batch of shards {:?}", batch);
+        let queuer = &*self;
+        let results = join_all(batch.iter().map(|shard| async move {
+            let bucket = u64::from(shard.id.0) % max_concurrency;
+            queuer.identify_coordinator.acquire(bucket).await;
+            queuer.start(shard.id, shard.total, None).await
+        }))
+        .await;
+
+        #[allow(deprecated)]
+        {
+            self.last_start = Some(Instant::now());
+        }
+
+        for (shard, result) in batch.into_iter().zip(results) {
+            if let Err(why) = result {
+                warn!("[Shard Queuer] Err starting shard {}: {:?}", shard.id, why);
+                self.retry_later(shard);
+            } else {
+                self.start_failures.remove(&shard.id);
+            }
+        }
     }
 
-    #[instrument(skip(self))]
-    async fn start(&mut self, id: ShardId, total: u32) -> Result<()> {
+    /// Starts a shard that resumes a previous session.
+    ///
+    /// As resumes neither count towards the session start limit nor need to be coordinated with
+    /// IDENTIFYs, this skips the queue. If the session turns out to be invalid, the shard is
+    /// restarted and queued to identify as usual.
+    #[instrument(skip(self, snapshot))]
+    async fn start_resumed(&mut self, snapshot: SessionSnapshot) {
+        let shard = snapshot.shard_info;
+        if let Err(why) = self.start(shard.id, shard.total, Some(snapshot)).await {
+            warn!("[Shard Queuer] Err starting shard {}: {:?}", shard.id, why);
+            self.retry_later(shard);
+        } else {
+            self.start_failures.remove(&shard.id);
+        }
+    }
+
+    #[instrument(skip(self, snapshot))]
+    async fn start(
+        &self,
+        id: ShardId,
+        total: u32,
+        mut snapshot: Option<SessionSnapshot>,
+    ) -> Result<()> {
         let shard_info = ShardInfo::new(id, total);
 
-        let mut shard = Shard::new(
-            Arc::clone(&self.ws_url),
-            self.http.token(),
-            shard_info,
-            self.intents,
-            self.presence.clone(),
-        )
-        .await?;
+        // Shards may still be queued for a set that was replaced or aborted by resharding.
+        if !self.manager.manages_total(total).await {
+            debug!("[Shard Queuer] Not starting shard {} of {}: no longer managed", id, total);
+            return Ok(());
+        }
+
+        let mut shard = match &self.gateway_replay {
+            Some(replay) => Shard::replay(
+                replay,
+                self.http.token(),
+                shard_info,
+                self.intents,
+                self.presence.clone(),
+                self.encoding,
+            ),
+            // Restored sessions are resumed on their own URL, without connecting to the shared
+            // gateway URL first.
+            None => match snapshot.take() {
+                Some(snapshot) => {
+                    Shard::restored(
+                        Arc::clone(&self.ws_url),
+                        self.http.token(),
+                        snapshot,
+                        self.intents,
+                        self.presence.clone(),
+                        self.compression,
+                        self.encoding,
+                    )
+                    .await?
+                },
+                None => {
+                    Shard::new(
+                        Arc::clone(&self.ws_url),
+                        self.http.token(),
+                        shard_info,
+                        self.intents,
+                        self.presence.clone(),
+                        self.compression,
+                        self.encoding,
+                    )
+                    .await?
+                },
+            },
+        };
+
+        // A replayed shard resumes the session once the recorded Hello is received.
+        if let Some(snapshot) = snapshot {
+            shard.restore_session(snapshot);
+        }
+
+        shard.set_reconnect_policy(Arc::clone(&self.reconnect_policy));
+        shard.set_retain_payloads(self.event_sink.is_some());
+        shard.set_recorder(self.gateway_recorder.clone());
 
         let cloned_http = Arc::clone(&self.http);
         shard.set_application_id_callback(move |id| cloned_http.set_application_id(id));
@@ -185,18 +399,23 @@ impl ShardQueuer {
             #[cfg(feature = "framework")]
             framework: self.framework.get().cloned(),
             manager: Arc::clone(&self.manager),
+            event_sink: self.event_sink.clone(),
             #[cfg(feature = "voice")]
             voice_manager: self.voice_manager.clone(),
             shard,
             #[cfg(feature = "cache")]
             cache: Arc::clone(&self.cache),
             http: Arc::clone(&self.http),
+            chunk_large_guilds: self.chunk_large_guilds,
+            presence_rotation: self.presence_rotation.clone(),
         });
 
+        let runner_tx = ShardMessenger::new(&runner);
         let runner_info = ShardRunnerInfo {
             latency: None,
-            runner_tx: ShardMessenger::new(&runner),
+            runner_tx: runner_tx.clone(),
             stage: ConnectionStage::Disconnected,
+            send_queue: SendQueueInfo::default(),
         };
 
         spawn_named("shard_queuer::stop", async move {
@@ -204,7 +423,12 @@ impl ShardQueuer {
             debug!("[ShardRunner {:?}] Stopping", runner.shard.shard_info());
         });
 
-        self.runners.lock().await.insert(id, runner_info);
+        if total == self.manager.shard_total() {
+            self.runners.lock().await.insert(id, runner_info);
+        } else if !self.manager.add_resharded_runner(shard_info, runner_info).await {
+            // Resharding was aborted while the shard was starting.
+            runner_tx.send_to_shard(ShardRunnerMessage::Shutdown(id, 1000));
+        }
 
         Ok(())
     }
@@ -250,3 +474,84 @@ impl ShardQueuer {
         }
     }
 }
+
+/// Removes the shards to start next from the queue: the first queued shard of each `shard_id %
+/// max_concurrency` bucket, up to `limit` shards in total.
+fn take_batch(queue: &mut VecDeque<ShardInfo>, max_concurrency: u64, limit: u64) -> Vec<ShardInfo> {
+    let mut buckets = HashSet::new();
+    let mut batch = Vec::new();
+
+    queue.retain(|shard| {
+        let bucket = u64::from(shard.id.0) % max_concurrency;
+        if (batch.len() as u64) < limit && buckets.insert(bucket) {
+            batch.push(*shard);
+            false
+        } else {
+            true
+        }
+    });
+
+    batch
+}
+
+/// Removes the shards whose retry is due from the scheduled retries, in the order they were due.
+fn take_due_retries(retries: &mut Vec<(Instant, ShardInfo)>, now: Instant) -> Vec<ShardInfo> {
+    let (mut due, pending): (Vec<_>, _) = retries.drain(..).partition(|&(at, _)| at <= now);
+    *retries = pending;
+
+    due.sort_by_key(|&(at, _)| at);
+    due.into_iter().map(|(_, shard)| shard).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use tokio::time::{Duration, Instant};
+
+    use super::{take_batch, take_due_retries, ShardId};
+    use crate::model::gateway::ShardInfo;
+
+    fn ids(shards: impl IntoIterator<Item = ShardInfo>) -> Vec<u32> {
+        shards.into_iter().map(|shard| shard.id.0).collect()
+    }
+
+    #[test]
+    fn batches_take_one_shard_per_bucket() {
+        let mut queue = (0..10).map(|id| ShardInfo::new(ShardId(id), 10)).collect();
+
+        assert_eq!(ids(take_batch(&mut queue, 4, u64::MAX)), [0, 1, 2, 3]);
+        assert_eq!(ids(take_batch(&mut queue, 4, u64::MAX)), [4, 5, 6, 7]);
+        assert_eq!(ids(take_batch(&mut queue, 4, u64::MAX)), [8, 9]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn batches_skip_busy_buckets_and_respect_limit() {
+        // Shards 4 and 8 share bucket 0 with shard 0, e.g. after failed starts were re-queued.
+        let mut queue: VecDeque<_> =
+            [0, 4, 8, 1, 2].into_iter().map(|id| ShardInfo::new(ShardId(id), 10)).collect();
+
+        assert_eq!(ids(take_batch(&mut queue, 4, 2)), [0, 1]);
+        assert_eq!(ids(queue.clone()), [4, 8, 2]);
+        assert_eq!(ids(take_batch(&mut queue, 4, u64::MAX)), [4, 2]);
+        assert_eq!(ids(take_batch(&mut queue, 1, u64::MAX)), [8]);
+    }
+
+    #[test]
+    fn due_retries_are_taken_in_order() {
+        let now = Instant::now();
+        let shard = |id| ShardInfo::new(ShardId(id), 4);
+        let mut retries = vec![
+            (now + Duration::from_secs(1), shard(0)),
+            (now, shard(1)),
+            (now - Duration::from_secs(1), shard(2)),
+            (now + Duration::from_secs(8), shard(3)),
+        ];
+
+        assert_eq!(ids(take_due_retries(&mut retries, now)), [2, 1]);
+        assert_eq!(ids(retries.iter().map(|&(_, shard)| shard)), [0, 3]);
+        assert_eq!(ids(take_due_retries(&mut retries, now + Duration::from_secs(8))), [0, 3]);
+        assert!(retries.is_empty());
+    }
+}
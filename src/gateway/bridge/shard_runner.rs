@@ -1,7 +1,10 @@
 use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use futures::channel::mpsc::{self, UnboundedReceiver as Receiver, UnboundedSender as Sender};
+use futures::StreamExt;
 use tokio::sync::RwLock;
 use tokio_tungstenite::tungstenite;
 use tokio_tungstenite::tungstenite::error::Error as TungsteniteError;
@@ -10,22 +13,43 @@ use tracing::{debug, error, info, instrument, trace, warn};
 use typemap_rev::TypeMap;
 
 use super::event::ShardStageUpdateEvent;
+use super::health::SharedHealth;
+use super::send_ratelimiter::SendRatelimiter;
 #[cfg(feature = "collector")]
 use super::CollectorCallback;
 #[cfg(feature = "voice")]
 use super::VoiceGatewayManager;
-use super::{ShardId, ShardManager, ShardRunnerMessage};
+use super::{
+    ChunkGuildFilter,
+    EventSink,
+    MemberRequests,
+    PresenceRotation,
+    SendQueueInfo,
+    ShardId,
+    ShardManager,
+    ShardRunnerMessage,
+};
 #[cfg(feature = "cache")]
 use crate::cache::Cache;
 use crate::client::dispatch::dispatch_model;
 use crate::client::{Context, EventHandler, RawEventHandler};
 #[cfg(feature = "framework")]
 use crate::framework::Framework;
-use crate::gateway::{GatewayError, ReconnectType, Shard, ShardAction};
+use crate::gateway::reconnect::ReconnectBackoff;
+use crate::gateway::{ConnectionStage, ReconnectType, Shard, ShardAction};
 use crate::http::Http;
 use crate::internal::prelude::*;
 use crate::internal::tokio::spawn_named;
-use crate::model::event::{Event, GatewayEvent};
+use crate::model::event::{Event, GatewayEvent, GuildMembersChunkEvent};
+use crate::model::id::GuildId;
+
+/// The minimum time between two member requests sent when automatically chunking guilds, which
+/// leaves most of the gateway send ratelimit to other messages.
+const AUTO_CHUNK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a shard waits for the next Guild Create of its session before considering the guilds
+/// it has not received yet to be unavailable.
+const GUILD_CREATE_TIMEOUT: Duration = Duration::from_secs(15);
 
 /// A runner for managing a [`Shard`] and its respective WebSocket client.
 pub struct ShardRunner {
@@ -35,6 +59,8 @@ pub struct ShardRunner {
     #[cfg(feature = "framework")]
     framework: Option<Arc<dyn Framework>>,
     manager: Arc<ShardManager>,
+    // where dispatched events are forwarded to instead of being dispatched, in broker mode
+    event_sink: Option<Arc<dyn EventSink>>,
     // channel to receive messages from the shard manager and dispatches
     runner_rx: Receiver<ShardRunnerMessage>,
     // channel to send messages to the shard runner from the shard manager
@@ -47,12 +73,36 @@ pub struct ShardRunner {
     pub http: Arc<Http>,
     #[cfg(feature = "collector")]
     pub(crate) collectors: Arc<std::sync::Mutex<Vec<CollectorCallback>>>,
+    pub(crate) member_requests: MemberRequests,
+    chunk_large_guilds: bool,
+    // the activities to rotate through, and when the next one is due
+    presence_rotation: Option<Arc<PresenceRotation>>,
+    next_rotation_at: Instant,
+    // large guilds that are yet to be chunked, and when the next one may be
+    pending_chunks: VecDeque<GuildId>,
+    next_chunk_at: Instant,
+    // messages waiting for the gateway send ratelimit, with when they were queued
+    send_queue: VecDeque<(ShardRunnerMessage, Instant)>,
+    send_ratelimiter: SendRatelimiter,
+    send_queue_info: SendQueueInfo,
+    // guilds of the session that are yet to be received, and when the last guild was received
+    awaiting_guilds: Option<(HashSet<GuildId>, Instant)>,
+    health: SharedHealth,
+    reconnect_backoff: ReconnectBackoff,
+    // whether events were dispatched in the previous iteration, to register with the voice
+    // manager once resharding switches to this shard
+    #[cfg(feature = "voice")]
+    dispatching: bool,
 }
 
 impl ShardRunner {
     /// Creates a new runner for a Shard.
     pub fn new(opt: ShardRunnerOptions) -> Self {
         let (tx, rx) = mpsc::unbounded();
+        #[cfg(feature = "voice")]
+        let dispatching = opt.shard.shard_info().total == opt.manager.shard_total();
+        let health = opt.manager.shard_health(opt.shard.shard_info());
+        health.lock().expect("poison").record_stage(opt.shard.stage(), Instant::now());
 
         Self {
             runner_rx: rx,
@@ -63,6 +113,7 @@ impl ShardRunner {
             #[cfg(feature = "framework")]
             framework: opt.framework,
             manager: opt.manager,
+            event_sink: opt.event_sink,
             shard: opt.shard,
             #[cfg(feature = "voice")]
             voice_manager: opt.voice_manager,
@@ -71,6 +122,20 @@ impl ShardRunner {
             http: opt.http,
             #[cfg(feature = "collector")]
             collectors: Arc::new(std::sync::Mutex::new(vec![])),
+            member_requests: MemberRequests::default(),
+            chunk_large_guilds: opt.chunk_large_guilds,
+            presence_rotation: opt.presence_rotation,
+            next_rotation_at: Instant::now(),
+            pending_chunks: VecDeque::new(),
+            next_chunk_at: Instant::now(),
+            send_queue: VecDeque::new(),
+            send_ratelimiter: SendRatelimiter::default(),
+            send_queue_info: SendQueueInfo::default(),
+            awaiting_guilds: None,
+            health,
+            reconnect_backoff: ReconnectBackoff::default(),
+            #[cfg(feature = "voice")]
+            dispatching,
         }
     }
 
@@ -78,47 +143,83 @@ impl ShardRunner {
     ///
     /// This runs a loop that performs the following in each iteration:
     ///
-    /// 1. checks the receiver for [`ShardRunnerMessage`]s, possibly from the [`ShardManager`], and
-    ///    if there is one, acts on it.
+    /// 1. checks if a heartbeat is due, and if so, sends one to the discord Gateway.
     ///
-    /// 2. checks if a heartbeat should be sent to the discord Gateway, and if so, sends one.
+    /// 2. checks the receiver for [`ShardRunnerMessage`]s, possibly from the [`ShardManager`], and
+    ///    if there is one, acts on it. Messages to send over the gateway are queued once the
+    ///    gateway send ratelimit of 120 messages per minute is reached.
     ///
     /// 3. attempts to retrieve a message from the WebSocket, processing it into a [`GatewayEvent`].
-    ///    This will block for 100ms before assuming there is no message available.
+    ///    This will block for 500ms, or until the next heartbeat is due, before assuming there is
+    ///    no message available.
     ///
     /// 4. Checks with the [`Shard`] to determine if the gateway event is specifying an action to
     ///    take (e.g. resuming, reconnecting, heartbeating) and then performs that action, if any.
     ///
-    /// 5. Dispatches the event via the Client.
+    /// 5. Dispatches the event via the Client, unless the shard belongs to a set of shards that is
+    ///    still being started by [`ShardManager::reshard`] or has been replaced by it.
     ///
     /// 6. Go back to 1.
     ///
     /// [`ShardManager`]: super::ShardManager
+    /// [`ShardManager::reshard`]: super::ShardManager::reshard
     #[instrument(skip(self))]
     pub async fn run(&mut self) -> Result<()> {
         info!("[ShardRunner {:?}] Running", self.shard.shard_info());
 
         loop {
             trace!("[ShardRunner {:?}] loop iteration started.", self.shard.shard_info());
-            if !self.recv().await {
-                return Ok(());
-            }
+            self.record_stage();
 
             // check heartbeat
+            let last_heartbeat_sent = self.shard.last_heartbeat_sent();
             if !self.shard.do_heartbeat().await {
                 warn!("[ShardRunner {:?}] Error heartbeating", self.shard.shard_info(),);
 
                 self.request_restart().await;
                 return Ok(());
             }
+            if self.shard.last_heartbeat_sent() != last_heartbeat_sent {
+                self.send_ratelimiter.record_heartbeat(Instant::now());
+            }
+
+            if !self.recv().await {
+                return Ok(());
+            }
+
+            if !self.chunk_pending_guild().await || !self.rotate_presence().await {
+                self.request_restart().await;
+                return Ok(());
+            }
 
             let pre = self.shard.stage();
             let (event, action, successful) = self.recv_event().await?;
+            let payload = self.shard.take_payload();
             let post = self.shard.stage();
+            let dispatching = self.is_dispatching();
+            self.record_stage();
+            if post == ConnectionStage::Connected {
+                self.reconnect_backoff.reset();
+            }
+
+            #[cfg(feature = "voice")]
+            if dispatching && !self.dispatching && self.shard.session_id().is_some() {
+                if let Some(voice_manager) = &self.voice_manager {
+                    voice_manager
+                        .register_shard(self.shard.shard_info().id.0, self.runner_tx.clone())
+                        .await;
+                }
+            }
+            #[cfg(feature = "voice")]
+            {
+                self.dispatching = dispatching;
+            }
 
             if post != pre {
                 self.update_manager().await;
+            }
 
+            if post != pre && dispatching {
                 for event_handler in self.event_handlers.clone() {
                     let context = self.make_context();
                     let event = ShardStageUpdateEvent {
@@ -137,49 +238,58 @@ impl ShardRunner {
                     self.request_restart().await;
                     return Ok(());
                 },
-                Some(other) => {
-                    if let Err(e) = self.action(&other).await {
+                Some(other) => match self.action(&other).await {
+                    Ok(true) => {},
+                    Ok(false) => return Ok(()),
+                    Err(e) => {
                         debug!(
                             "[ShardRunner {:?}] Reconnecting due to error performing {:?}: {:?}",
                             self.shard.shard_info(),
                             other,
                             e
                         );
-                        match self.shard.reconnection_type() {
-                            ReconnectType::Reidentify => {
-                                self.request_restart().await;
-                                return Ok(());
-                            },
-                            ReconnectType::Resume => {
-                                if let Err(why) = self.shard.resume().await {
-                                    warn!(
-                                        "[ShardRunner {:?}] Resume failed, reidentifying: {:?}",
-                                        self.shard.shard_info(),
-                                        why
-                                    );
-
-                                    self.request_restart().await;
-                                    return Ok(());
-                                }
-                            },
+                        if !self.reconnect().await {
+                            return Ok(());
                         }
-                    }
+                    },
                 },
                 None => {},
             }
 
-            if let Some(event) = event {
-                #[cfg(feature = "collector")]
-                self.collectors.lock().expect("poison").retain_mut(|callback| (callback.0)(&event));
-
-                dispatch_model(
-                    event,
-                    &self.make_context(),
-                    #[cfg(feature = "framework")]
-                    self.framework.clone(),
-                    self.event_handlers.clone(),
-                    self.raw_event_handlers.clone(),
-                );
+            self.track_guilds(event.as_ref()).await;
+
+            if let Some(event) = event.filter(|_| dispatching) {
+                match &event {
+                    Event::GuildCreate(event) if self.chunk_large_guilds && event.guild.large => {
+                        self.pending_chunks.push_back(event.guild.id);
+                    },
+                    Event::GuildMembersChunk(event) => self.forward_member_chunk(event),
+                    _ => {},
+                }
+
+                if let Some(event_sink) = &self.event_sink {
+                    let shard_id = self.shard.shard_info().id;
+                    if let Some(payload) = payload {
+                        if let Err(why) = event_sink.forward(shard_id, payload).await {
+                            warn!("[ShardRunner {:?}] Err forwarding event: {:?}", shard_id, why);
+                        }
+                    }
+                } else {
+                    #[cfg(feature = "collector")]
+                    self.collectors
+                        .lock()
+                        .expect("poison")
+                        .retain_mut(|callback| (callback.0)(&event));
+
+                    dispatch_model(
+                        event,
+                        &self.make_context(),
+                        #[cfg(feature = "framework")]
+                        self.framework.clone(),
+                        self.event_handlers.clone(),
+                        self.raw_event_handlers.clone(),
+                    );
+                }
             }
 
             if !successful && !self.shard.stage().is_connecting() {
@@ -190,6 +300,146 @@ impl ShardRunner {
         }
     }
 
+    /// Records the shard's current connection stage in its health metrics.
+    fn record_stage(&self) {
+        self.health.lock().expect("poison").record_stage(self.shard.stage(), Instant::now());
+    }
+
+    /// Records a received event in the shard's health metrics.
+    fn record_event(&self, event: &GatewayEvent) {
+        let mut health = self.health.lock().expect("poison");
+        health.record_event(matches!(event, GatewayEvent::Dispatch(..)), Instant::now());
+        if let GatewayEvent::Dispatch(_, Event::Resumed(_)) = event {
+            health.record_resumed();
+        }
+    }
+
+    /// Returns whether the shard's events are dispatched, which is the case unless the shard
+    /// belongs to a set of shards that is being started by [`ShardManager::reshard`] or was
+    /// replaced by it.
+    ///
+    /// [`ShardManager::reshard`]: super::ShardManager::reshard
+    fn is_dispatching(&self) -> bool {
+        self.shard.shard_info().total == self.manager.shard_total()
+    }
+
+    /// Tracks the guilds of the session that are yet to be received, and notifies the manager once
+    /// all of them were received or none were received for a while.
+    async fn track_guilds(&mut self, event: Option<&Event>) {
+        match event {
+            Some(Event::Ready(event)) => {
+                let guilds = event.ready.guilds.iter().map(|guild| guild.id).collect();
+                self.awaiting_guilds = Some((guilds, Instant::now()));
+            },
+            Some(Event::GuildCreate(event)) => self.guild_received(event.guild.id),
+            Some(Event::GuildDelete(event)) => self.guild_received(event.guild.id),
+            _ => {},
+        }
+
+        if let Some((guilds, last_received)) = &self.awaiting_guilds {
+            if guilds.is_empty() || last_received.elapsed() >= GUILD_CREATE_TIMEOUT {
+                self.awaiting_guilds = None;
+                self.manager.guilds_received(self.shard.shard_info()).await;
+            }
+        }
+    }
+
+    fn guild_received(&mut self, guild_id: GuildId) {
+        if let Some((guilds, last_received)) = &mut self.awaiting_guilds {
+            guilds.remove(&guild_id);
+            *last_received = Instant::now();
+        }
+    }
+
+    /// Requests the members of the next large guild waiting to be chunked, if any and if enough
+    /// time has passed since the last request.
+    ///
+    /// Returns whether the request could be sent.
+    #[instrument(skip(self))]
+    async fn chunk_pending_guild(&mut self) -> bool {
+        let now = Instant::now();
+        if self.pending_chunks.is_empty()
+            || self.next_chunk_at > now
+            || self.shard.stage() != ConnectionStage::Connected
+        {
+            return true;
+        }
+
+        // Messages sent through the shard messenger take precedence.
+        if !self.send_queue.is_empty() || !self.send_ratelimiter.try_acquire(now) {
+            return true;
+        }
+
+        let Some(guild_id) = self.pending_chunks.pop_front() else {
+            return true;
+        };
+
+        debug!("[ShardRunner {:?}] Chunking large guild {}", self.shard.shard_info(), guild_id);
+        self.next_chunk_at = Instant::now() + AUTO_CHUNK_INTERVAL;
+        self.shard.chunk_guild(guild_id, None, false, ChunkGuildFilter::None, None).await.is_ok()
+    }
+
+    /// Shows the activity of the presence rotation for the current interval, if it is due and the
+    /// shard is connected.
+    ///
+    /// Returns whether the presence update could be sent.
+    #[instrument(skip(self))]
+    async fn rotate_presence(&mut self) -> bool {
+        let now = Instant::now();
+        let Some(rotation) = self.presence_rotation.clone() else {
+            return true;
+        };
+        if self.next_rotation_at > now || self.shard.stage() != ConnectionStage::Connected {
+            return true;
+        }
+
+        let tick = rotation.tick(now);
+        let activity = rotation.activity(tick, &self.make_context());
+        if activity.is_some() && activity != self.shard.presence().activity {
+            // Messages sent through the shard messenger take precedence.
+            if !self.send_queue.is_empty() || !self.send_ratelimiter.try_acquire(now) {
+                return true;
+            }
+
+            debug!(
+                "[ShardRunner {:?}] Rotating presence to {:?}",
+                self.shard.shard_info(),
+                activity
+            );
+            self.shard.set_activity(activity);
+            if self.shard.update_presence().await.is_err() {
+                return false;
+            }
+        }
+
+        self.next_rotation_at = rotation.tick_start(tick.saturating_add(1));
+        true
+    }
+
+    /// Forwards a member chunk to the [`ShardMessenger::request_members`] call awaiting it.
+    ///
+    /// [`ShardMessenger::request_members`]: super::ShardMessenger::request_members
+    fn forward_member_chunk(&self, event: &GuildMembersChunkEvent) {
+        let Some(nonce) = &event.nonce else {
+            return;
+        };
+
+        let mut member_requests = self.member_requests.lock().expect("poison");
+        if let Some(tx) = member_requests.get(nonce) {
+            if tx.send(event.clone()).is_err() {
+                member_requests.remove(nonce);
+            }
+        }
+    }
+
+    /// Drops the senders of all pending [`ShardMessenger::request_members`] calls, so that they
+    /// fail as interrupted instead of waiting for chunks that will never be received.
+    ///
+    /// [`ShardMessenger::request_members`]: super::ShardMessenger::request_members
+    fn cancel_member_requests(&self) {
+        self.member_requests.lock().expect("poison").clear();
+    }
+
     /// Clones the internal copy of the Sender to the shard runner.
     pub(super) fn runner_tx(&self) -> Sender<ShardRunnerMessage> {
         self.runner_tx.clone()
@@ -200,19 +450,98 @@ impl ShardRunner {
     /// For example, if the shard says that an Identify message needs to be sent, this will do
     /// that.
     ///
+    /// Returns whether the shard runner can continue.
+    ///
     /// # Errors
     ///
-    /// Returns
+    /// Returns an error if sending a message over the gateway failed.
     #[instrument(skip(self, action))]
-    async fn action(&mut self, action: &ShardAction) -> Result<()> {
+    async fn action(&mut self, action: &ShardAction) -> Result<bool> {
         match *action {
             ShardAction::Reconnect(ReconnectType::Reidentify) => {
                 self.request_restart().await;
-                Ok(())
+                Ok(false)
             },
-            ShardAction::Reconnect(ReconnectType::Resume) => self.shard.resume().await,
-            ShardAction::Heartbeat => self.shard.heartbeat().await,
-            ShardAction::Identify => self.shard.identify().await,
+            ShardAction::Reconnect(ReconnectType::Resume) => {
+                self.shard.resume().await.map(|()| true)
+            },
+            ShardAction::Heartbeat => {
+                self.send_ratelimiter.record_heartbeat(Instant::now());
+                self.shard.heartbeat().await.map(|()| true)
+            },
+            ShardAction::Identify => self.shard.identify().await.map(|()| true),
+            ShardAction::Backoff => Ok(self.backoff().await && self.reconnect().await),
+        }
+    }
+
+    /// Reconnects the shard, resuming its session if there is one. Otherwise, or if resuming
+    /// fails, the shard is restarted to identify anew.
+    ///
+    /// Returns whether the shard runner can continue.
+    #[instrument(skip(self))]
+    async fn reconnect(&mut self) -> bool {
+        match self.shard.reconnection_type() {
+            ReconnectType::Reidentify => {
+                self.request_restart().await;
+                false
+            },
+            ReconnectType::Resume => {
+                if let Err(why) = self.shard.resume().await {
+                    warn!(
+                        "[ShardRunner {:?}] Resume failed, reidentifying: {:?}",
+                        self.shard.shard_info(),
+                        why
+                    );
+
+                    self.request_restart().await;
+                    return false;
+                }
+
+                true
+            },
+        }
+    }
+
+    /// Waits before reconnecting, for longer with each consecutive reconnect since the shard was
+    /// last connected.
+    ///
+    /// Restarts and shutdowns from the [`ShardManager`] are still handled while waiting, while
+    /// other messages are queued until the shard is reconnected.
+    ///
+    /// Returns whether the shard runner can continue.
+    ///
+    /// [`ShardManager`]: super::ShardManager
+    #[instrument(skip(self))]
+    async fn backoff(&mut self) -> bool {
+        let delay = self.reconnect_backoff.next_delay();
+        debug!("[ShardRunner {:?}] Backing off for {:?}", self.shard.shard_info(), delay);
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+
+        loop {
+            tokio::select! {
+                () = &mut sleep => return true,
+                value = self.runner_rx.next() => match value {
+                    Some(
+                        value @ (ShardRunnerMessage::Restart(_)
+                        | ShardRunnerMessage::Shutdown(..)),
+                    ) => {
+                        if !self.handle_rx_value(value).await {
+                            return false;
+                        }
+                    },
+                    Some(value) => self.send_queue.push_back((value, Instant::now())),
+                    None => {
+                        warn!(
+                            "[ShardRunner {:?}] Sending half DC; restarting",
+                            self.shard.shard_info(),
+                        );
+
+                        self.request_restart().await;
+                        return false;
+                    },
+                },
+            }
         }
     }
 
@@ -230,6 +559,8 @@ impl ShardRunner {
             return true;
         }
 
+        self.cancel_member_requests();
+
         // Send a Close Frame to Discord, which allows a bot to "log off"
         drop(
             self.shard
@@ -257,8 +588,18 @@ impl ShardRunner {
             }
         }
 
-        // Inform the manager that shutdown for this shard has finished.
-        self.manager.shutdown_finished(id);
+        // Discord invalidates sessions closed with 1000 or 1001, so anything else may be resumed.
+        if !matches!(close_code, 1000 | 1001) {
+            if let Some(snapshot) = self.shard.session_snapshot() {
+                self.manager.store_session_snapshot(snapshot).await;
+            }
+        }
+
+        // Inform the manager that shutdown for this shard has finished, unless the shard was
+        // replaced by resharding, in which case the manager is not waiting for it.
+        if self.is_dispatching() {
+            self.manager.shutdown_finished(id);
+        }
         false
     }
 
@@ -316,6 +657,19 @@ impl ShardRunner {
                 self.shard.set_status(status);
                 self.shard.update_presence().await.is_ok()
             },
+            ShardRunnerMessage::RequestSoundboardSounds {
+                guild_ids,
+            } => self.shard.request_soundboard_sounds(&guild_ids).await.is_ok(),
+            ShardRunnerMessage::UpdateVoiceState {
+                guild_id,
+                channel_id,
+                self_mute,
+                self_deaf,
+            } => self
+                .shard
+                .update_voice_state(guild_id, channel_id, self_mute, self_deaf)
+                .await
+                .is_ok(),
         }
     }
 
@@ -346,7 +700,8 @@ impl ShardRunner {
 
     // Receives values over the internal shard runner rx channel and handles them.
     //
-    // This will loop over values until there is no longer one.
+    // This will loop over values until there is no longer one. Values that send a message over
+    // the gateway are queued, and sent as the gateway send ratelimit allows.
     //
     // Requests a restart if the sending half of the channel disconnects. This should _never_
     // happen, as the sending half is kept on the runner.
@@ -355,6 +710,9 @@ impl ShardRunner {
     async fn recv(&mut self) -> bool {
         loop {
             match self.runner_rx.try_next() {
+                Ok(Some(value)) if is_ratelimited(&value) => {
+                    self.send_queue.push_back((value, Instant::now()));
+                },
                 Ok(Some(value)) => {
                     if !self.handle_rx_value(value).await {
                         return false;
@@ -375,6 +733,43 @@ impl ShardRunner {
 
         // There are no longer any values available.
 
+        self.flush_send_queue().await
+    }
+
+    // Sends queued messages until the gateway send ratelimit is reached.
+    //
+    // Returns whether the shard runner is in a state that can continue.
+    #[instrument(skip(self))]
+    async fn flush_send_queue(&mut self) -> bool {
+        while !self.send_queue.is_empty() && self.send_ratelimiter.try_acquire(Instant::now()) {
+            let Some((value, queued_at)) = self.send_queue.pop_front() else {
+                break;
+            };
+
+            self.send_queue_info.total_wait += queued_at.elapsed();
+            if !self.handle_rx_value(value).await {
+                return false;
+            }
+        }
+
+        if self.send_queue.len() != self.send_queue_info.depth {
+            if self.send_queue.len() > self.send_queue_info.depth {
+                debug!(
+                    "[ShardRunner {:?}] {} messages waiting for the send ratelimit",
+                    self.shard.shard_info(),
+                    self.send_queue.len(),
+                );
+            }
+
+            self.send_queue_info.depth = self.send_queue.len();
+            self.send_queue_info.oldest_queued_at =
+                self.send_queue.front().map(|(_, queued_at)| *queued_at);
+            let send_queue = self.send_queue_info;
+            self.manager
+                .update_runner(self.shard.shard_info(), |runner| runner.send_queue = send_queue)
+                .await;
+        }
+
         true
     }
 
@@ -382,7 +777,7 @@ impl ShardRunner {
     /// successful.
     #[instrument(skip(self))]
     async fn recv_event(&mut self) -> Result<(Option<Event>, Option<ShardAction>, bool)> {
-        let gw_event = match self.shard.client.recv_json().await {
+        let gw_event = match self.shard.recv_until_heartbeat().await {
             Ok(inner) => Ok(inner),
             Err(Error::Tungstenite(TungsteniteError::Io(_))) => {
                 debug!("Attempting to auto-reconnect");
@@ -394,9 +789,7 @@ impl ShardRunner {
                             warn!("Failed to resume: {:?}", why);
 
                             // Don't spam reattempts on internet connection loss
-                            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-
-                            return Ok((None, None, false));
+                            return Ok((None, Some(ShardAction::Backoff), true));
                         }
                     },
                 }
@@ -407,7 +800,10 @@ impl ShardRunner {
         };
 
         let event = match gw_event {
-            Ok(Some(event)) => Ok(event),
+            Ok(Some(event)) => {
+                self.record_event(&event);
+                Ok(event)
+            },
             Ok(None) => return Ok((None, None, true)),
             Err(why) => Err(why),
         };
@@ -418,18 +814,14 @@ impl ShardRunner {
             Err(why) => {
                 error!("Shard handler received err: {:?}", why);
 
-                match &why {
-                    Error::Gateway(
-                        error @ (GatewayError::InvalidAuthentication
-                        | GatewayError::InvalidGatewayIntents
-                        | GatewayError::DisallowedGatewayIntents),
-                    ) => {
-                        self.manager.return_with_value(Err(error.clone())).await;
+                // The reconnect policy decided that the close is fatal.
+                if let Error::Gateway(error) = &why {
+                    self.manager.return_with_value(Err(error.clone())).await;
 
-                        return Err(why);
-                    },
-                    _ => return Ok((None, None, true)),
+                    return Err(why);
                 }
+
+                return Ok((None, None, true));
             },
         };
 
@@ -438,7 +830,7 @@ impl ShardRunner {
         }
 
         #[cfg(feature = "voice")]
-        {
+        if self.is_dispatching() {
             if let Ok(GatewayEvent::Dispatch(_, ref event)) = event {
                 self.handle_voice_event(event).await;
             }
@@ -456,29 +848,48 @@ impl ShardRunner {
     async fn request_restart(&mut self) {
         debug!("[ShardRunner {:?}] Requesting restart", self.shard.shard_info());
 
+        // The chunks of pending member requests will not be received on a new connection.
+        self.cancel_member_requests();
         self.update_manager().await;
 
-        let shard_id = self.shard.shard_info().id;
-        self.manager.restart_shard(shard_id).await;
+        let shard_info = self.shard.shard_info();
+        self.manager.restart_runner(shard_info).await;
 
         #[cfg(feature = "voice")]
-        if let Some(voice_manager) = &self.voice_manager {
-            voice_manager.deregister_shard(shard_id.0).await;
+        if let Some(voice_manager) = self.voice_manager.as_ref().filter(|_| self.is_dispatching()) {
+            voice_manager.deregister_shard(shard_info.id.0).await;
         }
     }
 
     #[instrument(skip(self))]
     async fn update_manager(&self) {
+        let (latency, stage) = (self.shard.latency(), self.shard.stage());
         self.manager
-            .update_shard_latency_and_stage(
-                self.shard.shard_info().id,
-                self.shard.latency(),
-                self.shard.stage(),
-            )
+            .update_runner(self.shard.shard_info(), |runner| {
+                runner.latency = latency;
+                runner.stage = stage;
+            })
             .await;
     }
 }
 
+impl Drop for ShardRunner {
+    fn drop(&mut self) {
+        self.cancel_member_requests();
+    }
+}
+
+/// Returns whether a value received by the shard runner sends a message that counts towards the
+/// gateway send ratelimit.
+fn is_ratelimited(value: &ShardRunnerMessage) -> bool {
+    !matches!(
+        value,
+        ShardRunnerMessage::Restart(_)
+            | ShardRunnerMessage::Shutdown(..)
+            | ShardRunnerMessage::Close(..)
+    )
+}
+
 /// Options to be passed to [`ShardRunner::new`].
 pub struct ShardRunnerOptions {
     pub data: Arc<RwLock<TypeMap>>,
@@ -487,10 +898,17 @@ pub struct ShardRunnerOptions {
     #[cfg(feature = "framework")]
     pub framework: Option<Arc<dyn Framework>>,
     pub manager: Arc<ShardManager>,
+    /// The sink to forward dispatched events to instead of dispatching them. The shard must retain
+    /// its payloads, see [`Shard::set_retain_payloads`].
+    pub event_sink: Option<Arc<dyn EventSink>>,
     pub shard: Shard,
     #[cfg(feature = "voice")]
     pub voice_manager: Option<Arc<dyn VoiceGatewayManager>>,
     #[cfg(feature = "cache")]
     pub cache: Arc<Cache>,
     pub http: Arc<Http>,
+    /// Whether to request the members of large guilds when they are received.
+    pub chunk_large_guilds: bool,
+    /// The activities to rotate the shard's presence through.
+    pub presence_rotation: Option<Arc<PresenceRotation>>,
 }
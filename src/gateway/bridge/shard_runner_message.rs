@@ -2,7 +2,7 @@ use tokio_tungstenite::tungstenite::Message;
 
 use super::ShardId;
 use crate::gateway::{ActivityData, ChunkGuildFilter};
-use crate::model::id::GuildId;
+use crate::model::id::{ChannelId, GuildId};
 use crate::model::user::OnlineStatus;
 
 /// A message to send from a shard over a WebSocket.
@@ -50,4 +50,20 @@ pub enum ShardRunnerMessage {
     SetPresence(Option<ActivityData>, OnlineStatus),
     /// Indicates that the client is to update the shard's presence's status.
     SetStatus(OnlineStatus),
+    /// Indicates that the client is to request the soundboard sounds of guilds.
+    RequestSoundboardSounds {
+        /// The IDs of the guilds to request the soundboard sounds of.
+        guild_ids: Vec<GuildId>,
+    },
+    /// Indicates that the client is to update the current user's voice state in a guild.
+    UpdateVoiceState {
+        /// The ID of the guild.
+        guild_id: GuildId,
+        /// The ID of the voice or stage channel to join, or [`None`] to leave.
+        channel_id: Option<ChannelId>,
+        /// Whether the current user is muted.
+        self_mute: bool,
+        /// Whether the current user is deafened.
+        self_deaf: bool,
+    },
 }
@@ -3,6 +3,8 @@ use std::fmt;
 
 use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 
+use super::EtfError;
+
 /// An error that occurred while attempting to deal with the gateway.
 ///
 /// Note that - from a user standpoint - there should be no situation in which you manually handle
@@ -50,6 +52,13 @@ pub enum Error {
     /// If an connection has been established but privileged gateway intents were provided without
     /// enabling them prior.
     DisallowedGatewayIntents,
+    /// When a payload could not be encoded or decoded using the ETF encoding.
+    Etf(EtfError),
+    /// When no member chunk was received in time for a member request.
+    MemberRequestTimeout,
+    /// When the shard was restarted or shut down before all member chunks of a member request
+    /// were received.
+    MemberRequestInterrupted,
 }
 
 impl fmt::Display for Error {
@@ -70,6 +79,11 @@ impl fmt::Display for Error {
             Self::DisallowedGatewayIntents => {
                 f.write_str("Disallowed gateway intents were provided")
             },
+            Self::Etf(inner) => write!(f, "Error encoding or decoding ETF: {inner}"),
+            Self::MemberRequestTimeout => f.write_str("Timed out waiting for member chunks"),
+            Self::MemberRequestInterrupted => {
+                f.write_str("Shard stopped before all member chunks were received")
+            },
         }
     }
 }
@@ -0,0 +1,864 @@
+//! Serde support for the [External Term Format], used by the gateway's `etf` encoding.
+//!
+//! Only the subset of the format that Discord sends and accepts is supported. Binaries are
+//! deserialized as strings, the atoms `nil`, `true` and `false` as unit and booleans, and big
+//! integers - which Discord uses for snowflakes - as 64-bit (or, if needed, 128-bit) integers.
+//!
+//! [External Term Format]: https://www.erlang.org/doc/apps/erts/erl_ext_dist.html
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+
+const VERSION: u8 = 131;
+
+const NEW_FLOAT_EXT: u8 = 70;
+const SMALL_INTEGER_EXT: u8 = 97;
+const INTEGER_EXT: u8 = 98;
+const FLOAT_EXT: u8 = 99;
+const ATOM_EXT: u8 = 100;
+const SMALL_TUPLE_EXT: u8 = 104;
+const LARGE_TUPLE_EXT: u8 = 105;
+const NIL_EXT: u8 = 106;
+const STRING_EXT: u8 = 107;
+const LIST_EXT: u8 = 108;
+const BINARY_EXT: u8 = 109;
+const SMALL_BIG_EXT: u8 = 110;
+const LARGE_BIG_EXT: u8 = 111;
+const SMALL_ATOM_EXT: u8 = 115;
+const MAP_EXT: u8 = 116;
+const ATOM_UTF8_EXT: u8 = 118;
+const SMALL_ATOM_UTF8_EXT: u8 = 119;
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// An error that occurred while encoding or decoding an ETF payload.
+#[derive(Clone, Debug)]
+pub struct Error(String);
+
+impl Error {
+    fn msg(msg: impl Into<String>) -> Self {
+        Self(msg.into())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl StdError for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// Deserializes a value from a complete ETF payload, including the leading version byte.
+pub(crate) fn from_slice<'de, T: de::Deserialize<'de>>(bytes: &'de [u8]) -> Result<T> {
+    let mut deserializer = Deserializer {
+        input: bytes,
+    };
+
+    if deserializer.take_u8()? != VERSION {
+        return Err(Error::msg("unsupported ETF version"));
+    }
+
+    let value = T::deserialize(&mut deserializer)?;
+
+    if deserializer.input.is_empty() {
+        Ok(value)
+    } else {
+        Err(Error::msg("trailing bytes after ETF term"))
+    }
+}
+
+/// Serializes a value into a complete ETF payload, including the leading version byte.
+pub(crate) fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    let mut serializer = Serializer {
+        output: vec![VERSION],
+    };
+
+    value.serialize(&mut serializer)?;
+
+    Ok(serializer.output)
+}
+
+fn is_atom(tag: u8) -> bool {
+    matches!(tag, ATOM_EXT | SMALL_ATOM_EXT | ATOM_UTF8_EXT | SMALL_ATOM_UTF8_EXT)
+}
+
+struct Deserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> Deserializer<'de> {
+    fn take(&mut self, len: usize) -> Result<&'de [u8]> {
+        if self.input.len() < len {
+            return Err(Error::msg("unexpected end of ETF payload"));
+        }
+
+        let (taken, rest) = self.input.split_at(len);
+        self.input = rest;
+
+        Ok(taken)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+
+        Ok(array)
+    }
+
+    fn take_u8(&mut self) -> Result<u8> {
+        self.take_array::<1>().map(|[byte]| byte)
+    }
+
+    fn take_len_u8(&mut self) -> Result<usize> {
+        self.take_u8().map(usize::from)
+    }
+
+    fn take_len_u16(&mut self) -> Result<usize> {
+        self.take_array().map(u16::from_be_bytes).map(usize::from)
+    }
+
+    fn take_len_u32(&mut self) -> Result<usize> {
+        let len = u32::from_be_bytes(self.take_array()?);
+
+        usize::try_from(len).map_err(|_| Error::msg("ETF length out of range"))
+    }
+
+    /// Parses the name of an atom whose tag has already been consumed.
+    fn parse_atom(&mut self, tag: u8) -> Result<&'de str> {
+        let len = match tag {
+            SMALL_ATOM_EXT | SMALL_ATOM_UTF8_EXT => self.take_len_u8()?,
+            _ => self.take_len_u16()?,
+        };
+
+        std::str::from_utf8(self.take(len)?).map_err(|_| Error::msg("atom is not valid UTF-8"))
+    }
+
+    /// Parses a string stored as either a binary or an atom.
+    fn parse_str(&mut self) -> Result<&'de str> {
+        match self.take_u8()? {
+            BINARY_EXT => {
+                let len = self.take_len_u32()?;
+
+                std::str::from_utf8(self.take(len)?)
+                    .map_err(|_| Error::msg("binary is not valid UTF-8"))
+            },
+            tag if is_atom(tag) => self.parse_atom(tag),
+            tag => Err(Error::msg(format!("expected a binary or atom, found tag {tag}"))),
+        }
+    }
+
+    fn peek_nil(&self) -> bool {
+        let mut probe = Deserializer {
+            input: self.input,
+        };
+
+        match probe.take_u8() {
+            Ok(tag) if is_atom(tag) => probe.parse_atom(tag).is_ok_and(|atom| atom == "nil"),
+            _ => false,
+        }
+    }
+
+    /// Parses a big integer with the given number of little-endian digits.
+    fn visit_big<V: Visitor<'de>>(&mut self, len: usize, visitor: V) -> Result<V::Value> {
+        let negative = self.take_u8()? != 0;
+        let digits = self.take(len)?;
+
+        if digits.iter().skip(16).any(|&digit| digit != 0) {
+            return Err(Error::msg("ETF big integer out of range"));
+        }
+
+        let magnitude = digits
+            .iter()
+            .take(16)
+            .rev()
+            .fold(0_u128, |magnitude, &digit| (magnitude << 8) | u128::from(digit));
+
+        match (negative, u64::try_from(magnitude)) {
+            (false, Ok(magnitude)) => visitor.visit_u64(magnitude),
+            (false, Err(_)) => visitor.visit_u128(magnitude),
+            (true, _) => {
+                let value = 0_i128
+                    .checked_sub_unsigned(magnitude)
+                    .ok_or_else(|| Error::msg("ETF big integer out of range"))?;
+
+                match i64::try_from(value) {
+                    Ok(value) => visitor.visit_i64(value),
+                    Err(_) => visitor.visit_i128(value),
+                }
+            },
+        }
+    }
+
+    /// Consumes the tail of a proper list.
+    fn end_list(&mut self) -> Result<()> {
+        match self.take_u8()? {
+            NIL_EXT => Ok(()),
+            _ => Err(Error::msg("improper lists are not supported")),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.take_u8()? {
+            SMALL_INTEGER_EXT => visitor.visit_u8(self.take_u8()?),
+            INTEGER_EXT => visitor.visit_i32(i32::from_be_bytes(self.take_array()?)),
+            NEW_FLOAT_EXT => visitor.visit_f64(f64::from_be_bytes(self.take_array()?)),
+            FLOAT_EXT => {
+                let text = std::str::from_utf8(self.take(31)?)
+                    .map_err(|_| Error::msg("float is not valid UTF-8"))?;
+                let value = text
+                    .trim_end_matches('\0')
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::msg("invalid ETF float"))?;
+
+                visitor.visit_f64(value)
+            },
+            tag if is_atom(tag) => match self.parse_atom(tag)? {
+                "nil" => visitor.visit_unit(),
+                "true" => visitor.visit_bool(true),
+                "false" => visitor.visit_bool(false),
+                atom => visitor.visit_borrowed_str(atom),
+            },
+            BINARY_EXT => {
+                let len = self.take_len_u32()?;
+                let bytes = self.take(len)?;
+
+                match std::str::from_utf8(bytes) {
+                    Ok(string) => visitor.visit_borrowed_str(string),
+                    Err(_) => visitor.visit_borrowed_bytes(bytes),
+                }
+            },
+            STRING_EXT => {
+                let len = self.take_len_u16()?;
+                let bytes = self.take(len)?;
+
+                visitor.visit_seq(de::value::SeqDeserializer::new(bytes.iter().copied()))
+            },
+            NIL_EXT => visitor.visit_seq(Access {
+                de: self,
+                remaining: 0,
+            }),
+            LIST_EXT => {
+                let remaining = self.take_len_u32()?;
+                let value = visitor.visit_seq(Access {
+                    de: self,
+                    remaining,
+                })?;
+                self.end_list()?;
+
+                Ok(value)
+            },
+            SMALL_TUPLE_EXT => {
+                let remaining = self.take_len_u8()?;
+                visitor.visit_seq(Access {
+                    de: self,
+                    remaining,
+                })
+            },
+            LARGE_TUPLE_EXT => {
+                let remaining = self.take_len_u32()?;
+                visitor.visit_seq(Access {
+                    de: self,
+                    remaining,
+                })
+            },
+            MAP_EXT => {
+                let remaining = self.take_len_u32()?;
+                visitor.visit_map(Access {
+                    de: self,
+                    remaining,
+                })
+            },
+            SMALL_BIG_EXT => {
+                let len = self.take_len_u8()?;
+                self.visit_big(len, visitor)
+            },
+            LARGE_BIG_EXT => {
+                let len = self.take_len_u32()?;
+                self.visit_big(len, visitor)
+            },
+            tag => Err(Error::msg(format!("unsupported ETF tag {tag}"))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.peek_nil() {
+            de::Deserializer::deserialize_ignored_any(self, de::IgnoredAny)?;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        if self.input.first() == Some(&MAP_EXT) {
+            self.take_u8()?;
+            if self.take_len_u32()? != 1 {
+                return Err(Error::msg("expected a map with a single key for an enum"));
+            }
+
+            visitor.visit_enum(Access {
+                de: self,
+                remaining: 1,
+            })
+        } else {
+            visitor.visit_enum(self.parse_str()?.into_deserializer())
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// Access to the elements of a list, tuple or map, or to an externally tagged enum.
+struct Access<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    remaining: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for Access<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Access<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for Access<'_, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let variant = seed.deserialize(&mut *self.de)?;
+
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Access<'_, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        de::Deserialize::deserialize(self.de)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(self.de, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        de::Deserializer::deserialize_map(self.de, visitor)
+    }
+}
+
+struct Serializer {
+    output: Vec<u8>,
+}
+
+impl Serializer {
+    fn write_atom(&mut self, atom: &str) {
+        self.output.push(SMALL_ATOM_UTF8_EXT);
+        self.output.push(atom.len() as u8);
+        self.output.extend_from_slice(atom.as_bytes());
+    }
+
+    fn write_binary(&mut self, bytes: &[u8]) -> Result<()> {
+        let len = u32::try_from(bytes.len()).map_err(|_| Error::msg("binary too long for ETF"))?;
+
+        self.output.push(BINARY_EXT);
+        self.output.extend_from_slice(&len.to_be_bytes());
+        self.output.extend_from_slice(bytes);
+
+        Ok(())
+    }
+
+    fn write_integer(&mut self, value: i128) {
+        if let Ok(small) = u8::try_from(value) {
+            self.output.extend_from_slice(&[SMALL_INTEGER_EXT, small]);
+        } else if let Ok(integer) = i32::try_from(value) {
+            self.output.push(INTEGER_EXT);
+            self.output.extend_from_slice(&integer.to_be_bytes());
+        } else {
+            self.write_big(value < 0, value.unsigned_abs());
+        }
+    }
+
+    fn write_big(&mut self, negative: bool, magnitude: u128) {
+        let digits = magnitude.to_le_bytes();
+        let len = digits.iter().rposition(|&digit| digit != 0).map_or(0, |last| last + 1);
+
+        self.output.extend_from_slice(&[SMALL_BIG_EXT, len as u8, u8::from(negative)]);
+        self.output.extend_from_slice(&digits[..len]);
+    }
+
+    /// Writes the header of a list or map whose length is only known once it has been written.
+    fn begin(&mut self, tag: u8) -> Compound<'_> {
+        self.output.push(tag);
+        let header = self.output.len();
+        self.output.extend_from_slice(&[0; 4]);
+
+        Compound {
+            ser: self,
+            tag,
+            header,
+            len: 0,
+        }
+    }
+
+    /// Writes the head of an externally tagged enum variant, which is a map with a single key.
+    fn begin_variant(&mut self, variant: &str) -> Result<()> {
+        self.output.push(MAP_EXT);
+        self.output.extend_from_slice(&1_u32.to_be_bytes());
+        self.write_binary(variant.as_bytes())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.write_atom(if v { "true" } else { "false" });
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.write_integer(v);
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.write_big(false, v);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.output.push(NEW_FLOAT_EXT);
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.write_binary(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.write_binary(v)
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        self.write_atom("nil");
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.begin_variant(variant)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a>> {
+        Ok(self.begin(LIST_EXT))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Compound<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Compound<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>> {
+        self.begin_variant(variant)?;
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a>> {
+        Ok(self.begin(MAP_EXT))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Compound<'a>> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>> {
+        self.begin_variant(variant)?;
+        self.serialize_map(Some(len))
+    }
+}
+
+/// A list or map being serialized, whose length is filled in by [`Compound::finish`].
+struct Compound<'a> {
+    ser: &'a mut Serializer,
+    tag: u8,
+    /// The position of the length field in the output.
+    header: usize,
+    len: u32,
+}
+
+impl Compound<'_> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.len = self.len.checked_add(1).ok_or_else(|| Error::msg("too many ETF elements"))?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn finish(self) {
+        if self.tag == LIST_EXT {
+            if self.len == 0 {
+                // An empty list is written as a bare NIL_EXT.
+                self.ser.output.truncate(self.header - 1);
+                self.ser.output.push(NIL_EXT);
+
+                return;
+            }
+
+            self.ser.output.push(NIL_EXT);
+        }
+
+        self.ser.output[self.header..self.header + 4].copy_from_slice(&self.len.to_be_bytes());
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish();
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish();
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish();
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish();
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.element(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish();
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.element(key)?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish();
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.element(key)?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_slice, to_vec};
+    use crate::json::{json, Value};
+    use crate::model::event::{Event, GatewayEvent};
+    use crate::model::id::{ChannelId, GuildId, MessageId};
+
+    #[test]
+    fn decode_erlang_terms() {
+        // term_to_binary(#{<<"op">> => 11, <<"d">> => nil, <<"s">> => 1234567890123456789})
+        let bytes = [
+            131, 116, 0, 0, 0, 3, 109, 0, 0, 0, 2, b'o', b'p', 97, 11, 109, 0, 0, 0, 1, b'd', 119,
+            3, b'n', b'i', b'l', 109, 0, 0, 0, 1, b's', 110, 8, 0, 0x15, 0x81, 0xe9, 0x7d, 0xf4,
+            0x10, 0x22, 0x11,
+        ];
+
+        let value: Value = from_slice(&bytes).unwrap();
+        assert_eq!(value, json!({"op": 11, "d": null, "s": 1_234_567_890_123_456_789_u64}));
+    }
+
+    #[test]
+    fn roundtrip() {
+        let value = json!({
+            "small": 7,
+            "negative": -300,
+            "big": 1_234_567_890_123_456_789_u64,
+            "float": 1.5,
+            "string": "hello",
+            "bool": true,
+            "null": null,
+            "list": [1, "two", []],
+            "map": {},
+        });
+
+        assert_eq!(from_slice::<Value>(&to_vec(&value).unwrap()).unwrap(), value);
+    }
+
+    #[test]
+    fn decode_gateway_events_with_big_integer_snowflakes() {
+        let hello = json!({"op": 10, "s": null, "t": null, "d": {"heartbeat_interval": 41250}});
+        let event: GatewayEvent = from_slice(&to_vec(&hello).unwrap()).unwrap();
+        assert!(matches!(event, GatewayEvent::Hello(41250)));
+
+        // Discord sends snowflakes as integers over ETF, most of which do not fit an INTEGER_EXT.
+        let delete = json!({
+            "op": 0,
+            "s": 42,
+            "t": "MESSAGE_DELETE",
+            "d": {
+                "id": 1_085_641_257_813_348_362_u64,
+                "channel_id": 381_880_193_700_069_377_u64,
+                "guild_id": 381_880_193_251_409_931_u64,
+            },
+        });
+        let event: GatewayEvent = from_slice(&to_vec(&delete).unwrap()).unwrap();
+
+        let GatewayEvent::Dispatch(42, Event::MessageDelete(event)) = event else {
+            panic!("expected a MESSAGE_DELETE dispatch, got {event:?}");
+        };
+        assert_eq!(event.message_id, MessageId::new(1_085_641_257_813_348_362));
+        assert_eq!(event.channel_id, ChannelId::new(381_880_193_700_069_377));
+        assert_eq!(event.guild_id, Some(GuildId::new(381_880_193_251_409_931)));
+    }
+}
@@ -41,7 +41,11 @@
 
 mod bridge;
 mod error;
+mod etf;
+mod reconnect;
+mod recording;
 mod shard;
+mod stream;
 mod ws;
 
 use std::fmt;
@@ -52,11 +56,20 @@ use reqwest::Url;
 
 pub use self::bridge::*;
 pub use self::error::Error as GatewayError;
+pub use self::etf::Error as EtfError;
+pub use self::reconnect::{
+    DefaultReconnectPolicy,
+    GatewayCloseCode,
+    ReconnectAction,
+    ReconnectPolicy,
+};
+pub use self::recording::{GatewayRecorder, GatewayReplay};
 pub use self::shard::Shard;
+pub use self::stream::ShardStream;
 pub use self::ws::WsClient;
 #[cfg(feature = "http")]
 use crate::internal::prelude::*;
-use crate::model::gateway::{Activity, ActivityType};
+use crate::model::gateway::{Activity, ActivityType, ShardInfo};
 use crate::model::id::UserId;
 use crate::model::user::OnlineStatus;
 
@@ -242,6 +255,90 @@ pub enum ShardAction {
     Heartbeat,
     Identify,
     Reconnect(ReconnectType),
+    /// Indicator that the shard should reconnect like [`ReconnectAction::Resume`] after waiting
+    /// for a delay that grows with each consecutive reconnect.
+    Backoff,
+}
+
+/// A snapshot of a shard's gateway session, which can be persisted to resume the session after
+/// the process restarts instead of identifying anew.
+///
+/// Obtained from [`Shard::session_snapshot`] or [`ShardManager::shutdown_all_resumable`], and
+/// resumed by passing it to [`ClientBuilder::session_snapshots`].
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/gateway#resuming).
+///
+/// [`ClientBuilder::session_snapshots`]: crate::client::ClientBuilder::session_snapshots
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct SessionSnapshot {
+    /// The shard that the session belongs to.
+    pub shard_info: ShardInfo,
+    /// The ID of the session.
+    pub session_id: String,
+    /// The sequence number of the last event received in the session.
+    pub seq: u64,
+    /// The gateway URL to resume the session on.
+    pub resume_ws_url: String,
+}
+
+/// The transport compression to use for a gateway connection.
+///
+/// With [`Self::None`], Discord compresses large payloads individually instead. Transport
+/// compression shares a single compression context across all messages of a connection, which
+/// saves considerably more bandwidth at the cost of keeping that context in memory.
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/gateway#transport-compression).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum TransportCompression {
+    /// No transport compression is used; payloads are compressed individually.
+    #[default]
+    None,
+    /// The connection is compressed using `zlib-stream`.
+    Zlib,
+    /// The connection is compressed using `zstd-stream`, which decompresses faster than zlib.
+    #[cfg(feature = "transport_compression_zstd")]
+    Zstd,
+}
+
+impl TransportCompression {
+    /// The value of the `compress` query parameter to connect with, if any.
+    fn query_value(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Zlib => Some("zlib-stream"),
+            #[cfg(feature = "transport_compression_zstd")]
+            Self::Zstd => Some("zstd-stream"),
+        }
+    }
+}
+
+/// The encoding used for payloads sent over a gateway connection.
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/gateway#encoding-and-compression).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum GatewayEncoding {
+    /// Payloads are encoded as JSON.
+    #[default]
+    Json,
+    /// Payloads are encoded using Erlang's External Term Format, which is smaller and faster to
+    /// decode than JSON.
+    ///
+    /// Payload compression is not available with ETF, so this is best combined with a
+    /// [`TransportCompression`].
+    Etf,
+}
+
+impl GatewayEncoding {
+    /// The value of the `encoding` query parameter to connect with.
+    fn query_value(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Etf => "etf",
+        }
+    }
 }
 
 /// The type of reconnection that should be performed.
@@ -0,0 +1,315 @@
+use std::fmt;
+use std::time::Duration;
+
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+
+use super::GatewayError;
+use crate::constants::close_codes;
+use crate::model::gateway::ShardInfo;
+
+/// The delay before the first retry of a failed connection.
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+/// The longest delay between two retries of a failed connection.
+const BACKOFF_MAX: Duration = Duration::from_secs(120);
+
+/// A code that Discord closed a gateway connection with.
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/opcodes-and-status-codes#gateway-gateway-close-event-codes).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum GatewayCloseCode {
+    /// Something went wrong on Discord's side.
+    UnknownError,
+    /// An invalid opcode or payload for an opcode was sent.
+    UnknownOpcode,
+    /// An invalid payload was sent.
+    DecodeError,
+    /// A payload was sent prior to identifying.
+    NotAuthenticated,
+    /// The token sent with the identify payload was incorrect.
+    AuthenticationFailed,
+    /// More than one identify payload was sent.
+    AlreadyAuthenticated,
+    /// The sequence sent when resuming the session was invalid.
+    InvalidSequence,
+    /// Payloads were being sent too quickly.
+    RateLimited,
+    /// The session timed out.
+    SessionTimedOut,
+    /// An invalid shard was sent when identifying.
+    InvalidShard,
+    /// The session would have handled too many guilds.
+    ShardingRequired,
+    /// An invalid version of the gateway was connected to.
+    InvalidApiVersion,
+    /// Undocumented gateway intents were sent.
+    InvalidIntents,
+    /// Gateway intents that the bot is not approved or enabled for were sent.
+    DisallowedIntents,
+    /// Any other code, such as the 1000 and 1001 codes of a normal closure.
+    Other(u16),
+}
+
+impl GatewayCloseCode {
+    /// Returns the numeric value of the close code.
+    #[must_use]
+    pub fn code(self) -> u16 {
+        match self {
+            Self::UnknownError => close_codes::UNKNOWN_ERROR,
+            Self::UnknownOpcode => close_codes::UNKNOWN_OPCODE,
+            Self::DecodeError => close_codes::DECODE_ERROR,
+            Self::NotAuthenticated => close_codes::NOT_AUTHENTICATED,
+            Self::AuthenticationFailed => close_codes::AUTHENTICATION_FAILED,
+            Self::AlreadyAuthenticated => close_codes::ALREADY_AUTHENTICATED,
+            Self::InvalidSequence => close_codes::INVALID_SEQUENCE,
+            Self::RateLimited => close_codes::RATE_LIMITED,
+            Self::SessionTimedOut => close_codes::SESSION_TIMEOUT,
+            Self::InvalidShard => close_codes::INVALID_SHARD,
+            Self::ShardingRequired => close_codes::SHARDING_REQUIRED,
+            Self::InvalidApiVersion => close_codes::INVALID_API_VERSION,
+            Self::InvalidIntents => close_codes::INVALID_GATEWAY_INTENTS,
+            Self::DisallowedIntents => close_codes::DISALLOWED_GATEWAY_INTENTS,
+            Self::Other(code) => code,
+        }
+    }
+
+    /// Returns whether Discord allows reconnecting after a connection was closed with this code.
+    #[must_use]
+    pub fn can_reconnect(self) -> bool {
+        !matches!(
+            self,
+            Self::AuthenticationFailed
+                | Self::InvalidShard
+                | Self::ShardingRequired
+                | Self::InvalidApiVersion
+                | Self::InvalidIntents
+                | Self::DisallowedIntents
+        )
+    }
+
+    /// Returns the error that a shard closed with this code fails with, if it is not reconnected.
+    pub(crate) fn into_error(self, frame: Option<&CloseFrame<'static>>) -> GatewayError {
+        match self {
+            Self::NotAuthenticated => GatewayError::NoAuthentication,
+            Self::AuthenticationFailed => GatewayError::InvalidAuthentication,
+            Self::InvalidShard => GatewayError::InvalidShardData,
+            Self::ShardingRequired => GatewayError::OverloadedShard,
+            Self::InvalidIntents => GatewayError::InvalidGatewayIntents,
+            Self::DisallowedIntents => GatewayError::DisallowedGatewayIntents,
+            _ => GatewayError::Closed(frame.cloned()),
+        }
+    }
+}
+
+impl From<u16> for GatewayCloseCode {
+    fn from(code: u16) -> Self {
+        match code {
+            close_codes::UNKNOWN_ERROR => Self::UnknownError,
+            close_codes::UNKNOWN_OPCODE => Self::UnknownOpcode,
+            close_codes::DECODE_ERROR => Self::DecodeError,
+            close_codes::NOT_AUTHENTICATED => Self::NotAuthenticated,
+            close_codes::AUTHENTICATION_FAILED => Self::AuthenticationFailed,
+            close_codes::ALREADY_AUTHENTICATED => Self::AlreadyAuthenticated,
+            close_codes::INVALID_SEQUENCE => Self::InvalidSequence,
+            close_codes::RATE_LIMITED => Self::RateLimited,
+            close_codes::SESSION_TIMEOUT => Self::SessionTimedOut,
+            close_codes::INVALID_SHARD => Self::InvalidShard,
+            close_codes::SHARDING_REQUIRED => Self::ShardingRequired,
+            close_codes::INVALID_API_VERSION => Self::InvalidApiVersion,
+            close_codes::INVALID_GATEWAY_INTENTS => Self::InvalidIntents,
+            close_codes::DISALLOWED_GATEWAY_INTENTS => Self::DisallowedIntents,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl From<GatewayCloseCode> for u16 {
+    fn from(code: GatewayCloseCode) -> Self {
+        code.code()
+    }
+}
+
+impl fmt::Display for GatewayCloseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Other(code) => write!(f, "{code}"),
+            known => write!(f, "{} ({known:?})", known.code()),
+        }
+    }
+}
+
+/// What a shard does after its connection was closed, as decided by a [`ReconnectPolicy`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ReconnectAction {
+    /// Reconnects and resumes the session, or identifies anew if there is no session to resume.
+    Resume,
+    /// Reconnects and identifies anew, discarding the session.
+    Reidentify,
+    /// Reconnects like [`Self::Resume`], after a delay that grows exponentially with each
+    /// consecutive reconnect of the shard, up to 2 minutes.
+    Backoff,
+    /// Shuts the shard down and returns an error from [`Client::start`].
+    ///
+    /// [`Client::start`]: crate::Client::start
+    Fatal,
+}
+
+/// Decides how a shard reconnects after Discord closed its connection.
+///
+/// Set on the client with [`ClientBuilder::reconnect_policy`]. [`DefaultReconnectPolicy`] is used
+/// by default, and custom policies can defer to it for the codes they don't handle. Closures
+/// taking the shard's info and the close code, if any, implement this trait.
+///
+/// # Examples
+///
+/// Back off on every close instead of failing when the token is rejected, e.g. while a new token
+/// is being rolled out:
+///
+/// ```rust
+/// use serenity::gateway::{
+///     DefaultReconnectPolicy,
+///     GatewayCloseCode,
+///     ReconnectAction,
+///     ReconnectPolicy,
+/// };
+/// use serenity::model::gateway::ShardInfo;
+///
+/// let policy = |shard_info: ShardInfo, close_code: Option<GatewayCloseCode>| match close_code {
+///     Some(GatewayCloseCode::AuthenticationFailed) => ReconnectAction::Backoff,
+///     _ => DefaultReconnectPolicy.on_close(shard_info, close_code),
+/// };
+/// # fn assert_policy(_: impl ReconnectPolicy) {}
+/// # assert_policy(policy);
+/// ```
+///
+/// [`ClientBuilder::reconnect_policy`]: crate::client::ClientBuilder::reconnect_policy
+pub trait ReconnectPolicy: Send + Sync {
+    /// Decides what the shard does after its connection was closed with the given code, which is
+    /// [`None`] if the connection was closed without one.
+    fn on_close(
+        &self,
+        shard_info: ShardInfo,
+        close_code: Option<GatewayCloseCode>,
+    ) -> ReconnectAction;
+}
+
+impl<F> ReconnectPolicy for F
+where
+    F: Fn(ShardInfo, Option<GatewayCloseCode>) -> ReconnectAction + Send + Sync,
+{
+    fn on_close(
+        &self,
+        shard_info: ShardInfo,
+        close_code: Option<GatewayCloseCode>,
+    ) -> ReconnectAction {
+        self(shard_info, close_code)
+    }
+}
+
+/// The [`ReconnectPolicy`] following Discord's documentation of close codes.
+///
+/// Codes that Discord does not allow reconnecting after are fatal, invalidated sessions are
+/// reidentified, ratelimited connections back off, and all other connections are resumed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultReconnectPolicy;
+
+impl ReconnectPolicy for DefaultReconnectPolicy {
+    fn on_close(&self, _: ShardInfo, close_code: Option<GatewayCloseCode>) -> ReconnectAction {
+        match close_code {
+            Some(code) if !code.can_reconnect() => ReconnectAction::Fatal,
+            Some(
+                GatewayCloseCode::InvalidSequence
+                | GatewayCloseCode::SessionTimedOut
+                | GatewayCloseCode::Other(4006),
+            ) => ReconnectAction::Reidentify,
+            Some(GatewayCloseCode::RateLimited) => ReconnectAction::Backoff,
+            _ => ReconnectAction::Resume,
+        }
+    }
+}
+
+/// The delays between the reconnects of a shard, which grow exponentially with each consecutive
+/// reconnect since the shard was last connected.
+#[derive(Debug, Default)]
+pub(crate) struct ReconnectBackoff {
+    // consecutive reconnects since the shard was last connected
+    attempts: u32,
+}
+
+impl ReconnectBackoff {
+    /// Returns how long to wait before the given attempt, counted from 0, to retry a failed
+    /// connection.
+    pub(crate) fn delay(attempt: u32) -> Duration {
+        BACKOFF_BASE.saturating_mul(2_u32.saturating_pow(attempt)).min(BACKOFF_MAX)
+    }
+
+    /// Returns how long to wait before the next reconnect, counting it as an attempt.
+    pub(crate) fn next_delay(&mut self) -> Duration {
+        let delay = Self::delay(self.attempts);
+        self.attempts = self.attempts.saturating_add(1);
+
+        delay
+    }
+
+    /// Starts over from the shortest delay, once the shard is connected again.
+    pub(crate) fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{DefaultReconnectPolicy, GatewayCloseCode, ReconnectAction, ReconnectBackoff};
+    use crate::gateway::ReconnectPolicy;
+    use crate::model::gateway::ShardInfo;
+    use crate::model::id::ShardId;
+
+    #[test]
+    fn close_codes_round_trip() {
+        for code in [1000, 4000, 4004, 4006, 4012, 4014] {
+            assert_eq!(GatewayCloseCode::from(code).code(), code);
+        }
+        assert_eq!(GatewayCloseCode::from(4004), GatewayCloseCode::AuthenticationFailed);
+    }
+
+    #[test]
+    fn default_policy() {
+        let decide = |code: Option<u16>| {
+            DefaultReconnectPolicy.on_close(ShardInfo::new(ShardId(0), 1), code.map(Into::into))
+        };
+
+        assert_eq!(decide(Some(4004)), ReconnectAction::Fatal);
+        assert_eq!(decide(Some(4014)), ReconnectAction::Fatal);
+        assert_eq!(decide(Some(4009)), ReconnectAction::Reidentify);
+        assert_eq!(decide(Some(4008)), ReconnectAction::Backoff);
+        assert_eq!(decide(Some(4000)), ReconnectAction::Resume);
+        assert_eq!(decide(None), ReconnectAction::Resume);
+    }
+
+    #[test]
+    fn backoff_grows_up_to_cap() {
+        let mut backoff = ReconnectBackoff::default();
+        let delays: Vec<_> = (0..9).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 64, 120, 120]);
+
+        let mut backoff = ReconnectBackoff {
+            attempts: u32::MAX,
+        };
+        assert_eq!(backoff.next_delay(), Duration::from_secs(120));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn backoff_starts_over_once_reset() {
+        let mut backoff = ReconnectBackoff::default();
+        backoff.next_delay();
+        backoff.next_delay();
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(2));
+    }
+}
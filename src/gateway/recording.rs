@@ -0,0 +1,383 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use tokio::time::Instant;
+use tracing::warn;
+
+use super::{etf, GatewayEncoding, GatewayError};
+use crate::internal::prelude::*;
+use crate::json::{from_str, to_vec};
+use crate::model::id::ShardId;
+
+/// The payload answering each heartbeat sent during a replay.
+const HEARTBEAT_ACK: &[u8] = br#"{"op":11,"d":null}"#;
+/// How long a replayed connection waits for a payload before reporting that none was received,
+/// like a quiet WebSocket connection.
+const IDLE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A single line of a recording.
+#[derive(Deserialize)]
+struct RecordedPayload {
+    shard_id: u32,
+    elapsed_ms: u64,
+    payload: Value,
+}
+
+/// Records the raw payloads that shards receive from the gateway to a file, to be replayed with a
+/// [`GatewayReplay`] later on.
+///
+/// Set on the client with [`ClientBuilder::gateway_recorder`]. Each payload is written as a line
+/// of JSON holding the ID of the receiving shard, the milliseconds elapsed since the recorder was
+/// created, and the decompressed payload itself. Payloads received with the ETF encoding are
+/// converted to JSON. The file is written by a thread of the recorder, so that shards don't wait
+/// on it.
+///
+/// **Note**: Recordings contain everything the bot receives, including the session IDs needed to
+/// resume its sessions, and should be treated as sensitive.
+///
+/// [`ClientBuilder::gateway_recorder`]: crate::client::ClientBuilder::gateway_recorder
+#[derive(Clone, Debug)]
+pub struct GatewayRecorder {
+    lines: Sender<Vec<u8>>,
+    started: Instant,
+}
+
+impl GatewayRecorder {
+    /// Creates a recorder writing to the file at the given path, truncating it if it exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file could not be created, or if the thread writing it could
+    /// not be spawned.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::create(path)?;
+        let (lines, rx) = mpsc::channel();
+        thread::Builder::new().name("gateway_recorder".into()).spawn(move || {
+            if let Err(why) = write_lines(file, &rx) {
+                warn!("[GatewayRecorder] Err writing recording: {:?}", why);
+            }
+        })?;
+
+        Ok(Self {
+            lines,
+            started: Instant::now(),
+        })
+    }
+
+    /// Writes a payload received by a shard, in the given encoding, to the recording.
+    pub(crate) fn record(
+        &self,
+        shard_id: ShardId,
+        encoding: GatewayEncoding,
+        payload: &[u8],
+    ) -> Result<()> {
+        let converted;
+        let payload = match encoding {
+            GatewayEncoding::Json => payload,
+            GatewayEncoding::Etf => {
+                let value: Value = etf::from_slice(payload).map_err(GatewayError::Etf)?;
+                converted = to_vec(&value)?;
+                &converted
+            },
+        };
+
+        let elapsed_ms = u64::try_from(self.started.elapsed().as_millis()).unwrap_or(u64::MAX);
+        let mut line = Vec::with_capacity(payload.len() + 64);
+        write_line(&mut line, shard_id, elapsed_ms, payload)?;
+
+        self.lines.send(line).map_err(|_| Error::Other("gateway recording stopped"))
+    }
+}
+
+/// Writes the lines of a recording to its file until all recorders are dropped. The file is
+/// flushed whenever no more lines are waiting to be written.
+fn write_lines(file: File, lines: &Receiver<Vec<u8>>) -> std::io::Result<()> {
+    let mut file = BufWriter::new(file);
+    while let Ok(line) = lines.recv() {
+        file.write_all(&line)?;
+        while let Ok(line) = lines.try_recv() {
+            file.write_all(&line)?;
+        }
+
+        file.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Writes a line of a recording. Payloads from the gateway are compact JSON, which never contains
+/// a line break, so they are written as they are.
+fn write_line(
+    writer: &mut impl Write,
+    shard_id: ShardId,
+    elapsed_ms: u64,
+    payload: &[u8],
+) -> std::io::Result<()> {
+    write!(writer, r#"{{"shard_id":{shard_id},"elapsed_ms":{elapsed_ms},"payload":"#)?;
+    writer.write_all(payload)?;
+    writer.write_all(b"}\n")
+}
+
+/// Replays a recording made by a [`GatewayRecorder`], feeding the recorded payloads to the shards
+/// instead of connecting to the gateway.
+///
+/// Set on the client with [`ClientBuilder::gateway_replay`]. Each shard receives the payloads
+/// recorded for its ID, in order, and everything is processed as usual: the cache is updated and
+/// events are dispatched to the event handlers, the framework, and collectors. Nothing is sent to
+/// Discord; heartbeats are acknowledged right away, and all other messages to the gateway are
+/// discarded.
+///
+/// Start the client with the shards that were recorded, for example with [`Client::start`] for a
+/// single shard, as autosharding asks Discord for the number of shards. Once a shard has received
+/// all of its payloads, it stays idle until the client is shut down.
+///
+/// # Examples
+///
+/// Replay a recording as fast as possible, to test event handlers against real payloads:
+///
+/// ```rust,no_run
+/// use serenity::gateway::GatewayReplay;
+/// use serenity::prelude::*;
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let replay = GatewayReplay::open("session.jsonl")?;
+///
+/// let mut client = Client::builder("", GatewayIntents::all()).gateway_replay(replay).await?;
+/// client.start().await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`ClientBuilder::gateway_replay`]: crate::client::ClientBuilder::gateway_replay
+/// [`Client::start`]: crate::Client::start
+#[derive(Clone, Debug)]
+pub struct GatewayReplay {
+    shards: Arc<HashMap<ShardId, Arc<Mutex<ReplayQueue>>>>,
+    realtime: bool,
+}
+
+impl GatewayReplay {
+    /// Loads the recording at the given path.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file could not be read, or [`Error::Json`] if it is not a
+    /// valid recording.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_recording(&std::fs::read_to_string(path)?)
+    }
+
+    /// Loads a recording from its contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Json`] if the contents are not a valid recording.
+    pub fn from_recording(recording: &str) -> Result<Self> {
+        let mut shards = HashMap::<ShardId, ReplayQueue>::new();
+        for line in recording.lines().filter(|line| !line.trim().is_empty()) {
+            let recorded: RecordedPayload = from_str(line)?;
+            let queue = shards.entry(ShardId(recorded.shard_id)).or_default();
+            let at = Duration::from_millis(recorded.elapsed_ms);
+
+            queue.payloads.push_back((at, to_vec(&recorded.payload)?));
+        }
+
+        let shards = shards
+            .into_iter()
+            .map(|(shard_id, queue)| (shard_id, Arc::new(Mutex::new(queue))))
+            .collect();
+
+        Ok(Self {
+            shards: Arc::new(shards),
+            realtime: false,
+        })
+    }
+
+    /// Sets whether payloads are replayed with the delays they were recorded with, rather than as
+    /// fast as the shards process them. Disabled by default.
+    #[must_use]
+    pub fn realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    /// Returns the IDs of the shards that payloads were recorded for.
+    #[must_use]
+    pub fn shard_ids(&self) -> Vec<ShardId> {
+        let mut shard_ids = self.shards.keys().copied().collect::<Vec<_>>();
+        shard_ids.sort_unstable();
+        shard_ids
+    }
+
+    /// Returns whether all recorded payloads have been received by the shards.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.shards.values().all(|queue| queue.lock().expect("replay poisoned").payloads.is_empty())
+    }
+
+    /// Returns the payloads recorded for the given shard, which are empty if there are none.
+    pub(crate) fn shard(&self, shard_id: ShardId) -> ShardReplay {
+        ShardReplay {
+            queue: self.shards.get(&shard_id).map_or_else(Arc::default, Arc::clone),
+            realtime: self.realtime,
+        }
+    }
+}
+
+/// The recorded payloads of a shard that remain to be replayed.
+#[derive(Debug, Default)]
+struct ReplayQueue {
+    /// The payloads with the time they were received at, relative to the start of the recording.
+    payloads: VecDeque<(Duration, Vec<u8>)>,
+    /// When the first payload was replayed, and the time it was recorded at.
+    started: Option<(Instant, Duration)>,
+    /// The number of heartbeats sent that are yet to be acknowledged.
+    pending_acks: u32,
+}
+
+impl ReplayQueue {
+    /// Takes the next payload if it is due, or returns when it will be due otherwise.
+    fn pop(
+        &mut self,
+        realtime: bool,
+        now: Instant,
+    ) -> std::result::Result<Vec<u8>, Option<Instant>> {
+        if self.pending_acks > 0 {
+            self.pending_acks -= 1;
+            return Ok(HEARTBEAT_ACK.to_vec());
+        }
+
+        let Some(&(at, _)) = self.payloads.front() else {
+            return Err(None);
+        };
+
+        let (started, first_at) = *self.started.get_or_insert((now, at));
+        let due = started + at.saturating_sub(first_at);
+        if realtime && due > now {
+            return Err(Some(due));
+        }
+
+        Ok(self.payloads.pop_front().map(|(_, payload)| payload).unwrap_or_default())
+    }
+}
+
+/// The replayed connection of a single shard, taking the place of the WebSocket.
+#[derive(Debug)]
+pub(crate) struct ShardReplay {
+    queue: Arc<Mutex<ReplayQueue>>,
+    realtime: bool,
+}
+
+impl ShardReplay {
+    /// Receives the next payload, returning [`None`] if none was due in a while.
+    pub(crate) async fn recv(&self) -> Option<Vec<u8>> {
+        let now = Instant::now();
+        let due = match self.queue.lock().expect("replay poisoned").pop(self.realtime, now) {
+            Ok(payload) => return Some(payload),
+            Err(due) => due,
+        };
+
+        let idle_until = now + IDLE_TIMEOUT;
+        tokio::time::sleep_until(due.map_or(idle_until, |due| due.min(idle_until))).await;
+
+        None
+    }
+
+    /// Queues the acknowledgement of a heartbeat.
+    pub(crate) fn heartbeat(&self) {
+        self.queue.lock().expect("replay poisoned").pending_acks += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use tokio::sync::mpsc;
+    use tokio::time::{timeout, Instant};
+
+    use super::{write_line, GatewayReplay, HEARTBEAT_ACK};
+    use crate::client::{Client, Context, EventHandler};
+    use crate::json::from_slice;
+    use crate::model::event::{GatewayEvent, ResumedEvent};
+    use crate::model::gateway::GatewayIntents;
+    use crate::model::id::ShardId;
+
+    struct Handler(mpsc::UnboundedSender<ShardId>);
+
+    #[async_trait]
+    impl EventHandler for Handler {
+        async fn resume(&self, ctx: Context, _: ResumedEvent) {
+            self.0.send(ctx.shard_id).unwrap();
+        }
+    }
+
+    #[test]
+    fn recording_round_trip() {
+        let mut recording = Vec::new();
+        write_line(&mut recording, ShardId(1), 20, br#"{"op":11,"d":null}"#).unwrap();
+        write_line(&mut recording, ShardId(0), 10, br#"{"op":10,"d":{"heartbeat_interval":1}}"#)
+            .unwrap();
+        write_line(&mut recording, ShardId(1), 30, br#"{"op":7,"d":null}"#).unwrap();
+
+        let replay = GatewayReplay::from_recording(&String::from_utf8(recording).unwrap()).unwrap();
+        assert_eq!(replay.shard_ids(), vec![ShardId(0), ShardId(1)]);
+
+        let shard = replay.shard(ShardId(1));
+        let mut queue = shard.queue.lock().unwrap();
+        let events = std::iter::from_fn(|| queue.pop(false, Instant::now()).ok())
+            .map(|payload| from_slice::<GatewayEvent>(&payload).unwrap())
+            .collect::<Vec<_>>();
+        assert!(matches!(events[..], [GatewayEvent::HeartbeatAck, GatewayEvent::Reconnect]));
+    }
+
+    #[test]
+    fn realtime_waits_for_recorded_delays() {
+        let recording = concat!(
+            r#"{"shard_id":0,"elapsed_ms":1000,"payload":{"op":7,"d":null}}"#,
+            "\n",
+            r#"{"shard_id":0,"elapsed_ms":1500,"payload":{"op":7,"d":null}}"#,
+        );
+        let replay = GatewayReplay::from_recording(recording).unwrap().realtime(true);
+        let shard = replay.shard(ShardId(0));
+        let mut queue = shard.queue.lock().unwrap();
+        let start = Instant::now();
+
+        assert!(queue.pop(true, start).is_ok());
+        assert_eq!(queue.pop(true, start), Err(Some(start + Duration::from_millis(500))));
+
+        queue.pending_acks += 1;
+        assert_eq!(queue.pop(true, start), Ok(HEARTBEAT_ACK.to_vec()));
+        assert!(queue.pop(true, start + Duration::from_millis(500)).is_ok());
+        assert_eq!(queue.pop(true, start + Duration::from_secs(1)), Err(None));
+        drop(queue);
+        assert!(replay.is_finished());
+    }
+
+    #[tokio::test]
+    async fn replayed_events_reach_event_handlers() {
+        let recording = concat!(
+            r#"{"shard_id":0,"elapsed_ms":0,"payload":{"op":10,"d":{"heartbeat_interval":41250}}}"#,
+            "\n",
+            r#"{"shard_id":0,"elapsed_ms":10,"payload":{"op":0,"s":1,"t":"RESUMED","d":{}}}"#,
+        );
+        let replay = GatewayReplay::from_recording(recording).unwrap();
+        let (tx, mut resumes) = mpsc::unbounded_channel();
+        let mut client = Client::builder("token", GatewayIntents::empty())
+            .event_handler(Handler(tx))
+            .gateway_replay(replay)
+            .await
+            .unwrap();
+        tokio::spawn(async move { client.start().await });
+
+        let shard_id = timeout(Duration::from_secs(5), resumes.recv()).await.unwrap();
+        assert_eq!(shard_id, Some(ShardId(0)));
+    }
+}
@@ -1,6 +1,9 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
 use std::sync::Arc;
 use std::time::{Duration as StdDuration, Instant};
 
+use futures::Stream;
 use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::error::Error as TungsteniteError;
 use tokio_tungstenite::tungstenite::protocol::frame::CloseFrame;
@@ -11,17 +14,27 @@ use super::{
     ActivityData,
     ChunkGuildFilter,
     ConnectionStage,
+    DefaultReconnectPolicy,
+    GatewayCloseCode,
+    GatewayEncoding,
     GatewayError,
+    GatewayRecorder,
+    GatewayReplay,
     PresenceData,
+    ReconnectAction,
+    ReconnectPolicy,
     ReconnectType,
+    SessionSnapshot,
     ShardAction,
+    ShardStream,
+    TransportCompression,
     WsClient,
 };
-use crate::constants::{self, close_codes};
+use crate::constants;
 use crate::internal::prelude::*;
 use crate::model::event::{Event, GatewayEvent};
 use crate::model::gateway::{GatewayIntents, ShardInfo};
-use crate::model::id::{ApplicationId, GuildId};
+use crate::model::id::{ApplicationId, ChannelId, GuildId};
 use crate::model::user::OnlineStatus;
 
 /// A Shard is a higher-level handler for a websocket connection to Discord's gateway.
@@ -41,7 +54,7 @@ use crate::model::user::OnlineStatus;
 /// use cases, you will not need to do this, and you can leave the client to do it.
 ///
 /// This can be done by passing in the required parameters to [`Self::new`]. You can then manually
-/// handle the shard yourself.
+/// handle the shard yourself, or have a [`ShardStream`] keep it connected and yield its events.
 ///
 /// **Note**: You _really_ do not need to do this. Just call one of the appropriate methods on the
 /// [`Client`].
@@ -60,13 +73,24 @@ pub struct Shard {
     last_heartbeat_sent: Option<Instant>,
     last_heartbeat_ack: Option<Instant>,
     heartbeat_interval: Option<std::time::Duration>,
+    /// When the next heartbeat is due, once the Hello of the current connection was received.
+    next_heartbeat_at: Option<Instant>,
     application_id_callback: Option<Box<dyn FnOnce(ApplicationId) + Send + Sync>>,
+    reconnect_policy: Arc<dyn ReconnectPolicy>,
+    /// Whether the raw bytes of received payloads are kept, see [`Self::take_payload`].
+    retain_payloads: bool,
+    /// The recorder that received payloads are written to.
+    recorder: Option<GatewayRecorder>,
+    /// The recording that is replayed instead of connecting to the gateway.
+    replay: Option<GatewayReplay>,
     /// This is used by the heartbeater to determine whether the last heartbeat was sent without an
     /// acknowledgement, and whether to reconnect.
     // This must be set to `true` in `Shard::handle_event`'s `Ok(GatewayEvent::HeartbeatAck)` arm.
     last_heartbeat_acknowledged: bool,
     seq: u64,
     session_id: Option<String>,
+    /// The gateway URL given in the last Ready, used when resuming the session.
+    resume_ws_url: Option<String>,
     info: ShardInfo,
     stage: ConnectionStage,
     /// Instant of when the shard was started.
@@ -75,6 +99,8 @@ pub struct Shard {
     pub started: Instant,
     pub token: String,
     ws_url: Arc<Mutex<String>>,
+    compression: TransportCompression,
+    encoding: GatewayEncoding,
     pub intents: GatewayIntents,
 }
 
@@ -90,7 +116,7 @@ impl Shard {
     /// ```rust,no_run
     /// use std::sync::Arc;
     ///
-    /// use serenity::gateway::Shard;
+    /// use serenity::gateway::{GatewayEncoding, Shard, TransportCompression};
     /// use serenity::model::gateway::{GatewayIntents, ShardInfo};
     /// use serenity::model::id::ShardId;
     /// use tokio::sync::Mutex;
@@ -107,7 +133,11 @@ impl Shard {
     ///
     /// // retrieve the gateway response, which contains the URL to connect to
     /// let gateway = Arc::new(Mutex::new(http.get_gateway().await?.url));
-    /// let shard = Shard::new(gateway, &token, shard_info, GatewayIntents::all(), None).await?;
+    /// let compression = TransportCompression::None;
+    /// let encoding = GatewayEncoding::Json;
+    /// let intents = GatewayIntents::all();
+    /// let shard =
+    ///     Shard::new(gateway, &token, shard_info, intents, None, compression, encoding).await?;
     ///
     /// // at this point, you can create a `loop`, and receive events and match
     /// // their variants
@@ -125,10 +155,86 @@ impl Shard {
         info: ShardInfo,
         intents: GatewayIntents,
         presence: Option<PresenceData>,
+        compression: TransportCompression,
+        encoding: GatewayEncoding,
     ) -> Result<Shard> {
         let url = ws_url.lock().await.clone();
-        let client = connect(&url).await?;
+        let client = connect(&url, compression, encoding).await?;
 
+        Ok(Self::with_client(client, ws_url, token, info, intents, presence, compression))
+    }
+
+    /// Instantiates a shard that resumes the session of a snapshot, connecting straight to the
+    /// session's resume URL and sending a RESUME on that connection.
+    ///
+    /// If Discord deems the session invalid, the shard will reidentify on the given `ws_url` as
+    /// usual. Note that Ready is not sent for a resumed session.
+    ///
+    /// # Errors
+    ///
+    /// On Error, will return either [`Error::Gateway`], [`Error::Tungstenite`] or a Rustls/native
+    /// TLS error.
+    pub async fn restored(
+        ws_url: Arc<Mutex<String>>,
+        token: &str,
+        snapshot: SessionSnapshot,
+        intents: GatewayIntents,
+        presence: Option<PresenceData>,
+        compression: TransportCompression,
+        encoding: GatewayEncoding,
+    ) -> Result<Shard> {
+        let client = connect(&snapshot.resume_ws_url, compression, encoding).await?;
+        let info = snapshot.shard_info;
+
+        let mut shard =
+            Self::with_client(client, ws_url, token, info, intents, presence, compression);
+        shard.restore_session(snapshot);
+        shard.stage = ConnectionStage::Resuming;
+        shard.send_resume().await?;
+
+        Ok(shard)
+    }
+
+    /// Instantiates a shard receiving the payloads recorded for it in a [`GatewayReplay`] instead
+    /// of connecting to the gateway. Nothing that the shard sends is sent to Discord.
+    ///
+    /// The payloads are converted to the given encoding when retained, see
+    /// [`Self::set_retain_payloads`].
+    #[must_use]
+    pub fn replay(
+        replay: &GatewayReplay,
+        token: &str,
+        info: ShardInfo,
+        intents: GatewayIntents,
+        presence: Option<PresenceData>,
+        encoding: GatewayEncoding,
+    ) -> Shard {
+        let client = WsClient::replay(replay.shard(info.id), encoding);
+        let ws_url = Arc::new(Mutex::new(String::new()));
+
+        let mut shard = Self::with_client(
+            client,
+            ws_url,
+            token,
+            info,
+            intents,
+            presence,
+            TransportCompression::None,
+        );
+        shard.replay = Some(replay.clone());
+        shard
+    }
+
+    fn with_client(
+        client: WsClient,
+        ws_url: Arc<Mutex<String>>,
+        token: &str,
+        info: ShardInfo,
+        intents: GatewayIntents,
+        presence: Option<PresenceData>,
+        compression: TransportCompression,
+    ) -> Shard {
+        let encoding = client.encoding();
         let presence = presence.unwrap_or_default();
         let last_heartbeat_sent = None;
         let last_heartbeat_ack = None;
@@ -137,24 +243,33 @@ impl Shard {
         let seq = 0;
         let stage = ConnectionStage::Handshake;
         let session_id = None;
+        let resume_ws_url = None;
 
-        Ok(Shard {
+        Shard {
             client,
             presence,
             last_heartbeat_sent,
             last_heartbeat_ack,
             heartbeat_interval,
+            next_heartbeat_at: None,
             application_id_callback: None,
+            reconnect_policy: Arc::new(DefaultReconnectPolicy),
+            retain_payloads: false,
+            recorder: None,
+            replay: None,
             last_heartbeat_acknowledged,
             seq,
             stage,
             started: Instant::now(),
             token: token.to_string(),
             session_id,
+            resume_ws_url,
             info,
             ws_url,
+            compression,
+            encoding,
             intents,
-        })
+        }
     }
 
     /// Sets a callback to be called when the gateway receives the application's ID from Discord.
@@ -167,6 +282,33 @@ impl Shard {
         self.application_id_callback = Some(Box::new(callback));
     }
 
+    /// Sets the policy that decides how the shard reconnects after Discord closed its connection.
+    ///
+    /// Defaults to [`DefaultReconnectPolicy`].
+    pub fn set_reconnect_policy(&mut self, reconnect_policy: Arc<dyn ReconnectPolicy>) {
+        self.reconnect_policy = reconnect_policy;
+    }
+
+    /// Sets whether the raw bytes of each received payload are kept until the next payload is
+    /// received, so that they can be taken with [`Self::take_payload`]. Disabled by default.
+    pub fn set_retain_payloads(&mut self, retain_payloads: bool) {
+        self.retain_payloads = retain_payloads;
+        self.client.set_retain_payloads(retain_payloads);
+    }
+
+    /// Takes the raw bytes of the last received payload, decompressed but still in the shard's
+    /// [`GatewayEncoding`], if payloads are retained. See [`Self::set_retain_payloads`].
+    pub fn take_payload(&mut self) -> Option<Vec<u8>> {
+        self.client.take_payload()
+    }
+
+    /// Sets the recorder that the raw payloads received from the gateway are written to, or
+    /// [`None`] to stop recording. Payloads of a replay are not recorded.
+    pub fn set_recorder(&mut self, recorder: Option<GatewayRecorder>) {
+        self.client.set_recorder(recorder.clone().map(|recorder| (recorder, self.info.id)));
+        self.recorder = recorder;
+    }
+
     /// Retrieves the current presence of the shard.
     #[inline]
     pub fn presence(&self) -> &PresenceData {
@@ -185,10 +327,17 @@ impl Shard {
         self.last_heartbeat_ack
     }
 
+    /// Retrieves when the next heartbeat is due, if the Hello of the current connection has been
+    /// received.
+    #[inline]
+    pub fn next_heartbeat_at(&self) -> Option<Instant> {
+        self.next_heartbeat_at
+    }
+
     /// Sends a heartbeat to the gateway with the current sequence.
     ///
-    /// This sets the last heartbeat time to now, and [`Self::last_heartbeat_acknowledged`] to
-    /// `false`.
+    /// This sets the last heartbeat time to now, [`Self::last_heartbeat_acknowledged`] to `false`,
+    /// and schedules the next heartbeat one heartbeat interval from now.
     ///
     /// # Errors
     ///
@@ -197,8 +346,10 @@ impl Shard {
     pub async fn heartbeat(&mut self) -> Result<()> {
         match self.client.send_heartbeat(&self.info, Some(self.seq)).await {
             Ok(()) => {
-                self.last_heartbeat_sent = Some(Instant::now());
+                let now = Instant::now();
+                self.last_heartbeat_sent = Some(now);
                 self.last_heartbeat_acknowledged = false;
+                self.next_heartbeat_at = self.heartbeat_interval.map(|interval| now + interval);
 
                 Ok(())
             },
@@ -240,6 +391,39 @@ impl Shard {
         self.session_id.as_ref()
     }
 
+    /// Returns the gateway URL that the current session should be resumed on, if a Ready has been
+    /// received.
+    ///
+    /// Reidentifying always connects to the URL shared by all shards instead.
+    #[inline]
+    pub fn resume_ws_url(&self) -> Option<&str> {
+        self.resume_ws_url.as_deref()
+    }
+
+    /// Returns a snapshot of the current session, if there is one that can be resumed.
+    ///
+    /// To keep the session resumable, the connection must not be closed with a close code of 1000
+    /// or 1001.
+    pub fn session_snapshot(&self) -> Option<SessionSnapshot> {
+        Some(SessionSnapshot {
+            shard_info: self.info,
+            session_id: self.session_id.clone()?,
+            seq: self.seq,
+            resume_ws_url: self.resume_ws_url.clone()?,
+        })
+    }
+
+    /// Restores a session from a snapshot, so that it is resumed on its resume URL once the
+    /// gateway sends a Hello, instead of identifying.
+    ///
+    /// If Discord deems the session invalid, the shard will reidentify as usual. Note that Ready
+    /// is not sent for a resumed session.
+    pub fn restore_session(&mut self, snapshot: SessionSnapshot) {
+        self.session_id = Some(snapshot.session_id);
+        self.seq = snapshot.seq;
+        self.resume_ws_url = Some(snapshot.resume_ws_url);
+    }
+
     #[inline]
     #[instrument(skip(self))]
     pub fn set_activity(&mut self, activity: Option<ActivityData>) {
@@ -276,6 +460,16 @@ impl Shard {
         self.stage
     }
 
+    /// Returns the transport compression used for the shard's connections.
+    pub fn transport_compression(&self) -> TransportCompression {
+        self.compression
+    }
+
+    /// Returns the payload encoding used for the shard's connections.
+    pub fn encoding(&self) -> GatewayEncoding {
+        self.encoding
+    }
+
     #[instrument(skip(self))]
     fn handle_gateway_dispatch(&mut self, seq: u64, event: &Event) -> Option<ShardAction> {
         if seq > self.seq + 1 {
@@ -287,6 +481,7 @@ impl Shard {
                 debug!("[{:?}] Received Ready", self.info);
 
                 self.session_id = Some(ready.ready.session_id.clone());
+                self.resume_ws_url = Some(ready.ready.resume_gateway_url.clone());
                 self.stage = ConnectionStage::Connected;
 
                 if let Some(callback) = self.application_id_callback.take() {
@@ -314,63 +509,54 @@ impl Shard {
         &mut self,
         data: Option<&CloseFrame<'static>>,
     ) -> Result<Option<ShardAction>> {
-        let num = data.map(|d| d.code.into());
-        let clean = num == Some(1000);
+        let close_code = data.map(|d| GatewayCloseCode::from(u16::from(d.code)));
 
-        match num {
-            Some(close_codes::UNKNOWN_OPCODE) => {
+        match close_code {
+            Some(GatewayCloseCode::UnknownOpcode) => {
                 warn!("[{:?}] Sent invalid opcode.", self.info);
             },
-            Some(close_codes::DECODE_ERROR) => {
+            Some(GatewayCloseCode::DecodeError) => {
                 warn!("[{:?}] Sent invalid message.", self.info);
             },
-            Some(close_codes::NOT_AUTHENTICATED) => {
+            Some(GatewayCloseCode::NotAuthenticated) => {
                 warn!("[{:?}] Sent no authentication.", self.info);
-
-                return Err(Error::Gateway(GatewayError::NoAuthentication));
             },
-            Some(close_codes::AUTHENTICATION_FAILED) => {
+            Some(GatewayCloseCode::AuthenticationFailed) => {
                 error!("[{:?}] Sent invalid authentication, please check the token.", self.info);
-
-                return Err(Error::Gateway(GatewayError::InvalidAuthentication));
             },
-            Some(close_codes::ALREADY_AUTHENTICATED) => {
+            Some(GatewayCloseCode::AlreadyAuthenticated) => {
                 warn!("[{:?}] Already authenticated.", self.info);
             },
-            Some(close_codes::INVALID_SEQUENCE) => {
+            Some(GatewayCloseCode::InvalidSequence) => {
                 warn!("[{:?}] Sent invalid seq: {}.", self.info, self.seq);
 
                 self.seq = 0;
             },
-            Some(close_codes::RATE_LIMITED) => {
+            Some(GatewayCloseCode::RateLimited) => {
                 warn!("[{:?}] Gateway ratelimited.", self.info);
             },
-            Some(close_codes::INVALID_SHARD) => {
+            Some(GatewayCloseCode::InvalidShard) => {
                 warn!("[{:?}] Sent invalid shard data.", self.info);
-
-                return Err(Error::Gateway(GatewayError::InvalidShardData));
             },
-            Some(close_codes::SHARDING_REQUIRED) => {
+            Some(GatewayCloseCode::ShardingRequired) => {
                 error!("[{:?}] Shard has too many guilds.", self.info);
-
-                return Err(Error::Gateway(GatewayError::OverloadedShard));
             },
-            Some(4006 | close_codes::SESSION_TIMEOUT) => {
+            Some(GatewayCloseCode::Other(4006) | GatewayCloseCode::SessionTimedOut) => {
                 info!("[{:?}] Invalid session.", self.info);
 
                 self.session_id = None;
+                self.resume_ws_url = None;
             },
-            Some(close_codes::INVALID_GATEWAY_INTENTS) => {
+            Some(GatewayCloseCode::InvalidApiVersion) => {
+                error!("[{:?}] Connected to an invalid gateway version.", self.info);
+            },
+            Some(GatewayCloseCode::InvalidIntents) => {
                 error!("[{:?}] Invalid gateway intents have been provided.", self.info);
-
-                return Err(Error::Gateway(GatewayError::InvalidGatewayIntents));
             },
-            Some(close_codes::DISALLOWED_GATEWAY_INTENTS) => {
+            Some(GatewayCloseCode::DisallowedIntents) => {
                 error!("[{:?}] Disallowed gateway intents have been provided.", self.info);
-
-                return Err(Error::Gateway(GatewayError::DisallowedGatewayIntents));
             },
-            Some(other) if !clean => {
+            Some(other) if other.code() != 1000 => {
                 warn!(
                     "[{:?}] Unknown unclean close {}: {:?}",
                     self.info,
@@ -381,13 +567,18 @@ impl Shard {
             _ => {},
         }
 
-        let resume = num
-            .map_or(true, |x| x != close_codes::AUTHENTICATION_FAILED && self.session_id.is_some());
-
-        Ok(Some(if resume {
-            ShardAction::Reconnect(ReconnectType::Resume)
-        } else {
-            ShardAction::Reconnect(ReconnectType::Reidentify)
+        Ok(Some(match self.reconnect_policy.on_close(self.info, close_code) {
+            ReconnectAction::Resume => ShardAction::Reconnect(self.reconnection_type()),
+            ReconnectAction::Reidentify => ShardAction::Reconnect(ReconnectType::Reidentify),
+            ReconnectAction::Backoff => ShardAction::Backoff,
+            ReconnectAction::Fatal => {
+                let error = match close_code {
+                    Some(close_code) => close_code.into_error(data),
+                    None => GatewayError::Closed(None),
+                };
+
+                return Err(Error::Gateway(error));
+            },
         }))
     }
 
@@ -399,18 +590,25 @@ impl Shard {
     /// - `Ok(Some((event, None)))`: an op0 dispatch was received, and the shard's voice state will
     ///   be updated, _if_ the `voice` feature is enabled.
     ///
+    /// If the connection was closed, the shard's [`ReconnectPolicy`] decides how to reconnect.
+    ///
     /// # Errors
     ///
+    /// Returns an error if the connection was closed and the reconnect policy decided that the
+    /// close is fatal. For example, with the [`DefaultReconnectPolicy`]:
+    ///
     /// Returns a [`GatewayError::InvalidAuthentication`] if invalid authentication was sent in the
     /// IDENTIFY.
     ///
     /// Returns a [`GatewayError::InvalidShardData`] if invalid shard data was sent in the
     /// IDENTIFY.
     ///
-    /// Returns a [`GatewayError::NoAuthentication`] if no authentication was sent in the IDENTIFY.
-    ///
     /// Returns a [`GatewayError::OverloadedShard`] if the shard would have too many guilds
     /// assigned to it.
+    ///
+    /// Returns a [`GatewayError::InvalidGatewayIntents`] or
+    /// [`GatewayError::DisallowedGatewayIntents`] if invalid or disallowed gateway intents were
+    /// sent in the IDENTIFY.
     #[instrument(skip(self))]
     pub fn handle_event(&mut self, event: &Result<GatewayEvent>) -> Result<Option<ShardAction>> {
         match event {
@@ -431,18 +629,27 @@ impl Shard {
             &Ok(GatewayEvent::Hello(interval)) => {
                 debug!("[{:?}] Received a Hello; interval: {}", self.info, interval);
 
+                // The first heartbeat is jittered so that shards reconnecting at once don't
+                // heartbeat in lockstep.
+                let interval = StdDuration::from_millis(interval);
+                self.heartbeat_interval = Some(interval);
+                self.next_heartbeat_at =
+                    Some(Instant::now() + interval.mul_f64(heartbeat_jitter()));
+
                 if self.stage == ConnectionStage::Resuming {
                     return Ok(None);
                 }
 
-                self.heartbeat_interval = Some(std::time::Duration::from_millis(interval));
-
-                Ok(Some(if self.stage == ConnectionStage::Handshake {
-                    ShardAction::Identify
-                } else {
+                Ok(Some(if self.stage != ConnectionStage::Handshake {
                     debug!("[{:?}] Received late Hello; autoreconnecting", self.info);
 
                     ShardAction::Reconnect(self.reconnection_type())
+                } else if self.session_id.is_some() {
+                    debug!("[{:?}] Resuming restored session", self.info);
+
+                    ShardAction::Reconnect(ReconnectType::Resume)
+                } else {
+                    ShardAction::Identify
                 }))
             },
             &Ok(GatewayEvent::InvalidateSession(resumable)) => {
@@ -472,30 +679,28 @@ impl Shard {
         }
     }
 
-    /// Does a heartbeat if needed. Returns false if something went wrong and the shard should be
-    /// restarted.
+    /// Does a heartbeat if one is due, see [`Self::next_heartbeat_at`]. Returns false if something
+    /// went wrong and the shard should be restarted.
     ///
     /// `true` is returned under one of the following conditions:
-    /// - the heartbeat interval has not elapsed
+    /// - no heartbeat is due yet
     /// - a heartbeat was successfully sent
-    /// - there is no known heartbeat interval yet
+    /// - no Hello was received on the current connection yet
     ///
     /// `false` is returned under one of the following conditions:
     /// - a heartbeat acknowledgement was not received in time
     /// - an error occurred while heartbeating
     #[instrument(skip(self))]
     pub async fn do_heartbeat(&mut self) -> bool {
-        let Some(heartbeat_interval) = self.heartbeat_interval else {
+        let Some(next_heartbeat_at) = self.next_heartbeat_at else {
             // No Hello received yet
             return self.started.elapsed() < StdDuration::from_secs(15);
         };
 
-        // If a duration of time less than the heartbeat_interval has passed, then don't perform a
-        // keepalive or attempt to reconnect.
-        if let Some(last_sent) = self.last_heartbeat_sent {
-            if last_sent.elapsed() <= heartbeat_interval {
-                return true;
-            }
+        // If the next heartbeat isn't due yet, then don't perform a keepalive or attempt to
+        // reconnect.
+        if next_heartbeat_at > Instant::now() {
+            return true;
         }
 
         // If the last heartbeat didn't receive an acknowledgement, then auto-reconnect.
@@ -517,6 +722,29 @@ impl Shard {
         }
     }
 
+    /// Receives a message from the WebSocket, waiting no longer than until the next heartbeat is
+    /// due so that heartbeats are sent on schedule.
+    pub(crate) async fn recv_until_heartbeat(&mut self) -> Result<Option<GatewayEvent>> {
+        let Some(next_heartbeat_at) = self.next_heartbeat_at else {
+            return self.client.recv_json().await;
+        };
+
+        tokio::select! {
+            biased;
+            () = tokio::time::sleep_until(next_heartbeat_at.into()) => Ok(None),
+            event = self.client.recv_json() => event,
+        }
+    }
+
+    /// Returns a [`Stream`] of the events dispatched to this shard, which manages heartbeating and
+    /// reconnecting by itself. See [`ShardStream`] for more information.
+    ///
+    /// The stream ends after yielding the first error, which occurs when the connection is closed
+    /// fatally.
+    pub fn events(self) -> impl Stream<Item = Result<Event>> {
+        ShardStream::new(self).into_stream()
+    }
+
     /// Calculates the heartbeat latency between the shard and the gateway.
     // Shamelessly stolen from brayzure's commit in eris:
     // <https://github.com/abalabahaha/eris/commit/0ce296ae9a542bcec0edf1c999ee2d9986bed5a6>
@@ -586,7 +814,7 @@ impl Shard {
     /// #          total: 1,
     /// #     };
     /// #
-    /// #     let mut shard = Shard::new(mutex.clone(), "", shard_info, GatewayIntents::all(), None).await?;
+    /// #     let mut shard = Shard::new(mutex.clone(), "", shard_info, GatewayIntents::all(), None, Default::default(), Default::default()).await?;
     /// #
     /// use serenity::model::id::GuildId;
     ///
@@ -613,7 +841,7 @@ impl Shard {
     /// #          id: ShardId(0),
     /// #          total: 1,
     /// #     };
-    /// #     let mut shard = Shard::new(mutex.clone(), "", shard_info, GatewayIntents::all(), None).await?;
+    /// #     let mut shard = Shard::new(mutex.clone(), "", shard_info, GatewayIntents::all(), None, Default::default(), Default::default()).await?;
     /// #
     /// use serenity::model::id::GuildId;
     ///
@@ -647,6 +875,20 @@ impl Shard {
         self.client.send_chunk_guild(guild_id, &self.info, limit, presences, filter, nonce).await
     }
 
+    /// Requests the soundboard sounds of the given guilds.
+    ///
+    /// Discord responds with an [`Event::SoundboardSounds`] for each guild.
+    ///
+    /// # Errors
+    ///
+    /// Errors if there is a problem with the WS connection.
+    ///
+    /// [`Event::SoundboardSounds`]: crate::model::event::Event::SoundboardSounds
+    #[instrument(skip(self))]
+    pub async fn request_soundboard_sounds(&mut self, guild_ids: &[GuildId]) -> Result<()> {
+        self.client.send_request_soundboard_sounds(&self.info, guild_ids).await
+    }
+
     /// Sets the shard as going into identifying stage, which sets:
     /// - the time that the last heartbeat sent as being now
     /// - the `stage` to [`ConnectionStage::Identifying`]
@@ -662,6 +904,9 @@ impl Shard {
 
     /// Initializes a new WebSocket client.
     ///
+    /// If the shard has a session to resume, this connects to the session's resume URL, otherwise
+    /// to the shared gateway URL.
+    ///
     /// This will set the stage of the shard before and after instantiation of the client.
     #[instrument(skip(self))]
     pub async fn initialize(&mut self) -> Result<WsClient> {
@@ -676,8 +921,20 @@ impl Shard {
         // Hello is received.
         self.stage = ConnectionStage::Connecting;
         self.started = Instant::now();
-        let url = &self.ws_url.lock().await.clone();
-        let client = connect(url).await?;
+        // Heartbeats are scheduled anew by the Hello of the new connection.
+        self.next_heartbeat_at = None;
+        let mut client = if let Some(replay) = &self.replay {
+            // A replay carries on where the previous connection left off.
+            WsClient::replay(replay.shard(self.info.id), self.encoding)
+        } else {
+            let url = match &self.resume_ws_url {
+                Some(url) if self.session_id.is_some() => url.clone(),
+                _ => self.ws_url.lock().await.clone(),
+            };
+            connect(&url, self.compression, self.encoding).await?
+        };
+        client.set_retain_payloads(self.retain_payloads);
+        client.set_recorder(self.recorder.clone().map(|recorder| (recorder, self.info.id)));
         self.stage = ConnectionStage::Handshake;
 
         Ok(client)
@@ -688,12 +945,19 @@ impl Shard {
         self.last_heartbeat_sent = Some(Instant::now());
         self.last_heartbeat_ack = None;
         self.heartbeat_interval = None;
+        self.next_heartbeat_at = None;
         self.last_heartbeat_acknowledged = true;
         self.session_id = None;
+        self.resume_ws_url = None;
         self.stage = ConnectionStage::Disconnected;
         self.seq = 0;
     }
 
+    /// Opens a new connection to the session's resume URL and sends a RESUME.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`GatewayError::NoSessionId`] if there is no session to resume.
     #[instrument(skip(self))]
     pub async fn resume(&mut self) -> Result<()> {
         debug!("[{:?}] Attempting to resume", self.info);
@@ -701,6 +965,11 @@ impl Shard {
         self.client = self.initialize().await?;
         self.stage = ConnectionStage::Resuming;
 
+        self.send_resume().await
+    }
+
+    /// Sends a RESUME for the current session on the current connection.
+    async fn send_resume(&mut self) -> Result<()> {
         match &self.session_id {
             Some(session_id) => {
                 self.client.send_resume(&self.info, session_id, self.seq, &self.token).await
@@ -709,6 +978,7 @@ impl Shard {
         }
     }
 
+    /// Resets the session and opens a new connection to the shared gateway URL, ready to IDENTIFY.
     #[instrument(skip(self))]
     pub async fn reconnect(&mut self) -> Result<()> {
         info!("[{:?}] Attempting to reconnect", self.shard_info());
@@ -723,15 +993,117 @@ impl Shard {
     pub async fn update_presence(&mut self) -> Result<()> {
         self.client.send_presence_update(&self.info, &self.presence).await
     }
+
+    /// Joins, moves between, or leaves a voice or stage channel of a guild.
+    ///
+    /// Passing [`None`] as `channel_id` leaves the guild's voice channel.
+    ///
+    /// **Note**: This only updates the voice state of the current user. Connecting to the voice
+    /// server to send or receive audio requires a voice library.
+    ///
+    /// # Errors
+    ///
+    /// Errors if there is a problem with the WS connection.
+    #[instrument(skip(self))]
+    pub async fn update_voice_state(
+        &mut self,
+        guild_id: GuildId,
+        channel_id: Option<ChannelId>,
+        self_mute: bool,
+        self_deaf: bool,
+    ) -> Result<()> {
+        self.client
+            .send_voice_state_update(&self.info, guild_id, channel_id, self_mute, self_deaf)
+            .await
+    }
 }
 
-async fn connect(base_url: &str) -> Result<WsClient> {
-    let url =
+async fn connect(
+    base_url: &str,
+    compression: TransportCompression,
+    encoding: GatewayEncoding,
+) -> Result<WsClient> {
+    let mut url =
         Url::parse(&format!("{base_url}?v={}", constants::GATEWAY_VERSION)).map_err(|why| {
             warn!("Error building gateway URL with base `{}`: {:?}", base_url, why);
 
             Error::Gateway(GatewayError::BuildingUrl)
         })?;
 
-    WsClient::connect(url).await
+    url.query_pairs_mut().append_pair("encoding", encoding.query_value());
+    if let Some(compress) = compression.query_value() {
+        url.query_pairs_mut().append_pair("compress", compress);
+    }
+
+    WsClient::connect(url, compression, encoding).await
+}
+
+/// Returns a random factor in `0.0..1.0` to delay the first heartbeat of a connection by.
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/gateway#sending-heartbeats).
+#[allow(clippy::cast_precision_loss)]
+fn heartbeat_jitter() -> f64 {
+    // The hasher is randomly seeded, which is random enough for jitter.
+    let random = RandomState::new().build_hasher().finish();
+
+    // The top 53 bits fit the mantissa of an f64 exactly.
+    (random >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::StreamExt;
+    use tokio::net::TcpListener;
+    use tokio::sync::Mutex;
+    use tokio_tungstenite::accept_async;
+
+    use super::{heartbeat_jitter, Shard};
+    use crate::gateway::{GatewayEncoding, SessionSnapshot, TransportCompression};
+    use crate::json::Value;
+    use crate::model::gateway::{GatewayIntents, ShardInfo};
+    use crate::model::id::ShardId;
+
+    #[test]
+    fn heartbeat_jitter_is_a_fraction() {
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&heartbeat_jitter()));
+        }
+    }
+
+    #[tokio::test]
+    async fn restored_shard_resumes_on_first_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let snapshot = SessionSnapshot {
+            shard_info: ShardInfo::new(ShardId(0), 1),
+            session_id: "session".into(),
+            seq: 42,
+            resume_ws_url: format!("ws://{}", listener.local_addr().unwrap()),
+        };
+        // Nothing listens on the shared gateway URL, so connecting to it would fail.
+        let ws_url = Arc::new(Mutex::new("ws://127.0.0.1:1".to_string()));
+
+        let gateway = async {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = accept_async(stream).await.unwrap();
+            let message = socket.next().await.unwrap().unwrap();
+            crate::json::from_str::<Value>(message.to_text().unwrap()).unwrap()
+        };
+        let restored = Shard::restored(
+            ws_url,
+            "token",
+            snapshot,
+            GatewayIntents::empty(),
+            None,
+            TransportCompression::None,
+            GatewayEncoding::Json,
+        );
+        let (resume, shard) = tokio::join!(gateway, restored);
+
+        assert!(shard.is_ok());
+        assert_eq!(resume["op"], 6);
+        assert_eq!(resume["d"]["session_id"], "session");
+        assert_eq!(resume["d"]["seq"], 42);
+    }
 }
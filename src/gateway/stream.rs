@@ -0,0 +1,423 @@
+use futures::Stream;
+use tracing::{debug, info, instrument, warn};
+
+use super::reconnect::ReconnectBackoff;
+use super::{ConnectionStage, GatewayError, ReconnectType, Shard, ShardAction};
+use crate::internal::prelude::*;
+use crate::model::event::{Event, GatewayEvent};
+
+/// Drives a stand-alone [`Shard`], yielding the events it receives.
+///
+/// The stream takes care of everything the [`Client`] otherwise does to keep a shard connected:
+/// it identifies, heartbeats on schedule, and resumes or reidentifies as the shard's
+/// [`ReconnectPolicy`] decides when the connection is lost. Failed connection attempts are retried
+/// with a delay that grows exponentially up to 2 minutes.
+///
+/// This is useful for services that only consume events, such as event forwarders, and need
+/// neither the [`Client`] nor an [`EventHandler`].
+///
+/// # Examples
+///
+/// Log the content of every message received by a bot with a single shard:
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use serenity::futures::StreamExt;
+/// use serenity::gateway::{GatewayEncoding, Shard, ShardStream, TransportCompression};
+/// use serenity::model::event::Event;
+/// use serenity::model::gateway::{GatewayIntents, ShardInfo};
+/// use serenity::model::id::ShardId;
+/// use tokio::sync::Mutex;
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let token = std::env::var("DISCORD_BOT_TOKEN")?;
+/// let gateway = Arc::new(Mutex::new("wss://gateway.discord.gg".to_string()));
+/// let shard_info = ShardInfo {
+///     id: ShardId(0),
+///     total: 1,
+/// };
+/// let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+/// let compression = TransportCompression::None;
+/// let encoding = GatewayEncoding::Json;
+/// let shard =
+///     Shard::new(gateway, &token, shard_info, intents, None, compression, encoding).await?;
+///
+/// let mut events = ShardStream::new(shard).into_stream().boxed();
+/// while let Some(event) = events.next().await {
+///     if let Event::MessageCreate(event) = event? {
+///         println!("{}: {}", event.message.author.name, event.message.content);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Client`]: crate::Client
+/// [`EventHandler`]: crate::client::EventHandler
+/// [`ReconnectPolicy`]: super::ReconnectPolicy
+pub struct ShardStream {
+    shard: Shard,
+    reconnect_backoff: ReconnectBackoff,
+}
+
+impl ShardStream {
+    /// Creates a stream driving the given shard, which should have been freshly created with
+    /// [`Shard::new`].
+    #[must_use]
+    pub fn new(shard: Shard) -> Self {
+        Self {
+            shard,
+            reconnect_backoff: ReconnectBackoff::default(),
+        }
+    }
+
+    /// Returns the driven shard.
+    #[must_use]
+    pub fn shard(&self) -> &Shard {
+        &self.shard
+    }
+
+    /// Returns the driven shard mutably, for example to update its presence between events.
+    pub fn shard_mut(&mut self) -> &mut Shard {
+        &mut self.shard
+    }
+
+    /// Returns the driven shard, stopping the stream.
+    #[must_use]
+    pub fn into_shard(self) -> Shard {
+        self.shard
+    }
+
+    /// Waits for the next dispatched event, heartbeating and reconnecting in the meantime as
+    /// needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection was closed and the shard's [`ReconnectPolicy`] decided
+    /// that the close is fatal, such as when the token is invalid. The shard should not be driven
+    /// any further afterwards.
+    ///
+    /// [`ReconnectPolicy`]: super::ReconnectPolicy
+    #[instrument(skip(self))]
+    pub async fn next_event(&mut self) -> Result<Event> {
+        loop {
+            if !self.shard.do_heartbeat().await {
+                warn!("[ShardStream {:?}] Error heartbeating", self.shard.shard_info());
+
+                self.reconnect(self.shard.reconnection_type()).await;
+                continue;
+            }
+
+            let event = match self.shard.recv_until_heartbeat().await {
+                Ok(Some(event)) => Ok(event),
+                Ok(None) => continue,
+                Err(why) => Err(why),
+            };
+
+            let action = self.shard.handle_event(&event)?;
+            if self.shard.stage() == ConnectionStage::Connected {
+                self.reconnect_backoff.reset();
+            }
+
+            if let Some(action) = action {
+                self.action(&action).await;
+            }
+
+            if let Ok(GatewayEvent::Dispatch(_, event)) = event {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Turns this into a [`Stream`] of the shard's dispatched events, which ends after yielding
+    /// the first error. See [`Self::next_event`] for which errors can occur.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Event>> {
+        futures::stream::unfold(Some(self), |state| async {
+            let mut state = state?;
+            match state.next_event().await {
+                Ok(event) => Some((Ok(event), Some(state))),
+                Err(why) => Some((Err(why), None)),
+            }
+        })
+    }
+
+    #[instrument(skip(self))]
+    async fn action(&mut self, action: &ShardAction) {
+        let result = match *action {
+            ShardAction::Heartbeat => self.shard.heartbeat().await,
+            ShardAction::Identify => self.shard.identify().await,
+            ShardAction::Reconnect(ReconnectType::Resume) => {
+                self.reconnect(ReconnectType::Resume).await;
+                Ok(())
+            },
+            ShardAction::Reconnect(ReconnectType::Reidentify) => {
+                self.reconnect(ReconnectType::Reidentify).await;
+                Ok(())
+            },
+            ShardAction::Backoff => {
+                self.backoff().await;
+                self.reconnect(self.shard.reconnection_type()).await;
+                Ok(())
+            },
+        };
+
+        if let Err(why) = result {
+            debug!(
+                "[ShardStream {:?}] Reconnecting due to error performing {:?}: {:?}",
+                self.shard.shard_info(),
+                action,
+                why
+            );
+
+            self.reconnect(self.shard.reconnection_type()).await;
+        }
+    }
+
+    /// Opens a new connection, resuming the session if possible, until a connection succeeds.
+    ///
+    /// When reidentifying, the IDENTIFY is sent once the new connection's Hello is received.
+    #[instrument(skip(self))]
+    async fn reconnect(&mut self, mut reconnect_type: ReconnectType) {
+        loop {
+            let result = match reconnect_type {
+                ReconnectType::Resume => self.shard.resume().await,
+                ReconnectType::Reidentify => {
+                    // Discord asks to wait a little before identifying again, and the wait grows
+                    // if identifying keeps failing.
+                    self.backoff().await;
+                    self.shard.reconnect().await
+                },
+            };
+
+            match result {
+                Ok(()) => return,
+                Err(Error::Gateway(GatewayError::NoSessionId)) => {
+                    reconnect_type = ReconnectType::Reidentify;
+                },
+                Err(why) => {
+                    warn!(
+                        "[ShardStream {:?}] Err reconnecting: {:?}",
+                        self.shard.shard_info(),
+                        why
+                    );
+
+                    if matches!(reconnect_type, ReconnectType::Resume) {
+                        self.backoff().await;
+                    }
+                },
+            }
+        }
+    }
+
+    /// Waits out the delay before the next reconnect attempt.
+    async fn backoff(&mut self) {
+        let delay = self.reconnect_backoff.next_delay();
+
+        info!("[ShardStream {:?}] Backing off for {:?}", self.shard.shard_info(), delay);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::Mutex;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::{accept_async, WebSocketStream};
+
+    use super::ShardStream;
+    use crate::gateway::{
+        GatewayEncoding,
+        GatewayError,
+        SessionSnapshot,
+        Shard,
+        TransportCompression,
+    };
+    use crate::json::Value;
+    use crate::model::event::Event;
+    use crate::model::gateway::{GatewayIntents, ShardInfo};
+    use crate::model::id::ShardId;
+    use crate::Error;
+
+    /// A connection that a shard opened to the mock gateway.
+    struct Connection(WebSocketStream<TcpStream>);
+
+    impl Connection {
+        async fn accept(listener: &TcpListener) -> Self {
+            let (stream, _) = listener.accept().await.unwrap();
+            Self(accept_async(stream).await.unwrap())
+        }
+
+        async fn send(&mut self, payload: &str) {
+            self.0.send(Message::Text(payload.into())).await.unwrap();
+        }
+
+        async fn hello(&mut self, heartbeat_interval: u64) {
+            self.send(&format!(r#"{{"op":10,"d":{{"heartbeat_interval":{heartbeat_interval}}}}}"#))
+                .await;
+        }
+
+        async fn dispatch(&mut self, seq: u64) {
+            self.send(&format!(r#"{{"op":0,"s":{seq},"t":"MOCK_EVENT","d":{{}}}}"#)).await;
+        }
+
+        /// Receives the data of the next payload with the given opcode, skipping all others.
+        async fn recv(&mut self, op: u8) -> Value {
+            loop {
+                let message = self.0.next().await.unwrap().unwrap();
+                let mut payload =
+                    crate::json::from_str::<Value>(message.to_text().unwrap()).unwrap();
+                if payload["op"] == op {
+                    return payload["d"].take();
+                }
+            }
+        }
+    }
+
+    async fn mock_gateway() -> (TcpListener, Arc<Mutex<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        (listener, Arc::new(Mutex::new(url)))
+    }
+
+    async fn new_shard(ws_url: Arc<Mutex<String>>) -> Shard {
+        Shard::new(
+            ws_url,
+            "token",
+            ShardInfo::new(ShardId(0), 1),
+            GatewayIntents::empty(),
+            None,
+            TransportCompression::None,
+            GatewayEncoding::Json,
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn restored_shard(ws_url: Arc<Mutex<String>>) -> Shard {
+        let snapshot = SessionSnapshot {
+            shard_info: ShardInfo::new(ShardId(0), 1),
+            session_id: "session".into(),
+            seq: 42,
+            resume_ws_url: ws_url.lock().await.clone(),
+        };
+
+        Shard::restored(
+            ws_url,
+            "token",
+            snapshot,
+            GatewayIntents::empty(),
+            None,
+            TransportCompression::None,
+            GatewayEncoding::Json,
+        )
+        .await
+        .unwrap()
+    }
+
+    fn assert_mock_event(event: &Event) {
+        assert!(matches!(event, Event::Unknown(event) if event.kind == "MOCK_EVENT"));
+    }
+
+    #[tokio::test]
+    async fn heartbeats_on_schedule() {
+        let (listener, ws_url) = mock_gateway().await;
+        let gateway = tokio::spawn(async move {
+            let mut connection = Connection::accept(&listener).await;
+            // Dispatched before the Hello, so that the first heartbeat already carries its
+            // sequence.
+            connection.dispatch(5).await;
+            connection.hello(100).await;
+            connection.recv(2).await;
+
+            // The connection would be reopened if a heartbeat was not acknowledged.
+            assert_eq!(connection.recv(1).await, 5);
+            connection.send(r#"{"op":11}"#).await;
+            assert_eq!(connection.recv(1).await, 5);
+            connection.dispatch(6).await;
+        });
+
+        let mut stream = ShardStream::new(new_shard(ws_url).await);
+        assert_mock_event(&stream.next_event().await.unwrap());
+        assert_mock_event(&stream.next_event().await.unwrap());
+
+        gateway.await.unwrap();
+        assert_eq!(stream.shard().seq(), 6);
+    }
+
+    #[tokio::test]
+    async fn resumes_when_asked_to_reconnect() {
+        let (listener, ws_url) = mock_gateway().await;
+        let gateway = tokio::spawn(async move {
+            let mut connection = Connection::accept(&listener).await;
+            connection.recv(6).await;
+            connection.hello(41250).await;
+            connection.send(r#"{"op":7,"d":null}"#).await;
+
+            let mut connection = Connection::accept(&listener).await;
+            let resume = connection.recv(6).await;
+            connection.hello(41250).await;
+            connection.dispatch(43).await;
+            resume
+        });
+
+        let mut stream = ShardStream::new(restored_shard(ws_url).await);
+        assert_mock_event(&stream.next_event().await.unwrap());
+
+        let resume = gateway.await.unwrap();
+        assert_eq!(resume["session_id"], "session");
+        assert_eq!(resume["seq"], 42);
+    }
+
+    #[tokio::test]
+    async fn reidentifies_when_session_is_invalidated() {
+        let (listener, ws_url) = mock_gateway().await;
+        let gateway = tokio::spawn(async move {
+            let mut connection = Connection::accept(&listener).await;
+            connection.recv(6).await;
+            connection.hello(41250).await;
+            connection.send(r#"{"op":9,"d":false}"#).await;
+
+            let mut connection = Connection::accept(&listener).await;
+            connection.hello(41250).await;
+            let identify = connection.recv(2).await;
+            connection.dispatch(1).await;
+            identify
+        });
+
+        let mut stream = ShardStream::new(restored_shard(ws_url).await);
+        assert_mock_event(&stream.next_event().await.unwrap());
+
+        let identify = gateway.await.unwrap();
+        assert_eq!(identify["token"], "token");
+        assert_eq!(stream.shard().session_id(), None);
+    }
+
+    #[tokio::test]
+    async fn fatal_close_ends_stream() {
+        let (listener, ws_url) = mock_gateway().await;
+        let gateway = tokio::spawn(async move {
+            let mut connection = Connection::accept(&listener).await;
+            connection.hello(41250).await;
+            connection.recv(2).await;
+            let close = CloseFrame {
+                code: CloseCode::from(4004),
+                reason: "Authentication failed.".into(),
+            };
+            connection.0.close(Some(close)).await.unwrap();
+        });
+
+        let mut events = new_shard(ws_url).await.events().boxed();
+        let result = events.next().await.unwrap();
+        assert!(matches!(result, Err(Error::Gateway(GatewayError::InvalidAuthentication))));
+        assert!(events.next().await.is_none());
+
+        gateway.await.unwrap();
+    }
+}
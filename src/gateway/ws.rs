@@ -1,3 +1,5 @@
+#[cfg(feature = "client")]
+use std::borrow::Cow;
 use std::env::consts;
 #[cfg(feature = "client")]
 use std::io::Read;
@@ -5,6 +7,8 @@ use std::time::SystemTime;
 
 #[cfg(feature = "client")]
 use flate2::read::ZlibDecoder;
+#[cfg(feature = "client")]
+use flate2::{Decompress, FlushDecompress};
 use futures::SinkExt;
 #[cfg(feature = "client")]
 use futures::StreamExt;
@@ -22,18 +26,30 @@ use tokio_tungstenite::{connect_async_with_config, MaybeTlsStream, WebSocketStre
 use tracing::warn;
 use tracing::{debug, instrument, trace};
 use url::Url;
-
-use super::{ActivityData, ChunkGuildFilter, PresenceData};
+#[cfg(all(feature = "client", feature = "transport_compression_zstd"))]
+use zstd_safe::DCtx;
+#[cfg(all(feature = "client", feature = "transport_compression_zstd"))]
+use zstd_safe::{InBuffer, OutBuffer};
+
+use super::recording::ShardReplay;
+use super::{
+    etf,
+    ActivityData,
+    ChunkGuildFilter,
+    GatewayEncoding,
+    GatewayRecorder,
+    PresenceData,
+    TransportCompression,
+};
 use crate::constants::{self, Opcode};
-#[cfg(feature = "client")]
 use crate::gateway::GatewayError;
-#[cfg(feature = "client")]
-use crate::json::from_str;
 use crate::json::to_string;
 #[cfg(feature = "client")]
+use crate::json::{from_slice, from_str};
+#[cfg(feature = "client")]
 use crate::model::event::GatewayEvent;
 use crate::model::gateway::{GatewayIntents, ShardInfo};
-use crate::model::id::{GuildId, UserId};
+use crate::model::id::{ChannelId, GuildId, ShardId, UserId};
 #[cfg(feature = "client")]
 use crate::Error;
 use crate::Result;
@@ -65,6 +81,14 @@ struct PresenceUpdateMessage<'a> {
     activities: &'a [&'a ActivityData],
 }
 
+#[derive(Serialize)]
+struct VoiceStateUpdateMessage {
+    guild_id: GuildId,
+    channel_id: Option<ChannelId>,
+    self_mute: bool,
+    self_deaf: bool,
+}
+
 #[derive(Serialize)]
 #[serde(untagged)]
 enum WebSocketMessageData<'a> {
@@ -85,6 +109,10 @@ enum WebSocketMessageData<'a> {
         token: &'a str,
         seq: u64,
     },
+    VoiceStateUpdate(VoiceStateUpdateMessage),
+    RequestSoundboardSounds {
+        guild_ids: &'a [GuildId],
+    },
 }
 
 #[derive(Serialize)]
@@ -93,15 +121,178 @@ struct WebSocketMessage<'a> {
     d: WebSocketMessageData<'a>,
 }
 
-pub struct WsClient(WebSocketStream<MaybeTlsStream<TcpStream>>);
+pub struct WsClient {
+    transport: Transport,
+    #[cfg(feature = "client")]
+    compression: Compression,
+    encoding: GatewayEncoding,
+    /// Whether the decompressed bytes of received payloads are kept, see [`Self::take_payload`].
+    retain_payloads: bool,
+    payload: Option<Vec<u8>>,
+    /// The recorder that received payloads are written to, with the ID of the receiving shard.
+    recorder: Option<(GatewayRecorder, ShardId)>,
+}
+
+/// Where a [`WsClient`] sends messages to and receives messages from.
+// Replays are rare, so the socket is not boxed to keep it inline for the common case.
+#[allow(clippy::large_enum_variant)]
+enum Transport {
+    /// A WebSocket connection to the gateway.
+    Socket(WebSocketStream<MaybeTlsStream<TcpStream>>),
+    /// A recording being replayed, which discards all sent messages.
+    Replay(ShardReplay),
+}
 
 #[cfg(feature = "client")]
 const TIMEOUT: Duration = Duration::from_millis(500);
 #[cfg(feature = "client")]
 const DECOMPRESSION_MULTIPLIER: usize = 3;
+/// The suffix of every complete message sent over a `zlib-stream` connection.
+#[cfg(feature = "client")]
+const ZLIB_SUFFIX: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// The decompression state of a single gateway connection.
+#[cfg(feature = "client")]
+enum Compression {
+    /// Binary messages are not compressed.
+    None,
+    /// Each binary message is a complete zlib stream of its own.
+    Payload,
+    /// All binary messages share one zlib context, and a message may span multiple frames.
+    Zlib {
+        inflater: Box<Decompress>,
+        /// Compressed bytes of the message currently being received.
+        buffer: Vec<u8>,
+    },
+    /// All binary messages share one zstd context, and each message is flushed on its own.
+    #[cfg(feature = "transport_compression_zstd")]
+    Zstd { context: Box<DCtx<'static>> },
+}
+
+#[cfg(feature = "client")]
+impl Compression {
+    fn new(transport: TransportCompression, encoding: GatewayEncoding) -> Self {
+        match transport {
+            // Payload compression would make compressed and uncompressed ETF indistinguishable.
+            TransportCompression::None if encoding == GatewayEncoding::Etf => Self::None,
+            TransportCompression::None => Self::Payload,
+            TransportCompression::Zlib => Self::Zlib {
+                inflater: Box::new(Decompress::new(true)),
+                buffer: Vec::new(),
+            },
+            #[cfg(feature = "transport_compression_zstd")]
+            TransportCompression::Zstd => Self::Zstd {
+                context: Box::new(DCtx::create()),
+            },
+        }
+    }
+
+    /// Decompresses a binary message, returning `None` if the message is not yet complete.
+    fn decompress<'a>(&mut self, bytes: &'a [u8]) -> std::io::Result<Option<Cow<'a, [u8]>>> {
+        let decompressed = match self {
+            Self::None => return Ok(Some(Cow::Borrowed(bytes))),
+            Self::Payload => {
+                let mut decompressed = Vec::with_capacity(bytes.len() * DECOMPRESSION_MULTIPLIER);
+                ZlibDecoder::new(bytes).read_to_end(&mut decompressed)?;
+
+                decompressed
+            },
+            Self::Zlib {
+                inflater,
+                buffer,
+            } => {
+                buffer.extend_from_slice(bytes);
+                if !buffer.ends_with(&ZLIB_SUFFIX) {
+                    return Ok(None);
+                }
+
+                let result = inflate_sync(inflater, buffer);
+                buffer.clear();
+
+                result?
+            },
+            #[cfg(feature = "transport_compression_zstd")]
+            Self::Zstd {
+                context,
+            } => decompress_zstd(context, bytes)?,
+        };
+
+        Ok(Some(Cow::Owned(decompressed)))
+    }
+}
+
+/// Decompresses a flushed message using the connection's shared zstd context.
+#[cfg(all(feature = "client", feature = "transport_compression_zstd"))]
+fn decompress_zstd(context: &mut DCtx<'static>, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decompressed = Vec::with_capacity(bytes.len() * DECOMPRESSION_MULTIPLIER);
+    let mut input = InBuffer::around(bytes);
+
+    loop {
+        if decompressed.len() == decompressed.capacity() {
+            decompressed.reserve(bytes.len());
+        }
+
+        let pos = decompressed.len();
+        let mut output = OutBuffer::around_pos(&mut decompressed, pos);
+        context.decompress_stream(&mut output, &mut input).map_err(|code| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, zstd_safe::get_error_name(code))
+        })?;
+
+        // As with zlib, output may still be held back as long as the output buffer was filled.
+        if input.pos() == bytes.len() && decompressed.len() < decompressed.capacity() {
+            return Ok(decompressed);
+        }
+    }
+}
+
+/// Decodes a decompressed payload in the given encoding.
+#[cfg(feature = "client")]
+pub(crate) fn decode(encoding: GatewayEncoding, bytes: &[u8]) -> Result<GatewayEvent> {
+    match encoding {
+        GatewayEncoding::Json => from_slice(bytes),
+        GatewayEncoding::Etf => Ok(etf::from_slice(bytes).map_err(GatewayError::Etf)?),
+    }
+}
+
+/// Inflates a complete, sync-flushed message using the connection's shared zlib context.
+#[cfg(feature = "client")]
+fn inflate_sync(inflater: &mut Decompress, input: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decompressed = Vec::with_capacity(input.len() * DECOMPRESSION_MULTIPLIER);
+    let mut offset = 0;
+
+    loop {
+        if decompressed.len() == decompressed.capacity() {
+            decompressed.reserve(input.len());
+        }
+
+        let (total_in, total_out) = (inflater.total_in(), inflater.total_out());
+        inflater.decompress_vec(&input[offset..], &mut decompressed, FlushDecompress::Sync)?;
+
+        let consumed = inflater.total_in() - total_in;
+        offset += usize::try_from(consumed).expect("consumed at most input.len() bytes");
+
+        // Stop once all input is consumed without filling the output, as otherwise the inflater
+        // may still be holding back output.
+        if offset == input.len() && decompressed.len() < decompressed.capacity() {
+            return Ok(decompressed);
+        }
+
+        if consumed == 0 && inflater.total_out() == total_out {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "zlib-stream inflater made no progress",
+            ));
+        }
+    }
+}
 
 impl WsClient {
-    pub(crate) async fn connect(url: Url) -> Result<Self> {
+    #[cfg_attr(not(feature = "client"), allow(unused_variables))]
+    pub(crate) async fn connect(
+        url: Url,
+        compression: TransportCompression,
+        encoding: GatewayEncoding,
+    ) -> Result<Self> {
         let config = WebSocketConfig {
             max_message_size: None,
             max_frame_size: None,
@@ -109,41 +300,109 @@ impl WsClient {
         };
         let (stream, _) = connect_async_with_config(url, Some(config), false).await?;
 
-        Ok(Self(stream))
+        Ok(Self {
+            transport: Transport::Socket(stream),
+            #[cfg(feature = "client")]
+            compression: Compression::new(compression, encoding),
+            encoding,
+            retain_payloads: false,
+            payload: None,
+            recorder: None,
+        })
+    }
+
+    /// Creates a client receiving the payloads of a replayed recording instead of connecting to
+    /// the gateway.
+    pub(crate) fn replay(replay: ShardReplay, encoding: GatewayEncoding) -> Self {
+        Self {
+            transport: Transport::Replay(replay),
+            #[cfg(feature = "client")]
+            compression: Compression::new(TransportCompression::None, encoding),
+            encoding,
+            retain_payloads: false,
+            payload: None,
+            recorder: None,
+        }
+    }
+
+    /// Returns the encoding of the payloads sent and received.
+    pub(crate) fn encoding(&self) -> GatewayEncoding {
+        self.encoding
+    }
+
+    /// Sets whether the decompressed bytes of each received payload are kept until the next
+    /// payload is received.
+    pub(crate) fn set_retain_payloads(&mut self, retain_payloads: bool) {
+        self.retain_payloads = retain_payloads;
+        self.payload = None;
+    }
+
+    /// Takes the decompressed bytes of the last received payload, if payloads are retained.
+    pub(crate) fn take_payload(&mut self) -> Option<Vec<u8>> {
+        self.payload.take()
+    }
+
+    /// Sets the recorder that received payloads are written to, as received by the given shard.
+    pub(crate) fn set_recorder(&mut self, recorder: Option<(GatewayRecorder, ShardId)>) {
+        self.recorder = recorder;
     }
 
     #[cfg(feature = "client")]
     pub(crate) async fn recv_json(&mut self) -> Result<Option<GatewayEvent>> {
-        let message = match timeout(TIMEOUT, self.0.next()).await {
-            Ok(Some(Ok(msg))) => msg,
-            Ok(Some(Err(e))) => return Err(e.into()),
-            Ok(None) | Err(_) => return Ok(None),
+        let message = match &mut self.transport {
+            Transport::Socket(stream) => match timeout(TIMEOUT, stream.next()).await {
+                Ok(Some(Ok(msg))) => msg,
+                Ok(Some(Err(e))) => return Err(e.into()),
+                Ok(None) | Err(_) => return Ok(None),
+            },
+            Transport::Replay(replay) => match replay.recv().await {
+                Some(payload) => return self.recv_replayed(payload).map(Some),
+                None => return Ok(None),
+            },
         };
 
+        self.payload = None;
         let value = match message {
             Message::Binary(bytes) => {
-                let mut decompressed =
-                    String::with_capacity(bytes.len() * DECOMPRESSION_MULTIPLIER);
-
-                ZlibDecoder::new(&bytes[..]).read_to_string(&mut decompressed).map_err(|why| {
-                    warn!("Err decompressing bytes: {why:?}");
+                let decompressed = match self.compression.decompress(&bytes) {
+                    Ok(Some(decompressed)) => decompressed,
+                    Ok(None) => return Ok(None),
+                    Err(why) => {
+                        warn!("Err decompressing bytes: {why:?}");
+                        debug!("Failing bytes: {bytes:?}");
+
+                        return Err(why.into());
+                    },
+                };
+
+                self.record(self.encoding, &decompressed);
+                let value = decode(self.encoding, &decompressed).map_err(|why| {
+                    warn!("Err deserializing bytes: {why:?}");
                     debug!("Failing bytes: {bytes:?}");
 
                     why
                 })?;
 
-                from_str(&decompressed).map_err(|why| {
-                    warn!("Err deserializing bytes: {why:?}");
-                    debug!("Failing bytes: {bytes:?}");
+                if self.retain_payloads {
+                    self.payload = Some(decompressed.into_owned());
+                }
 
-                    why
-                })?
+                value
             },
-            Message::Text(payload) => from_str(&payload).map_err(|why| {
-                warn!("Err deserializing text: {why:?}; text: {payload}");
+            Message::Text(payload) => {
+                self.record(GatewayEncoding::Json, payload.as_bytes());
+                let value = from_str(&payload).map_err(|why| {
+                    warn!("Err deserializing text: {why:?}; text: {payload}");
+
+                    why
+                })?;
+
+                if self.retain_payloads {
+                    self.payload = Some(payload.into_bytes());
+                }
 
-                why
-            })?,
+                value
+            },
             Message::Close(Some(frame)) => {
                 return Err(Error::Gateway(GatewayError::Closed(Some(frame))));
             },
@@ -153,30 +412,74 @@ impl WsClient {
         Ok(Some(value))
     }
 
+    /// Decodes a replayed payload, which is always JSON. It is retained in the client's encoding,
+    /// as if it had been received from the gateway.
+    #[cfg(feature = "client")]
+    fn recv_replayed(&mut self, payload: Vec<u8>) -> Result<GatewayEvent> {
+        self.payload = None;
+        let value = from_slice(&payload)?;
+
+        if self.retain_payloads {
+            self.payload = Some(match self.encoding {
+                GatewayEncoding::Json => payload,
+                GatewayEncoding::Etf => {
+                    let json = from_slice::<crate::json::Value>(&payload)?;
+                    etf::to_vec(&json).map_err(GatewayError::Etf)?
+                },
+            });
+        }
+
+        Ok(value)
+    }
+
+    /// Writes a received payload to the recorder, if there is one.
+    #[cfg(feature = "client")]
+    fn record(&self, encoding: GatewayEncoding, payload: &[u8]) {
+        if let Some((recorder, shard_id)) = &self.recorder {
+            if let Err(why) = recorder.record(*shard_id, encoding, payload) {
+                warn!("[{shard_id:?}] Err recording payload: {why:?}");
+            }
+        }
+    }
+
     pub(crate) async fn send_json(&mut self, value: &impl serde::Serialize) -> Result<()> {
-        let message = to_string(value).map(Message::Text)?;
+        let message = match self.encoding {
+            GatewayEncoding::Json => to_string(value).map(Message::Text)?,
+            GatewayEncoding::Etf => {
+                etf::to_vec(value).map(Message::Binary).map_err(GatewayError::Etf)?
+            },
+        };
 
-        self.0.send(message).await?;
+        if let Transport::Socket(stream) = &mut self.transport {
+            stream.send(message).await?;
+        }
         Ok(())
     }
 
-    /// Delegate to `StreamExt::next`
+    /// Delegate to `StreamExt::next`. A replay is closed right away.
     #[cfg(feature = "client")]
     pub(crate) async fn next(&mut self) -> Option<std::result::Result<Message, WsError>> {
-        self.0.next().await
+        match &mut self.transport {
+            Transport::Socket(stream) => stream.next().await,
+            Transport::Replay(_) => Some(Ok(Message::Close(None))),
+        }
     }
 
     /// Delegate to `SinkExt::send`
     #[cfg(feature = "client")]
     pub(crate) async fn send(&mut self, message: Message) -> Result<()> {
-        self.0.send(message).await?;
+        if let Transport::Socket(stream) = &mut self.transport {
+            stream.send(message).await?;
+        }
         Ok(())
     }
 
     /// Delegate to `WebSocketStream::close`
     #[cfg(feature = "client")]
     pub(crate) async fn close(&mut self, msg: Option<CloseFrame<'_>>) -> Result<()> {
-        self.0.close(msg).await?;
+        if let Transport::Socket(stream) = &mut self.transport {
+            WebSocketStream::close(stream, msg).await?;
+        }
         Ok(())
     }
 
@@ -216,6 +519,10 @@ impl WsClient {
     pub async fn send_heartbeat(&mut self, shard_info: &ShardInfo, seq: Option<u64>) -> Result<()> {
         trace!("[{:?}] Sending heartbeat d: {:?}", shard_info, seq);
 
+        if let Transport::Replay(replay) = &self.transport {
+            replay.heartbeat();
+        }
+
         self.send_json(&WebSocketMessage {
             op: Opcode::Heartbeat,
             d: WebSocketMessageData::Heartbeat(seq),
@@ -242,7 +549,7 @@ impl WsClient {
                 token,
                 shard,
                 intents,
-                compress: true,
+                compress: matches!(self.compression, Compression::Payload),
                 large_threshold: constants::LARGE_THRESHOLD,
                 properties: IdentifyProperties {
                     browser: "Discord iOS",
@@ -284,6 +591,46 @@ impl WsClient {
         .await
     }
 
+    #[instrument(skip(self))]
+    pub async fn send_voice_state_update(
+        &mut self,
+        shard_info: &ShardInfo,
+        guild_id: GuildId,
+        channel_id: Option<ChannelId>,
+        self_mute: bool,
+        self_deaf: bool,
+    ) -> Result<()> {
+        debug!("[{:?}] Sending voice state update for guild {}", shard_info, guild_id);
+
+        self.send_json(&WebSocketMessage {
+            op: Opcode::VoiceStateUpdate,
+            d: WebSocketMessageData::VoiceStateUpdate(VoiceStateUpdateMessage {
+                guild_id,
+                channel_id,
+                self_mute,
+                self_deaf,
+            }),
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn send_request_soundboard_sounds(
+        &mut self,
+        shard_info: &ShardInfo,
+        guild_ids: &[GuildId],
+    ) -> Result<()> {
+        debug!("[{:?}] Requesting soundboard sounds", shard_info);
+
+        self.send_json(&WebSocketMessage {
+            op: Opcode::RequestSoundboardSounds,
+            d: WebSocketMessageData::RequestSoundboardSounds {
+                guild_ids,
+            },
+        })
+        .await
+    }
+
     #[instrument(skip(self, token))]
     pub async fn send_resume(
         &mut self,
@@ -305,3 +652,185 @@ impl WsClient {
         .await
     }
 }
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use flate2::{Compress, Compression as Level, FlushCompress};
+
+    use super::{Compression, GatewayEncoding, TransportCompression};
+    use crate::json::from_slice;
+    use crate::model::event::GatewayEvent;
+
+    /// A short session as sent by the gateway: a Hello, a heartbeat ACK, and a session
+    /// invalidation followed by a reconnect request.
+    const SESSION: [&str; 4] = [
+        r#"{"t":null,"s":null,"op":10,"d":{"heartbeat_interval":41250,"_trace":["[\"gateway-prd-us-east1-b-0568\",{\"micros\":0.0}]"]}}"#,
+        r#"{"t":null,"s":null,"op":11,"d":null}"#,
+        r#"{"t":null,"s":null,"op":9,"d":false}"#,
+        r#"{"t":null,"s":null,"op":7,"d":null}"#,
+    ];
+
+    /// The frames of a Hello and a heartbeat ACK in the form the gateway sends them over a
+    /// `zlib-stream` connection: one zlib context at the default level, sync-flushed after each
+    /// message. They were produced with the reference zlib, independently of `flate2`.
+    const ZLIB_STREAM_FIXTURE: [&[u8]; 2] = [
+        &[
+            0x78, 0x9c, 0x34, 0xc9, 0x41, 0x0a, 0xc2, 0x30, 0x10, 0x05, 0xd0, 0xbb, 0xfc, 0x75,
+            0x22, 0x89, 0x54, 0x84, 0xb9, 0x4a, 0x53, 0xca, 0x98, 0x0e, 0x5a, 0x88, 0x6d, 0x49,
+            0xa6, 0x55, 0x09, 0xb9, 0xbb, 0x6e, 0xdc, 0x3d, 0x78, 0x15, 0x0a, 0x5a, 0xf6, 0x94,
+            0x0c, 0xca, 0x1f, 0xeb, 0x06, 0xf2, 0xce, 0x60, 0x02, 0x55, 0x3c, 0x84, 0xb3, 0xde,
+            0x84, 0x75, 0x9c, 0x17, 0x95, 0x7c, 0x70, 0x02, 0x75, 0xfe, 0x7c, 0xf9, 0xfd, 0xa8,
+            0x99, 0xa3, 0x80, 0x7a, 0xf4, 0x01, 0x77, 0x56, 0x79, 0xf1, 0xc7, 0x6e, 0x79, 0xb2,
+            0x7b, 0xb1, 0xc2, 0x45, 0xbd, 0x8d, 0xf6, 0x5a, 0xba, 0x77, 0x80, 0xa9, 0x01, 0xcf,
+            0x39, 0xe6, 0xb5, 0x04, 0x90, 0x3b, 0xb9, 0x36, 0x60, 0x68, 0xed, 0x0b, 0x00, 0x00,
+            0xff, 0xff,
+        ],
+        &[0xaa, 0xc6, 0x65, 0xb7, 0x21, 0xd8, 0x6e, 0x90, 0x40, 0x2d, 0x00, 0x00, 0x00, 0xff, 0xff],
+    ];
+
+    /// The frames of a Hello and a heartbeat ACK in the form the gateway sends them over a
+    /// `zstd-stream` connection: one zstd context at the default level, flushed after each
+    /// message. They were produced with the system libzstd, independently of `zstd-safe`.
+    #[cfg(feature = "transport_compression_zstd")]
+    const ZSTD_STREAM_FIXTURE: [&[u8]; 2] = [
+        &[
+            0x28, 0xb5, 0x2f, 0xfd, 0x00, 0x58, 0x64, 0x03, 0x00, 0x42, 0x07, 0x19, 0x1c, 0x70,
+            0x89, 0x73, 0x74, 0x28, 0xd0, 0xcb, 0x55, 0xff, 0xa7, 0x4a, 0x4b, 0x7e, 0xcb, 0xde,
+            0xe7, 0xff, 0x97, 0x12, 0xd3, 0xd4, 0xab, 0x90, 0x97, 0xd2, 0xff, 0x33, 0x04, 0x31,
+            0x22, 0x47, 0x62, 0x02, 0x24, 0xfd, 0x7a, 0x8d, 0x63, 0xcb, 0xe1, 0x0b, 0xa2, 0x2f,
+            0x85, 0xf1, 0x06, 0xc9, 0x52, 0xf5, 0x5b, 0xce, 0xf7, 0xa4, 0xe2, 0x9c, 0x5c, 0x6c,
+            0x82, 0x7b, 0x0b, 0x5f, 0xc7, 0x0f, 0x3d, 0xb3, 0x75, 0x9d, 0x1c, 0x25, 0x41, 0x40,
+            0x19, 0xfa, 0xb6, 0x08, 0xc7, 0xcd, 0x2c, 0x49, 0x2f, 0x13, 0xda, 0x2d, 0x1b, 0x0e,
+            0xf5, 0xca, 0x51, 0x52, 0x7a, 0xa7, 0x79, 0x47, 0xad, 0x3d, 0x8c, 0xbe, 0x0f, 0x03,
+            0x01, 0x00, 0x24, 0x4a, 0x12,
+        ],
+        &[
+            0x74, 0x00, 0x00, 0x30, 0x31, 0x6e, 0x75, 0x6c, 0x6c, 0x7d, 0x02, 0x00, 0x20, 0xc8,
+            0xbf, 0x04, 0x20,
+        ],
+    ];
+
+    /// Compresses the session with a single zlib context, flushing after every message.
+    fn zlib_frames() -> Vec<Vec<u8>> {
+        let mut deflater = Compress::new(Level::default(), true);
+
+        SESSION
+            .iter()
+            .map(|message| {
+                let mut frame = Vec::with_capacity(message.len() + 64);
+                deflater.compress_vec(message.as_bytes(), &mut frame, FlushCompress::Sync).unwrap();
+                frame
+            })
+            .collect()
+    }
+
+    /// Compresses the session with a single zstd context, flushing after every message.
+    #[cfg(feature = "transport_compression_zstd")]
+    fn zstd_frames() -> Vec<Vec<u8>> {
+        use zstd_safe::zstd_sys::ZSTD_EndDirective;
+        use zstd_safe::{CCtx, InBuffer, OutBuffer};
+
+        let mut context = CCtx::create();
+
+        SESSION
+            .iter()
+            .map(|message| {
+                let mut frame = Vec::with_capacity(message.len() + 64);
+                let mut output = OutBuffer::around(&mut frame);
+                let mut input = InBuffer::around(message.as_bytes());
+                let remaining = context
+                    .compress_stream2(&mut output, &mut input, ZSTD_EndDirective::ZSTD_e_flush)
+                    .unwrap();
+                assert_eq!(remaining, 0);
+                frame
+            })
+            .collect()
+    }
+
+    fn replay(compression: &mut Compression, frames: &[impl AsRef<[u8]>]) -> Vec<GatewayEvent> {
+        frames
+            .iter()
+            .map(|frame| {
+                let decompressed = compression.decompress(frame.as_ref()).unwrap().unwrap();
+                from_slice(&decompressed).unwrap()
+            })
+            .collect()
+    }
+
+    fn assert_session(events: &[GatewayEvent]) {
+        assert!(matches!(events, [
+            GatewayEvent::Hello(41250),
+            GatewayEvent::HeartbeatAck,
+            GatewayEvent::InvalidateSession(false),
+            GatewayEvent::Reconnect,
+        ]));
+    }
+
+    fn assert_fixture(events: &[GatewayEvent]) {
+        assert!(matches!(events, [GatewayEvent::Hello(41250), GatewayEvent::HeartbeatAck]));
+    }
+
+    #[test]
+    fn zlib_stream_replay() {
+        let frames = zlib_frames();
+        assert!(frames.iter().all(|frame| frame.ends_with(&super::ZLIB_SUFFIX)));
+
+        assert_session(&replay(
+            &mut Compression::new(TransportCompression::Zlib, GatewayEncoding::Json),
+            &frames,
+        ));
+
+        // Later messages reference earlier ones, so they only inflate with the shared context.
+        let mut fresh = Compression::new(TransportCompression::Zlib, GatewayEncoding::Json);
+        assert!(fresh.decompress(&frames[1]).is_err());
+
+        assert_fixture(&replay(
+            &mut Compression::new(TransportCompression::Zlib, GatewayEncoding::Json),
+            &ZLIB_STREAM_FIXTURE,
+        ));
+    }
+
+    #[test]
+    fn zlib_stream_message_split_across_frames() {
+        let frames = zlib_frames();
+        let mut compression = Compression::new(TransportCompression::Zlib, GatewayEncoding::Json);
+
+        let (head, tail) = frames[0].split_at(frames[0].len() / 2);
+        assert_eq!(compression.decompress(head).unwrap(), None);
+        assert_eq!(compression.decompress(tail).unwrap().unwrap(), SESSION[0].as_bytes());
+        assert_eq!(compression.decompress(&frames[1]).unwrap().unwrap(), SESSION[1].as_bytes());
+    }
+
+    #[test]
+    fn zlib_stream_stall_is_an_error() {
+        // A finished stream followed by more data leaves the inflater unable to make progress.
+        let mut deflater = Compress::new(Level::default(), true);
+        let mut frame = Vec::with_capacity(SESSION[0].len() + 64);
+        deflater.compress_vec(SESSION[0].as_bytes(), &mut frame, FlushCompress::Finish).unwrap();
+        frame.extend_from_slice(&super::ZLIB_SUFFIX);
+
+        let mut compression = Compression::new(TransportCompression::Zlib, GatewayEncoding::Json);
+        let error = compression.decompress(&frame).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("no progress"), "{error}");
+    }
+
+    #[test]
+    #[cfg(feature = "transport_compression_zstd")]
+    fn zstd_stream_replay() {
+        let frames = zstd_frames();
+
+        assert_session(&replay(
+            &mut Compression::new(TransportCompression::Zstd, GatewayEncoding::Json),
+            &frames,
+        ));
+
+        let mut fresh = Compression::new(TransportCompression::Zstd, GatewayEncoding::Json);
+        assert!(fresh.decompress(&frames[1]).is_err());
+
+        assert_fixture(&replay(
+            &mut Compression::new(TransportCompression::Zstd, GatewayEncoding::Json),
+            &ZSTD_STREAM_FIXTURE,
+        ));
+    }
+}
@@ -15,6 +15,7 @@ use serde::de::DeserializeOwned;
 use tracing::{debug, instrument, warn};
 
 use super::multipart::{Multipart, MultipartUpload};
+use super::ratelimit_backend::RatelimitBackend;
 use super::ratelimiting::Ratelimiter;
 use super::request::Request;
 use super::routing::Route;
@@ -33,6 +34,11 @@ use crate::internal::prelude::*;
 use crate::json::*;
 use crate::model::prelude::*;
 
+/// The URL of Discord, which API requests and webhook URLs are on.
+const DISCORD_URL: &str = "https://discord.com";
+/// The URLs of Discord's CDN.
+const CDN_URLS: [&str; 2] = ["https://cdn.discordapp.com", "https://media.discordapp.net"];
+
 /// A builder for the underlying [`Http`] client that performs requests to Discord's HTTP API
 ///
 /// If you do not need to use a proxy or do not need to disable the rate limiter, you can use
@@ -53,9 +59,11 @@ use crate::model::prelude::*;
 pub struct HttpBuilder {
     client: Option<Client>,
     ratelimiter: Option<Ratelimiter>,
+    ratelimit_backend: Option<Arc<dyn RatelimitBackend>>,
     ratelimiter_disabled: bool,
     token: SecretString,
     proxy: Option<String>,
+    base_url: Option<String>,
     application_id: Option<ApplicationId>,
     default_allowed_mentions: Option<CreateAllowedMentions>,
 }
@@ -67,9 +75,11 @@ impl HttpBuilder {
         Self {
             client: None,
             ratelimiter: None,
+            ratelimit_backend: None,
             ratelimiter_disabled: false,
             token: SecretString::new(parse_token(token)),
             proxy: None,
+            base_url: None,
             application_id: None,
             default_allowed_mentions: None,
         }
@@ -100,6 +110,16 @@ impl HttpBuilder {
         self
     }
 
+    /// Sets the backend keeping the ratelimits of the ratelimiter, for example to share them with
+    /// other processes using the same token. If one isn't provided, ratelimits are kept in memory.
+    ///
+    /// This applies to the ratelimiter set with [`Self::ratelimiter`] as well. See
+    /// [`RatelimitBackend`] for details.
+    pub fn ratelimit_backend(mut self, backend: Arc<dyn RatelimitBackend>) -> Self {
+        self.ratelimit_backend = Some(backend);
+        self
+    }
+
     /// Sets whether or not the ratelimiter is disabled. By default if this this not used, it is
     /// enabled. In most cases, this should be used in conjunction with [`Self::proxy`].
     ///
@@ -130,6 +150,19 @@ impl HttpBuilder {
         self
     }
 
+    /// Sets the base URL that the client talks to in place of Discord, e.g.
+    /// `http://127.0.0.1:8080`, for example to run integration tests against a mock server.
+    ///
+    /// API requests are sent to the base URL instead of `https://discord.com`, unless a proxy is
+    /// set with [`Self::proxy`] as well. Webhook URLs on the base URL are accepted by
+    /// [`Http::get_webhook_from_url`], and files downloaded through the client from Discord's CDN,
+    /// such as with [`CreateAttachment::url`], are fetched from the base URL too, with the same
+    /// path.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
     /// Sets the [`CreateAllowedMentions`] used by default for each request that would use it.
     ///
     /// This only takes effect if you are calling through the model or builder methods, not directly
@@ -150,14 +183,24 @@ impl HttpBuilder {
         });
 
         let ratelimiter = (!self.ratelimiter_disabled).then(|| {
-            self.ratelimiter
-                .unwrap_or_else(|| Ratelimiter::new(client.clone(), self.token.expose_secret()))
+            let mut ratelimiter = self
+                .ratelimiter
+                .unwrap_or_else(|| Ratelimiter::new(client.clone(), self.token.expose_secret()));
+            if let Some(backend) = self.ratelimit_backend {
+                ratelimiter.set_backend(backend);
+            }
+            if let Some(api_url) = self.proxy.as_ref().or(self.base_url.as_ref()) {
+                ratelimiter.set_api_url(Some(api_url.clone()));
+            }
+
+            ratelimiter
         });
 
         Http {
             client,
             ratelimiter,
             proxy: self.proxy,
+            base_url: self.base_url,
             token: self.token,
             application_id,
             default_allowed_mentions: self.default_allowed_mentions,
@@ -165,6 +208,16 @@ impl HttpBuilder {
     }
 }
 
+/// Replaces the given prefix of a URL, if it starts with it, ignoring trailing slashes.
+fn replace_prefix(url: &str, prefix: &str, replacement: &str) -> Option<String> {
+    let path = url.strip_prefix(prefix.trim_end_matches('/'))?;
+    if !path.is_empty() && !path.starts_with(['/', '?']) {
+        return None;
+    }
+
+    Some(format!("{}{path}", replacement.trim_end_matches('/')))
+}
+
 fn parse_token(token: impl AsRef<str>) -> String {
     let token = token.as_ref().trim();
 
@@ -196,7 +249,8 @@ fn reason_into_header(reason: &str) -> Headers {
 pub struct Http {
     pub(crate) client: Client,
     pub ratelimiter: Option<Ratelimiter>,
-    pub proxy: Option<String>,
+    proxy: Option<String>,
+    base_url: Option<String>,
     token: SecretString,
     application_id: AtomicU64,
     pub default_allowed_mentions: Option<CreateAllowedMentions>,
@@ -225,6 +279,40 @@ impl Http {
         self.token.expose_secret()
     }
 
+    /// Returns the proxy that API requests are sent to, if set with [`HttpBuilder::proxy`].
+    pub fn proxy(&self) -> Option<&str> {
+        self.proxy.as_deref()
+    }
+
+    /// Returns the URL that Discord's URLs are rewritten to, if set with
+    /// [`HttpBuilder::base_url`].
+    pub fn base_url(&self) -> Option<&str> {
+        self.base_url.as_deref()
+    }
+
+    /// Returns the URL that API requests are sent to in place of `https://discord.com`, if any.
+    fn api_url(&self) -> Option<&str> {
+        self.proxy.as_deref().or(self.base_url.as_deref())
+    }
+
+    /// Rewrites a URL on Discord's CDN to the base URL, if one is set.
+    pub(crate) fn cdn_url<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        let Some(base_url) = &self.base_url else { return Cow::Borrowed(url) };
+
+        CDN_URLS
+            .iter()
+            .find_map(|cdn_url| replace_prefix(url, cdn_url, base_url))
+            .map_or(Cow::Borrowed(url), Cow::Owned)
+    }
+
+    /// Rewrites a URL on the base URL, if one is set, to the Discord URL it stands in for.
+    fn discord_url<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        self.base_url
+            .as_deref()
+            .and_then(|base_url| replace_prefix(url, base_url, DISCORD_URL))
+            .map_or(Cow::Borrowed(url), Cow::Owned)
+    }
+
     /// Adds a [`User`] to a [`Guild`] with a valid OAuth2 access token.
     ///
     /// Returns the created [`Member`] object, or nothing if the user is already a guild member.
@@ -4584,7 +4672,7 @@ impl Http {
     /// ```
     #[cfg(feature = "utils")]
     pub async fn get_webhook_from_url(&self, url: &str) -> Result<Webhook> {
-        let url = Url::parse(url).map_err(HttpError::Url)?;
+        let url = Url::parse(&self.discord_url(url)).map_err(HttpError::Url)?;
         let (webhook_id, token) =
             crate::utils::parse_webhook(&url).ok_or(HttpError::InvalidWebhook)?;
         self.fire(Request {
@@ -4956,7 +5044,7 @@ impl Http {
         let response = if let Some(ratelimiter) = &self.ratelimiter {
             ratelimiter.perform(req).await?
         } else {
-            let request = req.build(&self.client, self.token(), self.proxy.as_deref())?.build()?;
+            let request = req.build(&self.client, self.token(), self.api_url())?.build()?;
             self.client.execute(request).await?
         };
 
@@ -5011,3 +5099,31 @@ impl AsRef<Http> for Http {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::HttpBuilder;
+
+    #[test]
+    fn base_url_rewrites_discord_urls() {
+        let http = HttpBuilder::new("token").base_url("http://127.0.0.1:8080/").build();
+
+        assert_eq!(http.api_url(), Some("http://127.0.0.1:8080/"));
+        assert_eq!(
+            http.cdn_url("https://cdn.discordapp.com/avatars/1/a.png?size=1024"),
+            "http://127.0.0.1:8080/avatars/1/a.png?size=1024"
+        );
+        assert_eq!(
+            http.cdn_url("https://cdn.discordapp.com.evil"),
+            "https://cdn.discordapp.com.evil"
+        );
+        assert_eq!(
+            http.discord_url("http://127.0.0.1:8080/api/webhooks/1/token"),
+            "https://discord.com/api/webhooks/1/token"
+        );
+        assert_eq!(http.discord_url("https://example.com/a"), "https://example.com/a");
+
+        let http = HttpBuilder::new("token").proxy("http://proxy").base_url("http://mock").build();
+        assert_eq!(http.api_url(), Some("http://proxy"));
+    }
+}
@@ -80,6 +80,11 @@ pub enum HttpError {
     InvalidPort,
     /// When an application id was expected but missing.
     ApplicationIdMissing,
+    /// When a request was not sent because the limit of invalid requests set on the
+    /// [`Ratelimiter`] was reached.
+    ///
+    /// [`Ratelimiter`]: super::Ratelimiter
+    InvalidRequestLimit,
 }
 
 impl HttpError {
@@ -168,6 +173,7 @@ impl fmt::Display for HttpError {
             Self::InvalidScheme => f.write_str("Invalid Url scheme."),
             Self::InvalidPort => f.write_str("Invalid port."),
             Self::ApplicationIdMissing => f.write_str("Application id was expected but missing."),
+            Self::InvalidRequestLimit => f.write_str("Invalid request limit was reached."),
         }
     }
 }
@@ -22,6 +22,7 @@
 mod client;
 mod error;
 mod multipart;
+mod ratelimit_backend;
 mod ratelimiting;
 mod request;
 mod routing;
@@ -35,6 +36,7 @@ pub use reqwest::StatusCode;
 pub use self::client::*;
 pub use self::error::*;
 pub use self::multipart::*;
+pub use self::ratelimit_backend::*;
 pub use self::ratelimiting::*;
 pub use self::request::*;
 pub use self::routing::*;
@@ -0,0 +1,749 @@
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::io::{Error as IoError, ErrorKind};
+#[cfg(unix)]
+use std::num::NonZeroU64;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, Weak};
+use std::time::{Duration, Instant, SystemTime};
+
+use async_trait::async_trait;
+#[cfg(unix)]
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{Mutex, RwLock};
+use tokio::time::sleep;
+use tracing::debug;
+#[cfg(unix)]
+use tracing::warn;
+
+use super::{
+    BucketDelay,
+    BucketKey,
+    BucketStats,
+    LightMethod,
+    Ratelimit,
+    RatelimitHeaders,
+    RatelimitingBucket,
+};
+use crate::internal::prelude::*;
+use crate::internal::tokio::spawn_named;
+#[cfg(unix)]
+use crate::json::{from_str, to_vec};
+
+/// How often stale buckets are evicted.
+const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Keeps the ratelimits of buckets and the global ratelimit for a [`Ratelimiter`].
+///
+/// Set on a ratelimiter with [`Ratelimiter::set_backend`], or on the HTTP client with
+/// [`HttpBuilder::ratelimit_backend`]. The [`InMemoryBackend`] is used by default. Backends storing
+/// the ratelimits outside of the process, such as the [`UnixSocketBackend`], let several
+/// processes using the same token share them. Such backends can identify buckets by the
+/// [`Display`] string of their [`BucketKey`], and keep a [`Ratelimit`] for each.
+///
+/// Requests are only delayed by what the backend returns; the [`Ratelimiter`] does the waiting.
+///
+/// [`Ratelimiter`]: super::Ratelimiter
+/// [`Ratelimiter::set_backend`]: super::Ratelimiter::set_backend
+/// [`HttpBuilder::ratelimit_backend`]: super::HttpBuilder::ratelimit_backend
+/// [`Display`]: std::fmt::Display
+#[async_trait]
+pub trait RatelimitBackend: Send + Sync {
+    /// Counts a request towards the bucket with the given key if the bucket allows another one, or
+    /// returns how long to wait until it resets otherwise, after which this is called again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ratelimit could not be accessed, which fails the request.
+    async fn pre_hook(&self, key: &BucketKey) -> Result<Option<BucketDelay>>;
+
+    /// Updates the bucket with the given key from the headers of a response.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ratelimit could not be accessed. The error is logged, as the
+    /// response was already received.
+    async fn post_hook(&self, key: &BucketKey, headers: &RatelimitHeaders) -> Result<()>;
+
+    /// Returns how long requests must wait until the global ratelimit is over, if it was reached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the global ratelimit could not be accessed, which fails the request.
+    async fn global_delay(&self) -> Result<Option<Duration>>;
+
+    /// Blocks all requests for the given duration, as Discord reported that the global ratelimit
+    /// was reached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the global ratelimit could not be accessed. The error is logged, and
+    /// the request is still retried once the duration has passed.
+    async fn lock_global(&self, duration: Duration) -> Result<()>;
+
+    /// Called when Discord reported the bucket of a route for the first time, so that the
+    /// ratelimits kept for the route with [`BucketKey::Route`] keys can be moved to
+    /// [`BucketKey::Bucket`] keys. Does nothing by default.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ratelimits could not be accessed. The error is logged, as the
+    /// response was already received.
+    async fn learn_bucket(
+        &self,
+        _bucket: RatelimitingBucket,
+        _method: LightMethod,
+        _hash: &str,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the state of every bucket, as listed by [`Ratelimiter::stats`]. Returns no buckets
+    /// by default.
+    ///
+    /// [`Ratelimiter::stats`]: super::Ratelimiter::stats
+    async fn buckets(&self) -> HashMap<BucketKey, BucketStats> {
+        HashMap::new()
+    }
+}
+
+/// The [`RatelimitBackend`] keeping ratelimits in memory, used by default.
+///
+/// Buckets that have reset and have not been used for 10 minutes are evicted in the background.
+#[derive(Debug, Default)]
+pub struct InMemoryBackend {
+    routes: Arc<RwLock<HashMap<BucketKey, Arc<Mutex<Ratelimit>>>>>,
+    /// When the global ratelimit that Discord reported is over.
+    global_until: StdMutex<Option<Instant>>,
+    /// Whether the task evicting stale buckets was started, which happens on the first request.
+    eviction_started: AtomicBool,
+}
+
+impl InMemoryBackend {
+    /// Creates a backend without any ratelimits.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The routes mutex is a HashMap of each [`BucketKey`] and their respective ratelimit
+    /// information.
+    ///
+    /// See the documentation for [`Ratelimit`] for more information on how the library handles
+    /// ratelimiting.
+    ///
+    /// # Examples
+    ///
+    /// View the `reset` time of the bucket of `GET` requests for `ChannelsId(7)`:
+    ///
+    /// ```rust,no_run
+    /// use std::sync::Arc;
+    ///
+    /// use serenity::http::{HttpBuilder, InMemoryBackend, LightMethod, Route};
+    /// # use serenity::model::prelude::*;
+    ///
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let backend = Arc::new(InMemoryBackend::new());
+    /// let http = HttpBuilder::new("token").ratelimit_backend(backend.clone()).build();
+    /// let ratelimiter = http.ratelimiter.as_ref().unwrap();
+    ///
+    /// let channel_id = ChannelId::new(7);
+    /// let route = Route::Channel {
+    ///     channel_id,
+    /// };
+    /// let key = ratelimiter.bucket_key(route.ratelimiting_bucket(), LightMethod::Get).await;
+    /// if let Some(route) = backend.routes().read().await.get(&key) {
+    ///     if let Some(reset) = route.lock().await.reset() {
+    ///         println!("Reset time at: {:?}", reset);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn routes(&self) -> Arc<RwLock<HashMap<BucketKey, Arc<Mutex<Ratelimit>>>>> {
+        Arc::clone(&self.routes)
+    }
+
+    async fn ratelimit(&self, key: &BucketKey) -> Arc<Mutex<Ratelimit>> {
+        if let Some(ratelimit) = self.routes.read().await.get(key) {
+            return Arc::clone(ratelimit);
+        }
+
+        Arc::clone(self.routes.write().await.entry(key.clone()).or_default())
+    }
+
+    /// Starts evicting stale buckets in the background, unless that was done already.
+    ///
+    /// This is deferred to the first request, as the backend may be created outside of a runtime.
+    /// The task stops once the backend is dropped.
+    fn start_eviction(&self) {
+        if self.eviction_started.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let routes = Arc::downgrade(&self.routes);
+        spawn_named("ratelimiter::evict_buckets", async move {
+            loop {
+                sleep(EVICTION_INTERVAL).await;
+
+                let Some(routes) = Weak::upgrade(&routes) else { break };
+                evict_stale_buckets(&routes, Instant::now(), SystemTime::now()).await;
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl RatelimitBackend for InMemoryBackend {
+    async fn pre_hook(&self, key: &BucketKey) -> Result<Option<BucketDelay>> {
+        self.start_eviction();
+
+        let ratelimit = self.ratelimit(key).await;
+        let delay = ratelimit.lock().await.pre_hook(SystemTime::now());
+        Ok(delay)
+    }
+
+    async fn post_hook(&self, key: &BucketKey, headers: &RatelimitHeaders) -> Result<()> {
+        self.ratelimit(key).await.lock().await.post_hook(headers);
+        Ok(())
+    }
+
+    async fn global_delay(&self) -> Result<Option<Duration>> {
+        let global_until = self.global_until.lock().expect("global ratelimit poisoned");
+        Ok(global_delay(*global_until, Instant::now()))
+    }
+
+    async fn lock_global(&self, duration: Duration) -> Result<()> {
+        let mut global_until = self.global_until.lock().expect("global ratelimit poisoned");
+        lock_global(&mut global_until, Instant::now() + duration);
+        Ok(())
+    }
+
+    /// Moves the ratelimits tracked for the route so far to the bucket, unless the bucket already
+    /// has a ratelimit for the same major parameter through another route.
+    async fn learn_bucket(
+        &self,
+        bucket: RatelimitingBucket,
+        method: LightMethod,
+        hash: &str,
+    ) -> Result<()> {
+        let route = bucket.without_major_parameter();
+        let mut routes = self.routes.write().await;
+        let learned = routes
+            .keys()
+            .filter_map(|key| match key {
+                BucketKey::Route(other, other_method)
+                    if *other_method == method && other.without_major_parameter() == route =>
+                {
+                    Some((key.clone(), other.major_parameter()))
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        for (key, major_parameter) in learned {
+            if let Some(ratelimit) = routes.remove(&key) {
+                let key = BucketKey::Bucket(hash.to_string(), major_parameter);
+                routes.entry(key).or_insert(ratelimit);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn buckets(&self) -> HashMap<BucketKey, BucketStats> {
+        let routes = self.routes.read().await;
+        let mut buckets = HashMap::with_capacity(routes.len());
+        for (key, ratelimit) in routes.iter() {
+            buckets.insert(key.clone(), ratelimit.lock().await.stats());
+        }
+
+        buckets
+    }
+}
+
+/// Removes the buckets that have reset and have not been used for a while.
+///
+/// Buckets that are in use by a request are kept, as their state would be lost otherwise.
+async fn evict_stale_buckets(
+    routes: &RwLock<HashMap<BucketKey, Arc<Mutex<Ratelimit>>>>,
+    now: Instant,
+    system_now: SystemTime,
+) {
+    let mut routes = routes.write().await;
+    let before = routes.len();
+    routes.retain(|_, ratelimit| {
+        Arc::strong_count(ratelimit) > 1
+            || ratelimit.try_lock().map_or(true, |ratelimit| !ratelimit.is_stale(now, system_now))
+    });
+
+    if routes.len() < before {
+        debug!("Evicted {} stale ratelimit buckets", before - routes.len());
+    }
+}
+
+/// Returns how long is left until the global ratelimit is over, if it is locked.
+fn global_delay(global_until: Option<Instant>, now: Instant) -> Option<Duration> {
+    global_until.map(|until| until.saturating_duration_since(now)).filter(|delay| !delay.is_zero())
+}
+
+/// Locks the global ratelimit until the given instant, unless it is locked for longer already.
+fn lock_global(global_until: &mut Option<Instant>, until: Instant) {
+    if global_until.map_or(true, |locked_until| locked_until < until) {
+        *global_until = Some(until);
+    }
+}
+
+/// A [`RatelimitBackend`] keeping ratelimits in a server listening on a Unix domain socket, to
+/// share them between processes on the same host using the same token.
+///
+/// One of the processes, or a separate one, runs the server with [`Self::serve`], and every
+/// process sets a backend connecting to it. Each call is sent to the server as a line of JSON, and
+/// answered the same way. The socket is connected to on the first request, and connected to again
+/// after a call failed; requests fail while the server is unreachable, unless they were already
+/// sent.
+///
+/// The buckets listed by [`Ratelimiter::stats`] include the buckets of all processes, except for
+/// the routes whose Discord bucket is not known yet, which are only listed by the processes that
+/// made requests to them.
+///
+/// [`Ratelimiter::stats`]: super::Ratelimiter::stats
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use serenity::http::{HttpBuilder, UnixSocketBackend};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// // In the process keeping the ratelimits:
+/// tokio::spawn(UnixSocketBackend::serve("/tmp/ratelimits.sock"));
+///
+/// // In every process making requests:
+/// let token = std::env::var("DISCORD_TOKEN")?;
+/// let backend = Arc::new(UnixSocketBackend::new("/tmp/ratelimits.sock"));
+/// let http = HttpBuilder::new(token).ratelimit_backend(backend).build();
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixSocketBackend {
+    path: PathBuf,
+    stream: Mutex<Option<BufReader<UnixStream>>>,
+    /// The keys of the routes requests were made to, as they can't be parsed from the keys the
+    /// server lists.
+    routes: StdMutex<HashMap<SharedKey, BucketKey>>,
+}
+
+#[cfg(unix)]
+impl UnixSocketBackend {
+    /// Creates a backend connecting to the server listening on the socket at the given path.
+    #[must_use]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            stream: Mutex::new(None),
+            routes: StdMutex::default(),
+        }
+    }
+
+    /// Keeps ratelimits for the backends connecting to the socket at the given path, until the
+    /// returned future is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the socket could not be bound, or if accepting a connection
+    /// failed.
+    pub async fn serve(path: impl AsRef<Path>) -> Result<()> {
+        let listener = UnixListener::bind(path)?;
+        let ratelimits = Arc::new(StdMutex::new(SharedRatelimits::default()));
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let ratelimits = Arc::clone(&ratelimits);
+            spawn_named("ratelimiter::serve_connection", async move {
+                if let Err(why) = serve_connection(stream, &ratelimits).await {
+                    warn!("Ratelimit backend connection failed: {:?}", why);
+                }
+            });
+        }
+    }
+
+    async fn call(&self, request: &BackendRequest) -> Result<BackendResponse> {
+        let mut stream = self.stream.lock().await;
+        let connected = match &mut *stream {
+            Some(connected) => connected,
+            None => stream.insert(BufReader::new(UnixStream::connect(&self.path).await?)),
+        };
+
+        let response = call(connected, request).await;
+        if response.is_err() {
+            *stream = None;
+        }
+
+        response
+    }
+
+    /// Returns the key that the server keeps the ratelimit of a bucket by, remembering the keys
+    /// of routes to list them by.
+    fn shared_key(&self, key: &BucketKey) -> SharedKey {
+        let shared_key = SharedKey::from(key);
+        if shared_key.route {
+            let mut routes = self.routes.lock().expect("routes poisoned");
+            routes.entry(shared_key.clone()).or_insert_with(|| key.clone());
+        }
+
+        shared_key
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl RatelimitBackend for UnixSocketBackend {
+    async fn pre_hook(&self, key: &BucketKey) -> Result<Option<BucketDelay>> {
+        match self
+            .call(&BackendRequest::PreHook {
+                key: self.shared_key(key),
+            })
+            .await?
+        {
+            BackendResponse::Delay(delay) => Ok(delay),
+            _ => Err(Error::Other("unexpected ratelimit backend response")),
+        }
+    }
+
+    async fn post_hook(&self, key: &BucketKey, headers: &RatelimitHeaders) -> Result<()> {
+        self.call(&BackendRequest::PostHook {
+            key: self.shared_key(key),
+            headers: headers.clone(),
+        })
+        .await
+        .map(drop)
+    }
+
+    async fn global_delay(&self) -> Result<Option<Duration>> {
+        match self.call(&BackendRequest::GlobalDelay).await? {
+            BackendResponse::GlobalDelay(delay) => Ok(delay),
+            _ => Err(Error::Other("unexpected ratelimit backend response")),
+        }
+    }
+
+    async fn lock_global(&self, duration: Duration) -> Result<()> {
+        self.call(&BackendRequest::LockGlobal {
+            duration,
+        })
+        .await
+        .map(drop)
+    }
+
+    async fn learn_bucket(
+        &self,
+        bucket: RatelimitingBucket,
+        method: LightMethod,
+        hash: &str,
+    ) -> Result<()> {
+        self.call(&BackendRequest::LearnBucket {
+            route: SharedKey::from(&BucketKey::Route(bucket, method)).bucket,
+            hash: hash.to_string(),
+        })
+        .await
+        .map(drop)
+    }
+
+    async fn buckets(&self) -> HashMap<BucketKey, BucketStats> {
+        let buckets = match self.call(&BackendRequest::Buckets).await {
+            Ok(BackendResponse::Buckets(buckets)) => buckets,
+            Ok(_) => {
+                warn!("Unexpected ratelimit backend response to listing buckets");
+                return HashMap::new();
+            },
+            Err(why) => {
+                warn!("Err listing ratelimit buckets: {:?}", why);
+                return HashMap::new();
+            },
+        };
+
+        let routes = self.routes.lock().expect("routes poisoned");
+        buckets
+            .into_iter()
+            .filter_map(|(key, stats)| {
+                let key = if key.route {
+                    routes.get(&key)?.clone()
+                } else {
+                    BucketKey::Bucket(key.bucket, key.major_parameter)
+                };
+
+                Some((key, stats))
+            })
+            .collect()
+    }
+}
+
+/// A [`BucketKey`] as sent to the server of [`UnixSocketBackend`]s, with the major parameter of
+/// routes split off so that the server can move their ratelimits to the bucket they are in.
+#[cfg(unix)]
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+struct SharedKey {
+    /// The hash of the Discord bucket, or the method and route without its major parameter.
+    bucket: String,
+    /// Whether the Discord bucket of the route is not known yet.
+    route: bool,
+    major_parameter: Option<NonZeroU64>,
+}
+
+#[cfg(unix)]
+impl From<&BucketKey> for SharedKey {
+    fn from(key: &BucketKey) -> Self {
+        match key {
+            BucketKey::Route(bucket, method) => Self {
+                bucket: BucketKey::Route(bucket.without_major_parameter(), *method).to_string(),
+                route: true,
+                major_parameter: bucket.major_parameter(),
+            },
+            BucketKey::Bucket(hash, major_parameter) => Self {
+                bucket: hash.clone(),
+                route: false,
+                major_parameter: *major_parameter,
+            },
+        }
+    }
+}
+
+/// A call of a [`UnixSocketBackend`] to its server.
+#[cfg(unix)]
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum BackendRequest {
+    PreHook { key: SharedKey },
+    PostHook { key: SharedKey, headers: RatelimitHeaders },
+    GlobalDelay,
+    LockGlobal { duration: Duration },
+    LearnBucket { route: String, hash: String },
+    Buckets,
+}
+
+/// The answer of the server to a call of a [`UnixSocketBackend`].
+#[cfg(unix)]
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BackendResponse {
+    Delay(Option<BucketDelay>),
+    GlobalDelay(Option<Duration>),
+    Buckets(Vec<(SharedKey, BucketStats)>),
+    Done,
+}
+
+/// The ratelimits kept by the server of [`UnixSocketBackend`]s.
+#[cfg(unix)]
+#[derive(Debug, Default)]
+struct SharedRatelimits {
+    buckets: HashMap<SharedKey, Ratelimit>,
+    global_until: Option<Instant>,
+    evicted_at: Option<Instant>,
+}
+
+#[cfg(unix)]
+impl SharedRatelimits {
+    fn handle(&mut self, request: BackendRequest, now: Instant) -> BackendResponse {
+        if self.evicted_at.map_or(true, |at| now.saturating_duration_since(at) >= EVICTION_INTERVAL)
+        {
+            let system_now = SystemTime::now();
+            self.buckets.retain(|_, ratelimit| !ratelimit.is_stale(now, system_now));
+            self.evicted_at = Some(now);
+        }
+
+        match request {
+            BackendRequest::PreHook {
+                key,
+            } => BackendResponse::Delay(
+                self.buckets.entry(key).or_default().pre_hook(SystemTime::now()),
+            ),
+            BackendRequest::PostHook {
+                key,
+                headers,
+            } => {
+                self.buckets.entry(key).or_default().post_hook(&headers);
+                BackendResponse::Done
+            },
+            BackendRequest::GlobalDelay => {
+                BackendResponse::GlobalDelay(global_delay(self.global_until, now))
+            },
+            BackendRequest::LockGlobal {
+                duration,
+            } => {
+                lock_global(&mut self.global_until, now + duration);
+                BackendResponse::Done
+            },
+            BackendRequest::LearnBucket {
+                route,
+                hash,
+            } => {
+                self.learn_bucket(&route, &hash);
+                BackendResponse::Done
+            },
+            BackendRequest::Buckets => BackendResponse::Buckets(
+                self.buckets
+                    .iter()
+                    .map(|(key, ratelimit)| (key.clone(), ratelimit.stats()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Moves the ratelimits kept for the route so far to the bucket, like the
+    /// [`InMemoryBackend`] does.
+    fn learn_bucket(&mut self, route: &str, hash: &str) {
+        let learned = self
+            .buckets
+            .keys()
+            .filter(|key| key.route && key.bucket == route)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for key in learned {
+            if let Some(ratelimit) = self.buckets.remove(&key) {
+                let bucket = SharedKey {
+                    bucket: hash.to_string(),
+                    route: false,
+                    major_parameter: key.major_parameter,
+                };
+                self.buckets.entry(bucket).or_insert(ratelimit);
+            }
+        }
+    }
+}
+
+/// Sends a call to the server and reads its answer.
+#[cfg(unix)]
+async fn call(
+    stream: &mut BufReader<UnixStream>,
+    request: &BackendRequest,
+) -> Result<BackendResponse> {
+    let mut line = to_vec(request)?;
+    line.push(b'\n');
+    stream.get_mut().write_all(&line).await?;
+
+    let mut response = String::new();
+    if stream.read_line(&mut response).await? == 0 {
+        return Err(IoError::from(ErrorKind::UnexpectedEof).into());
+    }
+
+    from_str(response)
+}
+
+/// Answers the calls of a single [`UnixSocketBackend`] until it disconnects.
+#[cfg(unix)]
+async fn serve_connection(
+    stream: UnixStream,
+    ratelimits: &StdMutex<SharedRatelimits>,
+) -> Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+
+    while stream.read_line(&mut line).await? != 0 {
+        let request = from_str(std::mem::take(&mut line))?;
+        let response = {
+            let mut ratelimits = ratelimits.lock().expect("shared ratelimits poisoned");
+            ratelimits.handle(request, Instant::now())
+        };
+
+        let mut response = to_vec(&response)?;
+        response.push(b'\n');
+        stream.get_mut().write_all(&response).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant, SystemTime};
+
+    use super::{evict_stale_buckets, InMemoryBackend, RatelimitBackend};
+    use crate::http::{BucketKey, LightMethod, Ratelimit, RatelimitHeaders, Route};
+    use crate::model::id::ChannelId;
+
+    #[tokio::test]
+    async fn evicts_stale_buckets() {
+        let backend = InMemoryBackend::new();
+        let now = Instant::now() + Duration::from_secs(3600);
+        let system_now = SystemTime::now();
+        let key = |id: &str| BucketKey::Bucket(id.to_string(), None);
+
+        let mut not_reset = Ratelimit::default();
+        not_reset.post_hook(&RatelimitHeaders {
+            reset: Some(system_now + Duration::from_secs(5)),
+            ..RatelimitHeaders::default()
+        });
+        let in_use = Arc::new(Ratelimit::default().into());
+
+        let mut routes = backend.routes.write().await;
+        routes.insert(key("idle"), Arc::default());
+        routes.insert(key("not_reset"), Arc::new(not_reset.into()));
+        routes.insert(key("in_use"), Arc::clone(&in_use));
+        drop(routes);
+
+        evict_stale_buckets(&backend.routes, now, system_now).await;
+
+        let buckets = backend.buckets().await;
+        assert_eq!(buckets.len(), 2);
+        assert!(!buckets.contains_key(&key("idle")));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket_backend_shares_ratelimits() {
+        use super::UnixSocketBackend;
+
+        let path = std::env::temp_dir().join(format!("serenity-ratelimits-{}", std::process::id()));
+        drop(std::fs::remove_file(&path));
+        let server = tokio::spawn(UnixSocketBackend::serve(path.clone()));
+        while !path.exists() {
+            tokio::task::yield_now().await;
+        }
+
+        let (first, second) = (UnixSocketBackend::new(&path), UnixSocketBackend::new(&path));
+        let key = BucketKey::Bucket("abc".into(), None);
+        let headers = RatelimitHeaders {
+            limit: Some(2),
+            remaining: Some(1),
+            reset: Some(SystemTime::now() + Duration::from_secs(60)),
+            reset_after: Some(Duration::from_secs(60)),
+        };
+
+        first.post_hook(&key, &headers).await.unwrap();
+        assert!(second.pre_hook(&key).await.unwrap().is_none());
+        let delay = first.pre_hook(&key).await.unwrap().unwrap();
+        assert_eq!(delay.limit, 2);
+
+        assert_eq!(second.global_delay().await.unwrap(), None);
+        first.lock_global(Duration::from_secs(1)).await.unwrap();
+        assert!(second.global_delay().await.unwrap().is_some());
+
+        let channel_id = ChannelId::new(1);
+        let route = Route::Channel {
+            channel_id,
+        }
+        .ratelimiting_bucket();
+        let route_key = BucketKey::Route(route, LightMethod::Get);
+        assert!(first.pre_hook(&route_key).await.unwrap().is_none());
+        assert!(first.buckets().await.contains_key(&route_key));
+        assert_eq!(second.buckets().await.len(), 1);
+
+        second.learn_bucket(route, LightMethod::Get, "def").await.unwrap();
+        let buckets = first.buckets().await;
+        assert_eq!(buckets.len(), 2);
+        assert!(buckets.contains_key(&BucketKey::Bucket("def".into(), Some(channel_id.into()))));
+
+        server.abort();
+        drop(std::fs::remove_file(&path));
+    }
+}
@@ -35,23 +35,34 @@
 //!
 //! [Taken from]: https://discord.com/developers/docs/topics/rate-limits#rate-limits
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
+use std::num::{NonZeroU32, NonZeroU64};
 use std::str::{self, FromStr};
-use std::sync::Arc;
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Instant, SystemTime};
 
 use reqwest::header::HeaderMap;
 use reqwest::{Client, Response, StatusCode};
 use secrecy::{ExposeSecret, SecretString};
 use tokio::sync::{Mutex, RwLock};
 use tokio::time::{sleep, Duration};
-use tracing::{debug, instrument};
+use tracing::{debug, instrument, warn};
 
 pub use super::routing::RatelimitingBucket;
-use super::{HttpError, LightMethod, Request};
+use super::{HttpError, InMemoryBackend, LightMethod, RatelimitBackend, Request, Route};
 use crate::internal::prelude::*;
 
+/// The number of requests per second that Discord allows by default, across all routes.
+const GLOBAL_LIMIT: u32 = 50;
+/// The period over which Discord counts invalid requests.
+const INVALID_REQUEST_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// The granularity with which invalid requests are counted.
+const INVALID_REQUEST_BUCKET: Duration = Duration::from_secs(1);
+/// How long a bucket must have been unused after its reset before it is evicted.
+const BUCKET_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
 /// Passed to the [`Ratelimiter::set_ratelimit_callback`] callback. If using Client, that callback
 /// is initialized to call the `EventHandler::ratelimit()` method.
 #[derive(Clone, Debug)]
@@ -64,43 +75,170 @@ pub struct RatelimitInfo {
     pub global: bool,
 }
 
+/// A snapshot of the state of a [`Ratelimiter`], as returned by [`Ratelimiter::stats`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct RatelimiterStats {
+    /// The state of each bucket that is currently tracked.
+    pub buckets: HashMap<BucketKey, BucketStats>,
+    /// The number of requests currently waiting for a ratelimit to reset.
+    pub waiting_requests: usize,
+    /// The total time that requests have spent waiting for ratelimits to reset.
+    pub time_slept: Duration,
+    /// The number of invalid requests made in the last 10 minutes, see
+    /// [`Ratelimiter::invalid_requests`].
+    pub invalid_requests: u32,
+}
+
+/// The state of a single bucket of a [`Ratelimiter`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct BucketStats {
+    /// The total number of requests that can be made in a period of time.
+    pub limit: i64,
+    /// The number of requests remaining in the period of time.
+    pub remaining: i64,
+    /// The absolute time when the interval resets.
+    pub reset: Option<SystemTime>,
+}
+
+/// Identifies the [`Ratelimit`] that a request counts towards.
+///
+/// Requests are ratelimited by their route and method until Discord reports the bucket they are
+/// in through the `X-RateLimit-Bucket` header. From then on, all routes in the same bucket share
+/// one ratelimit per major parameter.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum BucketKey {
+    /// A route and method whose Discord bucket is not known yet.
+    Route(RatelimitingBucket, LightMethod),
+    /// A Discord bucket, identified by its hash, and the major parameter of the route.
+    Bucket(String, Option<NonZeroU64>),
+}
+
+/// Formats the key as a string identifying the bucket, for backends storing ratelimits outside of
+/// the process. The strings of [`Self::Route`] keys are only the same between processes running
+/// the same version of the library.
+impl fmt::Display for BucketKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Route(bucket, method) => write!(f, "{method:?}:{bucket:?}"),
+            Self::Bucket(hash, Some(major_parameter)) => write!(f, "{hash}:{major_parameter}"),
+            Self::Bucket(hash, None) => write!(f, "{hash}"),
+        }
+    }
+}
+
+/// The state of a bucket as reported by Discord in the headers of a response, passed to
+/// [`RatelimitBackend::post_hook`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct RatelimitHeaders {
+    /// The total number of requests that can be made in a period of time.
+    pub limit: Option<i64>,
+    /// The number of requests remaining in the period of time.
+    pub remaining: Option<i64>,
+    /// The absolute time when the interval resets, taken from the `X-RateLimit-Reset` header when
+    /// absolute ratelimits are used, and from the `X-RateLimit-Reset-After` header otherwise.
+    pub reset: Option<SystemTime>,
+    /// The total time when the interval resets.
+    pub reset_after: Option<Duration>,
+}
+
+impl RatelimitHeaders {
+    fn parse(headers: &HeaderMap, absolute_ratelimits: bool) -> Result<Self> {
+        let reset_after =
+            parse_header::<f64>(headers, "x-ratelimit-reset-after")?.map(Duration::from_secs_f64);
+        let reset = if absolute_ratelimits {
+            parse_header::<f64>(headers, "x-ratelimit-reset")?
+                .map(|reset| std::time::UNIX_EPOCH + Duration::from_secs_f64(reset))
+        } else {
+            reset_after.map(|reset_after| SystemTime::now() + reset_after)
+        };
+
+        Ok(Self {
+            limit: parse_header(headers, "x-ratelimit-limit")?,
+            remaining: parse_header(headers, "x-ratelimit-remaining")?,
+            reset,
+            reset_after,
+        })
+    }
+}
+
+/// Returned by [`Ratelimit::pre_hook`] when a bucket has no requests remaining until it resets.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct BucketDelay {
+    /// How long to wait until the bucket resets.
+    pub delay: Duration,
+    /// The total number of requests that can be made in a period of time.
+    pub limit: i64,
+}
+
 /// Ratelimiter for requests to the Discord API.
 ///
-/// This keeps track of ratelimit data for known routes through the [`Ratelimit`] implementation
-/// for each route: how many tickets are [`remaining`] until the user needs to wait for the known
-/// [`reset`] time, and the [`limit`] of requests that can be made within that time.
+/// This keeps track of ratelimit data for known buckets through the [`Ratelimit`] implementation
+/// for each bucket: how many tickets are [`remaining`] until the user needs to wait for the known
+/// [`reset`] time, and the [`limit`] of requests that can be made within that time. See
+/// [`BucketKey`] for how requests are grouped into buckets.
 ///
 /// When no tickets are available for some time, then the thread sleeps until that time passes. The
 /// mechanism is known as "pre-emptive ratelimiting".
 ///
-/// Occasionally for very high traffic bots, a global ratelimit may be reached which blocks all
-/// future requests until the global ratelimit is over, regardless of route. The value of this
-/// global ratelimit is never given through the API, so it can't be pre-emptively ratelimited. This
-/// only affects the largest of bots.
+/// Requests are also kept below the global ratelimit of 50 requests per second, which Discord
+/// raises for some large bots; see [`Self::set_global_limit`]. Should the global ratelimit still be
+/// reached, all requests are blocked until it is over, regardless of route.
+///
+/// Responses with a status of 401, 403, or 429 count as invalid requests, of which Discord allows
+/// 10,000 per 10 minutes before temporarily banning the IP address. Their number is available
+/// through [`Self::invalid_requests`], and requests can be stopped before the ban threshold with
+/// [`Self::set_invalid_request_limit`].
+///
+/// The ratelimits are kept by a [`RatelimitBackend`], which is an [`InMemoryBackend`] by default.
+/// Several processes using the same token can share their ratelimits through a backend storing
+/// them in one place, such as the [`UnixSocketBackend`]; see [`Self::set_backend`].
 ///
 /// [`limit`]: Ratelimit::limit
 /// [`remaining`]: Ratelimit::remaining
 /// [`reset`]: Ratelimit::reset
+/// [`UnixSocketBackend`]: super::UnixSocketBackend
 pub struct Ratelimiter {
     client: Client,
-    global: Arc<Mutex<()>>,
-    // When futures is implemented, make tasks clear out their respective entry when the 'reset'
-    // passes.
-    routes: Arc<RwLock<HashMap<RatelimitingBucket, Arc<Mutex<Ratelimit>>>>>,
+    backend: Arc<dyn RatelimitBackend>,
+    /// The backend used by default, until it is replaced with [`Self::set_backend`].
+    default_backend: Option<Arc<InMemoryBackend>>,
+    /// The Discord bucket hash of each route and method, learned from the responses to them.
+    bucket_hashes: Arc<RwLock<HashMap<(RatelimitingBucket, LightMethod), String>>>,
     token: SecretString,
+    /// The URL that requests are sent to in place of `https://discord.com`, if any, which is only
+    /// set by [`HttpBuilder::build`] so that it can't differ from the [`Http`] client's.
+    ///
+    /// [`HttpBuilder::build`]: super::HttpBuilder::build
+    /// [`Http`]: super::Http
+    api_url: Option<String>,
     absolute_ratelimits: bool,
     ratelimit_callback: Box<dyn Fn(RatelimitInfo) + Send + Sync>,
+    /// The bucket keeping requests below the global ratelimit, if it is enforced.
+    global_bucket: Option<StdMutex<GlobalBucket>>,
+    invalid_requests: StdMutex<InvalidRequests>,
+    /// The number of invalid requests within 10 minutes at which requests are refused.
+    invalid_request_limit: Option<u32>,
+    waiting: WaitMetrics,
 }
 
 impl fmt::Debug for Ratelimiter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Ratelimiter")
             .field("client", &self.client)
-            .field("global", &self.global)
-            .field("routes", &self.routes)
+            .field("backend", &"dyn RatelimitBackend")
+            .field("default_backend", &self.default_backend)
+            .field("bucket_hashes", &self.bucket_hashes)
             .field("token", &self.token)
+            .field("api_url", &self.api_url)
             .field("absolute_ratelimits", &self.absolute_ratelimits)
             .field("ratelimit_callback", &"Fn(RatelimitInfo)")
+            .field("global_bucket", &self.global_bucket)
+            .field("invalid_requests", &self.invalid_requests)
+            .field("invalid_request_limit", &self.invalid_request_limit)
+            .field("waiting", &self.waiting)
             .finish()
     }
 }
@@ -115,16 +253,35 @@ impl Ratelimiter {
     }
 
     fn new_(client: Client, token: String) -> Self {
+        let default_backend = Arc::new(InMemoryBackend::new());
+
         Self {
             client,
-            global: Arc::default(),
-            routes: Arc::default(),
+            backend: Arc::<InMemoryBackend>::clone(&default_backend),
+            default_backend: Some(default_backend),
+            bucket_hashes: Arc::default(),
             token: SecretString::new(token),
+            api_url: None,
             ratelimit_callback: Box::new(|_| {}),
             absolute_ratelimits: false,
+            global_bucket: Some(StdMutex::new(GlobalBucket::new(GLOBAL_LIMIT, Instant::now()))),
+            invalid_requests: StdMutex::default(),
+            invalid_request_limit: None,
+            waiting: WaitMetrics::default(),
         }
     }
 
+    /// Sets the backend keeping the ratelimits of buckets, replacing the [`InMemoryBackend`] used
+    /// by default.
+    ///
+    /// **Note**: The global limit set with [`Self::set_global_limit`] and the count of invalid
+    /// requests are kept by each ratelimiter. When sharing a backend between processes, each
+    /// should be given a share of the global limit.
+    pub fn set_backend(&mut self, backend: Arc<dyn RatelimitBackend>) {
+        self.backend = backend;
+        self.default_backend = None;
+    }
+
     /// Sets a callback to be called when a route is rate limited.
     pub fn set_ratelimit_callback(
         &mut self,
@@ -133,56 +290,120 @@ impl Ratelimiter {
         self.ratelimit_callback = ratelimit_callback;
     }
 
+    /// Sets the URL that requests are sent to in place of `https://discord.com`, which is the
+    /// [`HttpBuilder::proxy`] or [`HttpBuilder::base_url`] of the client being built.
+    ///
+    /// [`HttpBuilder::proxy`]: super::HttpBuilder::proxy
+    /// [`HttpBuilder::base_url`]: super::HttpBuilder::base_url
+    pub(super) fn set_api_url(&mut self, api_url: Option<String>) {
+        self.api_url = api_url;
+    }
+
     // Sets whether absolute ratelimits should be used.
     pub fn set_absolute_ratelimits(&mut self, absolute_ratelimits: bool) {
         self.absolute_ratelimits = absolute_ratelimits;
     }
 
-    /// The routes mutex is a HashMap of each [`RatelimitingBucket`] and their respective ratelimit
-    /// information.
-    ///
-    /// See the documentation for [`Ratelimit`] for more information on how the library handles
-    /// ratelimiting.
-    ///
-    /// # Examples
-    ///
-    /// View the `reset` time of the route for `ChannelsId(7)`:
-    ///
-    /// ```rust,no_run
-    /// use serenity::http::Route;
-    /// # use serenity::http::Http;
-    /// # use serenity::model::prelude::*;
+    /// The routes mutex is a HashMap of each [`BucketKey`] and their respective ratelimit
+    /// information, as kept by the [`InMemoryBackend`] used by default. The map is always empty if
+    /// another backend was set with [`Self::set_backend`].
+    #[deprecated = "Use InMemoryBackend::routes of a backend set with Ratelimiter::set_backend"]
+    #[must_use]
+    pub fn routes(&self) -> Arc<RwLock<HashMap<BucketKey, Arc<Mutex<Ratelimit>>>>> {
+        self.default_backend.as_ref().map(|backend| backend.routes()).unwrap_or_default()
+    }
+
+    /// Sets the number of requests per second that are made at most across all routes, or
+    /// [`None`] to only wait for the global ratelimit once Discord reports that it was reached.
     ///
-    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
-    /// # let http: Http = unimplemented!();
-    /// let routes = http.ratelimiter.unwrap().routes();
-    /// let reader = routes.read().await;
+    /// Defaults to 50, Discord's global ratelimit for most bots. Large bots may have been granted a
+    /// higher limit by Discord.
+    pub fn set_global_limit(&mut self, requests_per_second: Option<NonZeroU32>) {
+        self.global_bucket = requests_per_second
+            .map(|limit| StdMutex::new(GlobalBucket::new(limit.get(), Instant::now())));
+    }
+
+    /// Sets the number of invalid requests within 10 minutes at which further requests fail with
+    /// [`HttpError::InvalidRequestLimit`] instead of being sent, or [`None`] to never refuse
+    /// requests. Disabled by default.
     ///
-    /// let channel_id = ChannelId::new(7);
-    /// let route = Route::Channel {
-    ///     channel_id,
-    /// };
-    /// if let Some(route) = reader.get(&route.ratelimiting_bucket()) {
-    ///     if let Some(reset) = route.lock().await.reset() {
-    ///         println!("Reset time at: {:?}", reset);
-    ///     }
-    /// }
-    /// # Ok(())
-    /// # }
-    /// ```
+    /// Discord temporarily bans the IP address after 10,000 invalid requests in 10 minutes, so the
+    /// limit should leave some headroom below that. See [`Self::invalid_requests`].
+    pub fn set_invalid_request_limit(&mut self, invalid_request_limit: Option<u32>) {
+        self.invalid_request_limit = invalid_request_limit;
+    }
+
+    /// Returns the number of invalid requests made in the last 10 minutes, being those responded
+    /// to with a status of 401, 403, or 429. 429s of ratelimits shared with other applications do
+    /// not count, as Discord does not count them either.
     #[must_use]
-    pub fn routes(&self) -> Arc<RwLock<HashMap<RatelimitingBucket, Arc<Mutex<Ratelimit>>>>> {
-        Arc::clone(&self.routes)
+    pub fn invalid_requests(&self) -> u32 {
+        self.invalid_requests.lock().expect("invalid requests poisoned").count(Instant::now())
     }
 
+    /// Returns a snapshot of the state of every tracked bucket and of the requests waiting for
+    /// them, for example to monitor how close the bot is to being ratelimited.
+    ///
+    /// Buckets are only listed if the backend supports it, as described by
+    /// [`RatelimitBackend::buckets`].
+    pub async fn stats(&self) -> RatelimiterStats {
+        RatelimiterStats {
+            buckets: self.backend.buckets().await,
+            waiting_requests: self.waiting.requests.load(Ordering::Relaxed),
+            time_slept: Duration::from_nanos(self.waiting.nanos.load(Ordering::Relaxed)),
+            invalid_requests: self.invalid_requests(),
+        }
+    }
+
+    /// Returns the key of the ratelimit that requests to a route with the given method count
+    /// towards, which depends on whether the Discord bucket of the route is known yet.
+    pub async fn bucket_key(&self, bucket: RatelimitingBucket, method: LightMethod) -> BucketKey {
+        let route = (bucket.without_major_parameter(), method);
+        match self.bucket_hashes.read().await.get(&route) {
+            Some(hash) => BucketKey::Bucket(hash.clone(), bucket.major_parameter()),
+            None => BucketKey::Route(bucket, method),
+        }
+    }
+
+    /// Records the Discord bucket that a route was reported to be in, letting the backend move
+    /// the ratelimits tracked for the route so far to the bucket.
+    async fn learn_bucket(
+        &self,
+        bucket: RatelimitingBucket,
+        method: LightMethod,
+        hash: &str,
+    ) -> Result<()> {
+        let route = (bucket.without_major_parameter(), method);
+        if self.bucket_hashes.read().await.get(&route).is_some_and(|known| known == hash) {
+            return Ok(());
+        }
+
+        debug!("Route {:?} with method {:?} is in bucket {}", bucket, method, hash);
+        let mut bucket_hashes = self.bucket_hashes.write().await;
+        bucket_hashes.insert(route, hash.to_string());
+
+        self.backend.learn_bucket(bucket, method, hash).await
+    }
+
+    /// Performs a request once its ratelimits allow it, sending it to the proxy or base URL of the
+    /// [`Http`] client the ratelimiter belongs to, if any.
+    ///
+    /// [`Http`]: super::Http
+    ///
     /// # Errors
     ///
-    /// Only error kind that may be returned is [`Error::Http`].
+    /// Returns [`Error::Http`] if the request failed, or any error of the backend keeping the
+    /// ratelimits while checking them before the request is sent. Errors of the backend once the
+    /// response was received are logged instead.
     #[instrument]
     pub async fn perform(&self, req: Request<'_>) -> Result<Response> {
         loop {
-            // This will block if another thread hit the global ratelimit.
-            drop(self.global.lock().await);
+            if let Some(limit) = self.invalid_request_limit {
+                if self.invalid_requests() >= limit {
+                    warn!("Refusing request to {:?}: invalid request limit reached", req.route);
+                    return Err(Error::Http(HttpError::InvalidRequestLimit));
+                }
+            }
 
             // Perform pre-checking here:
             // - get the route's relevant rate
@@ -191,14 +412,45 @@ impl Ratelimiter {
             // - sleep if there is 0 remaining
             // - then, perform the request
             let ratelimiting_bucket = req.route.ratelimiting_bucket();
-            let bucket =
-                Arc::clone(self.routes.write().await.entry(ratelimiting_bucket).or_default());
+            // This will block if another thread hit the global ratelimit.
+            while let Some(delay) = self.backend.global_delay().await? {
+                self.wait(delay).await;
+            }
+
+            let key = self.bucket_key(ratelimiting_bucket, req.method).await;
+            while let Some(BucketDelay {
+                delay,
+                limit,
+            }) = self.backend.pre_hook(&key).await?
+            {
+                debug!(
+                    "Pre-emptive ratelimit on route {:?} for {}ms",
+                    ratelimiting_bucket,
+                    delay.as_millis(),
+                );
+                (self.ratelimit_callback)(RatelimitInfo {
+                    timeout: delay,
+                    limit,
+                    method: req.method,
+                    path: req.route.path().to_string(),
+                    global: false,
+                });
+
+                self.wait(delay).await;
+            }
 
-            bucket.lock().await.pre_hook(&req, &self.ratelimit_callback).await;
+            self.acquire_global(&req.route).await;
 
-            let request = req.clone().build(&self.client, self.token.expose_secret(), None)?;
+            let api_url = self.api_url.as_deref();
+            let request = req.clone().build(&self.client, self.token.expose_secret(), api_url)?;
             let response = self.client.execute(request.build()?).await?;
 
+            if is_invalid(&response) {
+                let mut invalid_requests =
+                    self.invalid_requests.lock().expect("invalid requests poisoned");
+                invalid_requests.record(Instant::now());
+            }
+
             // Check if the request got ratelimited by checking for status 429, and if so, sleep
             // for the value of the header 'retry-after' - which is in milliseconds - and then
             // `continue` to try again
@@ -215,25 +467,37 @@ impl Ratelimiter {
                 return Ok(response);
             }
 
-            let redo = if response.headers().get("x-ratelimit-global").is_some() {
-                drop(self.global.lock().await);
+            // Routes in the same Discord bucket share its ratelimit from now on.
+            if let Some(hash) = response.headers().get("x-ratelimit-bucket") {
+                if let Ok(hash) = hash.to_str() {
+                    let learned = self.learn_bucket(ratelimiting_bucket, req.method, hash).await;
+                    if let Err(why) = learned {
+                        warn!("Err recording bucket {} of route {:?}: {:?}", hash, req.route, why);
+                    }
+                }
+            }
 
+            let redo = if response.headers().get("x-ratelimit-global").is_some() {
                 Ok(
                     if let Some(retry_after) =
                         parse_header::<f64>(response.headers(), "retry-after")?
                     {
+                        let timeout = Duration::from_secs_f64(retry_after);
                         debug!(
                             "Ratelimited on route {:?} for {:?}s",
                             ratelimiting_bucket, retry_after
                         );
+                        if let Err(why) = self.backend.lock_global(timeout).await {
+                            warn!("Err locking the global ratelimit: {:?}", why);
+                        }
                         (self.ratelimit_callback)(RatelimitInfo {
-                            timeout: Duration::from_secs_f64(retry_after),
+                            timeout,
                             limit: 50,
                             method: req.method,
                             path: req.route.path().to_string(),
                             global: true,
                         });
-                        sleep(Duration::from_secs_f64(retry_after)).await;
+                        self.wait(timeout).await;
 
                         true
                     } else {
@@ -241,11 +505,8 @@ impl Ratelimiter {
                     },
                 )
             } else {
-                bucket
-                    .lock()
-                    .await
-                    .post_hook(&response, &req, &self.ratelimit_callback, self.absolute_ratelimits)
-                    .await
+                let key = self.bucket_key(ratelimiting_bucket, req.method).await;
+                self.post_hook(&key, &response, &req).await
             };
 
             if !redo.unwrap_or(true) {
@@ -253,6 +514,175 @@ impl Ratelimiter {
             }
         }
     }
+
+    /// Updates the ratelimit of a bucket from a response, returning whether the request was
+    /// ratelimited and should be retried after having waited for the ratelimit to reset.
+    async fn post_hook(
+        &self,
+        key: &BucketKey,
+        response: &Response,
+        req: &Request<'_>,
+    ) -> Result<bool> {
+        let headers = RatelimitHeaders::parse(response.headers(), self.absolute_ratelimits)?;
+        if let Err(why) = self.backend.post_hook(key, &headers).await {
+            warn!("Err updating the ratelimit of bucket {}: {:?}", key, why);
+        }
+
+        Ok(if response.status() != StatusCode::TOO_MANY_REQUESTS {
+            false
+        } else if let Some(retry_after) = parse_header::<f64>(response.headers(), "retry-after")? {
+            debug!(
+                "Ratelimited on route {:?} for {:?}s",
+                req.route.ratelimiting_bucket(),
+                retry_after
+            );
+            (self.ratelimit_callback)(RatelimitInfo {
+                timeout: Duration::from_secs_f64(retry_after),
+                limit: headers.limit.unwrap_or_default(),
+                method: req.method,
+                path: req.route.path().to_string(),
+                global: false,
+            });
+
+            self.wait(Duration::from_secs_f64(retry_after)).await;
+
+            true
+        } else {
+            false
+        })
+    }
+
+    /// Sleeps for a ratelimit, accounting the request as waiting meanwhile.
+    async fn wait(&self, duration: Duration) {
+        self.waiting.requests.fetch_add(1, Ordering::Relaxed);
+        let _guard = WaitGuard {
+            metrics: &self.waiting,
+            started: Instant::now(),
+        };
+
+        sleep(duration).await;
+    }
+
+    /// Waits until a request to the route can be made without exceeding the global limit, if it
+    /// is enforced and applies to the route.
+    async fn acquire_global(&self, route: &Route<'_>) {
+        let Some(global_bucket) = &self.global_bucket else { return };
+        if !route.is_globally_ratelimited() {
+            return;
+        }
+
+        loop {
+            let wait =
+                global_bucket.lock().expect("global bucket poisoned").acquire(Instant::now());
+            match wait {
+                Some(wait) => self.wait(wait).await,
+                None => return,
+            }
+        }
+    }
+}
+
+/// Counts the requests waiting for a ratelimit, and the time they spent doing so.
+#[derive(Debug, Default)]
+struct WaitMetrics {
+    requests: AtomicUsize,
+    nanos: AtomicU64,
+}
+
+/// Accounts a request as no longer waiting when dropped, including when it is cancelled.
+struct WaitGuard<'a> {
+    metrics: &'a WaitMetrics,
+    started: Instant,
+}
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        let nanos = u64::try_from(self.started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.metrics.nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.metrics.requests.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A token bucket keeping requests below the global ratelimit, refilling continuously so that
+/// requests are spread evenly instead of being sent in bursts at the start of each second.
+#[derive(Debug)]
+struct GlobalBucket {
+    /// The number of requests allowed per second, which is also the capacity of the bucket.
+    limit: u32,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl GlobalBucket {
+    fn new(limit: u32, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: f64::from(limit),
+            refilled_at: now,
+        }
+    }
+
+    /// Takes a token if one is available, or returns how long to wait until one is.
+    fn acquire(&mut self, now: Instant) -> Option<Duration> {
+        let limit = f64::from(self.limit);
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = elapsed.mul_add(limit, self.tokens).min(limit);
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / limit))
+        }
+    }
+}
+
+/// Counts invalid requests over the last 10 minutes.
+#[derive(Debug, Default)]
+struct InvalidRequests {
+    /// The number of invalid requests per bucket, oldest first.
+    counts: VecDeque<(Instant, u32)>,
+}
+
+impl InvalidRequests {
+    fn record(&mut self, now: Instant) {
+        match self.counts.back_mut() {
+            Some((start, count))
+                if now.saturating_duration_since(*start) < INVALID_REQUEST_BUCKET =>
+            {
+                *count += 1;
+            },
+            _ => self.counts.push_back((now, 1)),
+        }
+
+        self.expire(now);
+    }
+
+    fn count(&mut self, now: Instant) -> u32 {
+        self.expire(now);
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self.counts.front().is_some_and(|&(start, _)| {
+            now.saturating_duration_since(start) >= INVALID_REQUEST_WINDOW
+        }) {
+            self.counts.pop_front();
+        }
+    }
+}
+
+/// Returns whether a response counts towards Discord's limit of invalid requests.
+fn is_invalid(response: &Response) -> bool {
+    match response.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => true,
+        StatusCode::TOO_MANY_REQUESTS => response
+            .headers()
+            .get("x-ratelimit-scope")
+            .map_or(true, |scope| scope.as_bytes() != b"shared"),
+        _ => false,
+    }
 }
 
 /// A set of data containing information about the ratelimits for a particular
@@ -274,109 +704,72 @@ pub struct Ratelimit {
     reset: Option<SystemTime>,
     /// The total time when the interval resets.
     reset_after: Option<Duration>,
+    /// When a request last counted towards this ratelimit.
+    last_used: Instant,
 }
 
 impl Ratelimit {
-    #[instrument(skip(ratelimit_callback))]
-    pub async fn pre_hook(
-        &mut self,
-        req: &Request<'_>,
-        ratelimit_callback: &(dyn Fn(RatelimitInfo) + Send + Sync),
-    ) {
+    /// Counts a request towards this ratelimit if another one can be made before the interval
+    /// resets, or returns how long to wait until it resets otherwise.
+    pub fn pre_hook(&mut self, now: SystemTime) -> Option<BucketDelay> {
+        self.last_used = Instant::now();
+
         if self.limit() == 0 {
-            return;
+            return None;
         }
 
         let Some(reset) = self.reset else {
             // We're probably in the past.
             self.remaining = self.limit;
-            return;
+            return None;
         };
 
-        let Ok(delay) = reset.duration_since(SystemTime::now()) else {
+        let Ok(delay) = reset.duration_since(now) else {
             // if duration is negative (i.e. adequate time has passed since last call to this api)
             if self.remaining() != 0 {
                 self.remaining -= 1;
             }
-            return;
+            return None;
         };
 
         if self.remaining() == 0 {
-            debug!(
-                "Pre-emptive ratelimit on route {:?} for {}ms",
-                req.route.ratelimiting_bucket(),
-                delay.as_millis(),
-            );
-            ratelimit_callback(RatelimitInfo {
-                timeout: delay,
+            return Some(BucketDelay {
+                delay,
                 limit: self.limit,
-                method: req.method,
-                path: req.route.path().to_string(),
-                global: false,
             });
-
-            sleep(delay).await;
-
-            return;
         }
 
         self.remaining -= 1;
+        None
     }
 
-    #[instrument(skip(ratelimit_callback))]
-    pub async fn post_hook(
-        &mut self,
-        response: &Response,
-        req: &Request<'_>,
-        ratelimit_callback: &(dyn Fn(RatelimitInfo) + Send + Sync),
-        absolute_ratelimits: bool,
-    ) -> Result<bool> {
-        if let Some(limit) = parse_header(response.headers(), "x-ratelimit-limit")? {
+    /// Updates this ratelimit with the state that Discord reported in the headers of a response.
+    pub fn post_hook(&mut self, headers: &RatelimitHeaders) {
+        if let Some(limit) = headers.limit {
             self.limit = limit;
         }
 
-        if let Some(remaining) = parse_header(response.headers(), "x-ratelimit-remaining")? {
+        if let Some(remaining) = headers.remaining {
             self.remaining = remaining;
         }
 
-        if absolute_ratelimits {
-            if let Some(reset) = parse_header::<f64>(response.headers(), "x-ratelimit-reset")? {
-                self.reset = Some(std::time::UNIX_EPOCH + Duration::from_secs_f64(reset));
-            }
+        if let Some(reset) = headers.reset {
+            self.reset = Some(reset);
         }
 
-        if let Some(reset_after) =
-            parse_header::<f64>(response.headers(), "x-ratelimit-reset-after")?
-        {
-            if !absolute_ratelimits {
-                self.reset = Some(SystemTime::now() + Duration::from_secs_f64(reset_after));
-            }
-
-            self.reset_after = Some(Duration::from_secs_f64(reset_after));
+        if let Some(reset_after) = headers.reset_after {
+            self.reset_after = Some(reset_after);
         }
+    }
 
-        Ok(if response.status() != StatusCode::TOO_MANY_REQUESTS {
-            false
-        } else if let Some(retry_after) = parse_header::<f64>(response.headers(), "retry-after")? {
-            debug!(
-                "Ratelimited on route {:?} for {:?}s",
-                req.route.ratelimiting_bucket(),
-                retry_after
-            );
-            ratelimit_callback(RatelimitInfo {
-                timeout: Duration::from_secs_f64(retry_after),
-                limit: self.limit,
-                method: req.method,
-                path: req.route.path().to_string(),
-                global: false,
-            });
-
-            sleep(Duration::from_secs_f64(retry_after)).await;
-
-            true
-        } else {
-            false
-        })
+    /// Returns the state of this ratelimit, as listed by [`Ratelimiter::stats`].
+    #[must_use]
+    pub fn stats(&self) -> BucketStats {
+        BucketStats {
+            limit: self.limit,
+            remaining: self.remaining,
+            reset: self.reset,
+        }
     }
 
     /// The total number of requests that can be made in a period of time.
@@ -406,6 +799,13 @@ impl Ratelimit {
     pub const fn reset_after(&self) -> Option<Duration> {
         self.reset_after
     }
+
+    /// Returns whether the interval has reset and no request was made for a while, so that
+    /// forgetting about this ratelimit makes no difference.
+    pub(super) fn is_stale(&self, now: Instant, system_now: SystemTime) -> bool {
+        self.reset.map_or(true, |reset| reset <= system_now)
+            && now.saturating_duration_since(self.last_used) >= BUCKET_IDLE_TIMEOUT
+    }
 }
 
 impl Default for Ratelimit {
@@ -415,6 +815,7 @@ impl Default for Ratelimit {
             remaining: i64::MAX,
             reset: None,
             reset_after: None,
+            last_used: Instant::now(),
         }
     }
 }
@@ -433,13 +834,33 @@ fn parse_header<T: FromStr>(headers: &HeaderMap, header: &str) -> Result<Option<
 #[cfg(test)]
 mod tests {
     use std::error::Error as StdError;
+    use std::num::NonZeroU32;
     use std::result::Result as StdResult;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant, SystemTime};
 
     use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+    use tokio::time::timeout;
 
-    use super::parse_header;
+    use super::{
+        parse_header,
+        BucketKey,
+        GlobalBucket,
+        InvalidRequests,
+        Ratelimit,
+        RatelimitHeaders,
+        Ratelimiter,
+    };
     use crate::error::Error;
-    use crate::http::HttpError;
+    use crate::http::{
+        HttpBuilder,
+        HttpError,
+        InMemoryBackend,
+        LightMethod,
+        RatelimitBackend,
+        Route,
+    };
+    use crate::model::id::{ChannelId, InteractionId, MessageId};
 
     type Result<T> = StdResult<T, Box<dyn StdError>>;
 
@@ -492,4 +913,155 @@ mod tests {
             Error::Http(HttpError::RateLimitUtf8)
         ));
     }
+
+    #[tokio::test]
+    async fn routes_share_learned_buckets() {
+        let backend = Arc::new(InMemoryBackend::new());
+        let mut ratelimiter = Ratelimiter::new(reqwest::Client::new(), "Bot token");
+        ratelimiter.set_backend(Arc::<InMemoryBackend>::clone(&backend));
+        let (channel_a, channel_b) = (ChannelId::new(1), ChannelId::new(2));
+        let message_id = MessageId::new(3);
+        let reaction = |channel_id| Route::ChannelMessageReactionMe {
+            channel_id,
+            message_id,
+            reaction: "%F0%9F%91%8D",
+        };
+        let reactions = |channel_id| Route::ChannelMessageReactions {
+            channel_id,
+            message_id,
+        };
+
+        let key = ratelimiter
+            .bucket_key(reaction(channel_a).ratelimiting_bucket(), LightMethod::Put)
+            .await;
+        assert!(matches!(key, BucketKey::Route(..)));
+        assert!(backend.pre_hook(&key).await.unwrap().is_none());
+        let ratelimit = Arc::clone(&backend.routes().read().await[&key]);
+
+        for route in [reaction(channel_a), reactions(channel_a)] {
+            let bucket = route.ratelimiting_bucket();
+            ratelimiter.learn_bucket(bucket, LightMethod::Put, "abc").await.unwrap();
+        }
+
+        let key_a = ratelimiter
+            .bucket_key(reactions(channel_a).ratelimiting_bucket(), LightMethod::Put)
+            .await;
+        let key_b = ratelimiter
+            .bucket_key(reaction(channel_b).ratelimiting_bucket(), LightMethod::Put)
+            .await;
+        assert_eq!(key_a, BucketKey::Bucket("abc".into(), Some(channel_a.into())));
+        assert_eq!(key_b, BucketKey::Bucket("abc".into(), Some(channel_b.into())));
+
+        let routes = backend.routes();
+        let routes = routes.read().await;
+        assert_eq!(routes.len(), 1);
+        assert!(Arc::ptr_eq(&routes[&key_a], &ratelimit));
+    }
+
+    #[test]
+    fn builder_sets_api_url() {
+        let http = HttpBuilder::new("token").base_url("http://mock").build();
+        assert_eq!(http.ratelimiter.unwrap().api_url.as_deref(), Some("http://mock"));
+
+        let http = HttpBuilder::new("token").proxy("http://proxy").base_url("http://mock").build();
+        assert_eq!(http.proxy(), Some("http://proxy"));
+        assert_eq!(http.ratelimiter.unwrap().api_url.as_deref(), Some("http://proxy"));
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn routes_of_default_backend() {
+        let mut ratelimiter = Ratelimiter::new(reqwest::Client::new(), "Bot token");
+        let route = Route::Channel {
+            channel_id: ChannelId::new(1),
+        };
+        let key = ratelimiter.bucket_key(route.ratelimiting_bucket(), LightMethod::Get).await;
+        assert!(ratelimiter.backend.pre_hook(&key).await.unwrap().is_none());
+        assert!(ratelimiter.routes().read().await.contains_key(&key));
+
+        ratelimiter.set_backend(Arc::new(InMemoryBackend::new()));
+        assert!(ratelimiter.routes().read().await.is_empty());
+    }
+
+    #[test]
+    fn global_bucket_spreads_requests() {
+        let start = Instant::now();
+        let mut bucket = GlobalBucket::new(50, start);
+
+        for _ in 0..50 {
+            assert_eq!(bucket.acquire(start), None);
+        }
+        let wait = bucket.acquire(start).unwrap();
+        assert_eq!(wait, Duration::from_millis(20));
+
+        assert_eq!(bucket.acquire(start + wait), None);
+        assert!(bucket.acquire(start + wait).is_some());
+    }
+
+    #[tokio::test]
+    async fn interactions_skip_global_bucket() {
+        let mut ratelimiter = Ratelimiter::new(reqwest::Client::new(), "Bot token");
+        ratelimiter.set_global_limit(NonZeroU32::new(1));
+        let channel = Route::Channel {
+            channel_id: ChannelId::new(1),
+        };
+        let interaction = Route::InteractionResponse {
+            interaction_id: InteractionId::new(2),
+            token: "token",
+        };
+        let within = |route| timeout(Duration::from_millis(100), ratelimiter.acquire_global(route));
+
+        assert!(within(&channel).await.is_ok());
+        assert!(within(&interaction).await.is_ok());
+        assert!(within(&channel).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn only_sleeps_count_as_waiting() {
+        let mut ratelimiter = Ratelimiter::new(reqwest::Client::new(), "Bot token");
+        ratelimiter.set_global_limit(NonZeroU32::new(20));
+        let route = Route::Channel {
+            channel_id: ChannelId::new(1),
+        };
+
+        for _ in 0..20 {
+            ratelimiter.acquire_global(&route).await;
+        }
+        assert_eq!(ratelimiter.stats().await.time_slept, Duration::ZERO);
+
+        ratelimiter.acquire_global(&route).await;
+        let stats = ratelimiter.stats().await;
+        assert!(stats.time_slept > Duration::ZERO);
+        assert_eq!(stats.waiting_requests, 0);
+    }
+
+    #[test]
+    fn invalid_requests_expire() {
+        let start = Instant::now();
+        let mut invalid_requests = InvalidRequests::default();
+
+        for i in 0..30 {
+            invalid_requests.record(start + Duration::from_secs(i * 30));
+        }
+
+        assert_eq!(invalid_requests.count(start + Duration::from_secs(870)), 20);
+        assert_eq!(invalid_requests.count(start + Duration::from_secs(2000)), 0);
+    }
+
+    #[test]
+    fn pre_hook_waits_for_reset() {
+        let now = SystemTime::now();
+        let mut ratelimit = Ratelimit::default();
+        ratelimit.post_hook(&RatelimitHeaders {
+            limit: Some(2),
+            remaining: Some(1),
+            reset: Some(now + Duration::from_secs(2)),
+            reset_after: Some(Duration::from_secs(2)),
+        });
+
+        assert!(ratelimit.pre_hook(now).is_none());
+        let delay = ratelimit.pre_hook(now).unwrap();
+        assert_eq!((delay.delay, delay.limit), (Duration::from_secs(2), 2));
+        assert!(ratelimit.pre_hook(now + Duration::from_secs(3)).is_none());
+    }
 }
@@ -13,6 +13,17 @@ impl RatelimitingBucket {
     pub fn is_none(&self) -> bool {
         self.0.is_none()
     }
+
+    /// Returns the bucket of the same route with any major parameter, which all share one
+    /// Discord bucket hash.
+    pub(crate) fn without_major_parameter(self) -> Self {
+        Self(self.0.map(|(route, _)| (route, None)))
+    }
+
+    /// Returns the major parameter of the route, if it has one.
+    pub(crate) fn major_parameter(self) -> Option<NonZeroU64> {
+        self.0.and_then(|(_, id)| id)
+    }
 }
 
 enum RatelimitingKind {
@@ -502,3 +513,18 @@ routes! ('a, {
     api!("/stage-instances/{}", channel_id),
     Some(RatelimitingKind::Path);
 });
+
+impl Route<'_> {
+    /// Whether requests to the route count towards the global ratelimit. Routes authenticated with
+    /// an interaction token are exempt from it.
+    #[must_use]
+    pub(crate) fn is_globally_ratelimited(&self) -> bool {
+        !matches!(
+            self,
+            Self::InteractionResponse { .. }
+                | Self::WebhookOriginalInteractionResponse { .. }
+                | Self::WebhookFollowupMessage { .. }
+                | Self::WebhookFollowupMessages { .. }
+        )
+    }
+}
@@ -1092,6 +1092,63 @@ pub struct MessagePollVoteRemoveEvent {
     pub answer_id: AnswerId,
 }
 
+/// Requires [`GatewayIntents::GUILD_EMOJIS_AND_STICKERS`].
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/gateway-events#guild-soundboard-sound-create).
+#[cfg_attr(feature = "typesize", derive(typesize::derive::TypeSize))]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(transparent)]
+#[non_exhaustive]
+pub struct GuildSoundboardSoundCreateEvent {
+    pub sound: SoundboardSound,
+}
+
+/// Requires [`GatewayIntents::GUILD_EMOJIS_AND_STICKERS`].
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/gateway-events#guild-soundboard-sound-update).
+#[cfg_attr(feature = "typesize", derive(typesize::derive::TypeSize))]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(transparent)]
+#[non_exhaustive]
+pub struct GuildSoundboardSoundUpdateEvent {
+    pub sound: SoundboardSound,
+}
+
+/// Requires [`GatewayIntents::GUILD_EMOJIS_AND_STICKERS`].
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/gateway-events#guild-soundboard-sound-delete).
+#[cfg_attr(feature = "typesize", derive(typesize::derive::TypeSize))]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct GuildSoundboardSoundDeleteEvent {
+    pub sound_id: SoundId,
+    pub guild_id: GuildId,
+}
+
+/// Requires [`GatewayIntents::GUILD_EMOJIS_AND_STICKERS`].
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/gateway-events#guild-soundboard-sounds-update).
+#[cfg_attr(feature = "typesize", derive(typesize::derive::TypeSize))]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct GuildSoundboardSoundsUpdateEvent {
+    pub soundboard_sounds: Vec<SoundboardSound>,
+    pub guild_id: GuildId,
+}
+
+/// Sent in response to [`ShardMessenger::request_soundboard_sounds`]. Requires no gateway intents.
+///
+/// [Discord docs](https://discord.com/developers/docs/topics/gateway-events#soundboard-sounds).
+///
+/// [`ShardMessenger::request_soundboard_sounds`]: crate::gateway::ShardMessenger::request_soundboard_sounds
+#[cfg_attr(feature = "typesize", derive(typesize::derive::TypeSize))]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct SoundboardSoundsEvent {
+    pub soundboard_sounds: Vec<SoundboardSound>,
+    pub guild_id: GuildId,
+}
+
 /// [Discord docs](https://discord.com/developers/docs/topics/gateway-events#payload-structure).
 #[cfg_attr(feature = "typesize", derive(typesize::derive::TypeSize))]
 #[allow(clippy::large_enum_variant)]
@@ -1351,6 +1408,16 @@ pub enum Event {
     MessagePollVoteAdd(MessagePollVoteAddEvent),
     /// A user has removed a previous vote on a Message Poll.
     MessagePollVoteRemove(MessagePollVoteRemoveEvent),
+    /// A soundboard sound was created in a guild.
+    GuildSoundboardSoundCreate(GuildSoundboardSoundCreateEvent),
+    /// A soundboard sound of a guild was updated.
+    GuildSoundboardSoundUpdate(GuildSoundboardSoundUpdateEvent),
+    /// A soundboard sound was deleted from a guild.
+    GuildSoundboardSoundDelete(GuildSoundboardSoundDeleteEvent),
+    /// Multiple soundboard sounds of a guild were updated.
+    GuildSoundboardSoundsUpdate(GuildSoundboardSoundsUpdateEvent),
+    /// The soundboard sounds of a guild, in response to a request for them.
+    SoundboardSounds(SoundboardSoundsEvent),
     /// An event type not covered by the above
     #[serde(untagged)]
     Unknown(UnknownEvent),
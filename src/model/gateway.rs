@@ -494,6 +494,10 @@ bitflags! {
         /// Enables the following gateway events:
         /// - GUILD_EMOJIS_UPDATE
         /// - GUILD_STICKERS_UPDATE
+        /// - GUILD_SOUNDBOARD_SOUND_CREATE
+        /// - GUILD_SOUNDBOARD_SOUND_UPDATE
+        /// - GUILD_SOUNDBOARD_SOUND_DELETE
+        /// - GUILD_SOUNDBOARD_SOUNDS_UPDATE
         const GUILD_EMOJIS_AND_STICKERS = 1 << 3;
         /// Enables the following gateway events:
         /// - GUILD_INTEGRATIONS_UPDATE
@@ -0,0 +1,58 @@
+use crate::model::prelude::*;
+
+/// A sound that can be played in voice channels through the soundboard.
+///
+/// [Discord docs](https://discord.com/developers/docs/resources/soundboard#soundboard-sound-object).
+#[cfg_attr(feature = "typesize", derive(typesize::derive::TypeSize))]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct SoundboardSound {
+    /// The name of the sound.
+    pub name: String,
+    /// The unique ID given to this sound.
+    pub sound_id: SoundId,
+    /// The volume of the sound, from 0 to 1.
+    pub volume: f64,
+    /// The ID of the sound's custom emoji.
+    pub emoji_id: Option<EmojiId>,
+    /// The unicode character of the sound's standard emoji.
+    pub emoji_name: Option<String>,
+    /// The ID of the guild this sound is in, or [`None`] for default sounds.
+    pub guild_id: Option<GuildId>,
+    /// Whether this sound can be used. May be `false` due to loss of server boosts.
+    pub available: bool,
+    /// The user who created this sound.
+    pub user: Option<User>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::{from_value, json};
+    use crate::model::prelude::*;
+
+    #[test]
+    fn soundboard_sounds_event() {
+        let value = json!({
+            "t": "SOUNDBOARD_SOUNDS",
+            "d": {
+                "guild_id": "613425648685547541",
+                "soundboard_sounds": [{
+                    "name": "quack",
+                    "sound_id": "1106714396018884649",
+                    "volume": 1.0,
+                    "emoji_id": null,
+                    "emoji_name": "\u{1f986}",
+                    "guild_id": "613425648685547541",
+                    "available": true
+                }]
+            }
+        });
+
+        let Event::SoundboardSounds(event) = from_value(value).unwrap() else {
+            panic!("expected a typed soundboard sounds event");
+        };
+        assert_eq!(event.guild_id, GuildId::new(613425648685547541));
+        assert_eq!(event.soundboard_sounds[0].sound_id, SoundId::new(1106714396018884649));
+        assert!(event.soundboard_sounds[0].user.is_none());
+    }
+}